@@ -0,0 +1,898 @@
+//! Async versions of the traits, for use with async executors.
+//!
+//! This module is only available with the `async` feature, which raises the
+//! minimum supported Rust version to 1.75.
+
+// The crate's MSRV doesn't apply to this module.
+#![allow(clippy::incompatible_msrv)]
+
+use crate::clock::millis;
+use crate::connect::MAX_ADDRS;
+use crate::{
+	AddrType, Clock, ConnectError, Direction, DnsError, HostAddr, Mode, NetworkErrorKind,
+	RecvOutcome, TcpError, TcpStack, UdpError, UdpStack,
+};
+use core::future::poll_fn;
+use core::task::{Context, Poll, Waker};
+use core::time::Duration;
+use heapless::{consts, String};
+use no_std_net::{IpAddr, SocketAddr};
+
+/// The async version of [`Dns`]. Resolution is awaited rather than polled,
+/// so name lookups don't block the executor while the query is in flight.
+///
+/// [`Dns`]: ../trait.Dns.html
+#[allow(async_fn_in_trait)]
+pub trait DnsAsync {
+	/// The type returned when we have an error
+	type Error: DnsError;
+
+	/// Resolve the first ip address of a host, given its hostname and a desired
+	/// address record type to look for
+	async fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> Result<IpAddr, Self::Error>;
+
+	/// Resolve all ip addresses of a host, given its hostname and a desired
+	/// address record type to look for. Returns `Ok(n)`, which means `n`
+	/// addresses have been placed in `&out[0..n]`.
+	///
+	/// The default implementation only returns the single address found by
+	/// [`get_host_by_name`](#tymethod.get_host_by_name).
+	async fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> Result<usize, Self::Error> {
+		match out.first_mut() {
+			Some(first) => {
+				*first = self.get_host_by_name(hostname, addr_type).await?;
+				Ok(1)
+			}
+			None => Ok(0),
+		}
+	}
+
+	/// Resolve the hostname of a host, given its ip address
+	///
+	/// **Note**: A fully qualified domain name (FQDN), has a maximum length of
+	/// 255 bytes [`rfc1035`]
+	///
+	/// [`rfc1035`]: https://tools.ietf.org/html/rfc1035
+	async fn get_host_by_address(&self, addr: IpAddr) -> Result<String<consts::U256>, Self::Error>;
+}
+
+/// The async version of [`TcpStack`], for stacks whose driver is itself
+/// async, so that connections can be awaited instead of polled.
+///
+/// There is no [`Mode`] to open sockets with: every operation waits until it
+/// completes, and a timeout is applied by racing it with a timer of the
+/// executor.
+///
+/// [`TcpStack`]: ../trait.TcpStack.html
+/// [`Mode`]: ../enum.Mode.html
+#[allow(async_fn_in_trait)]
+pub trait TcpStackAsync {
+	/// The type returned when we create a new TCP socket
+	type TcpSocket;
+	/// The type returned when we have an error
+	type Error: TcpError;
+
+	/// Open a new TCP socket. The socket starts in the unconnected state.
+	async fn open(&self) -> Result<Self::TcpSocket, Self::Error>;
+
+	/// Connect to the given remote host and port.
+	///
+	/// If the future is dropped before it completes, the socket it was
+	/// given is dropped with it, and must then be released by the stack, as
+	/// [`close`](#tymethod.close) can't be awaited from a destructor.
+	async fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error>;
+
+	/// Check if this socket is connected
+	async fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error>;
+
+	/// Write to the stream, once at least some of `buffer` can be. Returns the
+	/// number of bytes written (which may be less than `buffer.len()`), or an
+	/// error.
+	async fn write(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &[u8],
+	) -> Result<usize, Self::Error>;
+
+	/// Read from the stream, once data has been received. Returns `Ok(n)`,
+	/// which means `n` bytes of data have been received and they have been
+	/// placed in `&buffer[0..n]`, or an error. As with [`TcpStack::read`],
+	/// `Ok(0)` into a non-empty `buffer` means the peer closed the stream.
+	///
+	/// [`TcpStack::read`]: ../trait.TcpStack.html#tymethod.read
+	async fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> Result<usize, Self::Error>;
+
+	/// Read from the stream, like [`read`](#tymethod.read), telling data
+	/// apart from the end of the stream and from a reset connection, as
+	/// [`TcpStack::receive`] does.
+	///
+	/// [`TcpStack::receive`]: ../trait.TcpStack.html#method.receive
+	async fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> Result<RecvOutcome, Self::Error> {
+		let empty = buffer.is_empty();
+		match self.read(socket, buffer).await {
+			Ok(0) if !empty => Ok(RecvOutcome::Closed),
+			Ok(n) => Ok(RecvOutcome::Data(n)),
+			Err(ref e) if e.kind() == NetworkErrorKind::ConnectionReset => Ok(RecvOutcome::Reset),
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Close an existing TCP socket.
+	async fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error>;
+}
+
+/// The async version of [`UdpStack`]. Sockets are connected to a remote
+/// address as with [`UdpStack`], and opened without a [`Mode`] as with
+/// [`TcpStackAsync`].
+///
+/// [`UdpStack`]: ../trait.UdpStack.html
+/// [`Mode`]: ../enum.Mode.html
+/// [`TcpStackAsync`]: trait.TcpStackAsync.html
+#[allow(async_fn_in_trait)]
+pub trait UdpStackAsync {
+	/// The type returned when we create a new UDP socket
+	type UdpSocket;
+	/// The type returned when we have an error
+	type Error: UdpError;
+
+	/// Open a new UDP socket to the given address and port.
+	async fn open(&self, remote: SocketAddr) -> Result<Self::UdpSocket, Self::Error>;
+
+	/// Send a datagram to the remote host.
+	async fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> Result<(), Self::Error>;
+
+	/// Read a datagram the remote host has sent to us, once one has been
+	/// received. Returns `Ok(n)`, which means a datagram of size `n` has been
+	/// received and it has been placed in `&buffer[0..n]`, or an error.
+	async fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> Result<usize, Self::Error>;
+
+	/// Close an existing UDP socket.
+	async fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error>;
+}
+
+/// This trait is an extension trait for [`TcpStack`] for stacks that can wake
+/// a task when a socket becomes ready. It is the primitive needed to build
+/// efficient async adapters over the `nb` traits: after an operation returned
+/// `WouldBlock`, the adapter registers the task's waker and sleeps until the
+/// stack signals readiness, instead of polling in a loop.
+///
+/// Only the most recently registered waker of each kind is kept, and it is
+/// woken once, so it must be registered again after every `WouldBlock`.
+///
+/// [`TcpStack`]: ../trait.TcpStack.html
+pub trait TcpWakers: TcpStack {
+	/// Wake `waker` when data can be read from `socket`, or it is closed.
+	fn register_read_waker(&self, socket: &Self::TcpSocket, waker: &Waker);
+
+	/// Wake `waker` when data can be written to `socket`, or it becomes
+	/// connected.
+	fn register_write_waker(&self, socket: &Self::TcpSocket, waker: &Waker);
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that can wake
+/// a task when a socket becomes ready, like [`TcpWakers`] does for TCP.
+///
+/// [`UdpStack`]: ../trait.UdpStack.html
+/// [`TcpWakers`]: trait.TcpWakers.html
+pub trait UdpWakers: UdpStack {
+	/// Wake `waker` when a datagram can be read from `socket`.
+	fn register_read_waker(&self, socket: &Self::UdpSocket, waker: &Waker);
+
+	/// Wake `waker` when a datagram can be written to `socket`.
+	fn register_write_waker(&self, socket: &Self::UdpSocket, waker: &Waker);
+}
+
+/// This trait is an extension trait for [`TcpStack`] for stacks that can be
+/// polled from a hand-written future or executor, with the task's `Context`.
+/// Unlike [`TcpStackAsync`] it needs neither `async fn` in traits nor boxed
+/// futures.
+///
+/// Each method returns `Poll::Pending` when the operation can't complete
+/// yet, after arranging for the task's waker to be woken once it may.
+///
+/// [`TcpStack`]: ../trait.TcpStack.html
+/// [`TcpStackAsync`]: trait.TcpStackAsync.html
+pub trait TcpPoll: TcpStack {
+	/// Connect `socket` to the given remote host and port. The first call
+	/// starts connecting, and later calls with the same `remote` report
+	/// whether the connection is up.
+	fn poll_connect(
+		&self,
+		socket: &mut Self::TcpSocket,
+		remote: SocketAddr,
+		cx: &mut Context<'_>,
+	) -> Poll<Result<(), Self::Error>>;
+
+	/// Write to the stream. Returns the number of bytes written (which may be
+	/// less than `buffer.len()`), or an error.
+	fn poll_write(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &[u8],
+		cx: &mut Context<'_>,
+	) -> Poll<Result<usize, Self::Error>>;
+
+	/// Read from the stream. Returns `Ok(n)`, which means `n` bytes of data
+	/// have been received and they have been placed in `&buffer[0..n]`, or an
+	/// error. As with [`TcpStack::read`], `Ok(0)` into a non-empty `buffer`
+	/// means the peer closed the stream.
+	///
+	/// [`TcpStack::read`]: ../trait.TcpStack.html#tymethod.read
+	fn poll_read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+		cx: &mut Context<'_>,
+	) -> Poll<Result<usize, Self::Error>>;
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that can be
+/// polled with the task's `Context`, like [`TcpPoll`] does for TCP.
+///
+/// [`UdpStack`]: ../trait.UdpStack.html
+/// [`TcpPoll`]: trait.TcpPoll.html
+pub trait UdpPoll: UdpStack {
+	/// Send a datagram to the remote host.
+	fn poll_write(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &[u8],
+		cx: &mut Context<'_>,
+	) -> Poll<Result<(), Self::Error>>;
+
+	/// Read a datagram the remote host has sent to us. Returns `Ok(n)`, which
+	/// means a datagram of size `n` has been received and it has been placed
+	/// in `&buffer[0..n]`, or an error.
+	fn poll_read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+		cx: &mut Context<'_>,
+	) -> Poll<Result<usize, Self::Error>>;
+}
+
+/// How an [`Awaitable`] stack waits for a TCP socket that returned
+/// `WouldBlock` to become ready in the given direction.
+///
+/// [`Awaitable`]: struct.Awaitable.html
+pub trait TcpWait<S: TcpStack> {
+	/// Arrange for `waker` to be woken once `socket` may be ready.
+	fn wait(&self, stack: &S, socket: &S::TcpSocket, direction: Direction, waker: &Waker);
+}
+
+/// How an [`Awaitable`] stack waits for a UDP socket that returned
+/// `WouldBlock` to become ready in the given direction.
+///
+/// [`Awaitable`]: struct.Awaitable.html
+pub trait UdpWait<S: UdpStack> {
+	/// Arrange for `waker` to be woken once `socket` may be ready.
+	fn wait(&self, stack: &S, socket: &S::UdpSocket, direction: Direction, waker: &Waker);
+}
+
+/// A wait strategy that wakes the task straight away, so that the executor
+/// polls the stack again as soon as it has run its other tasks. It works with
+/// any stack, at the cost of keeping the executor busy.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Spin;
+
+impl<S: TcpStack> TcpWait<S> for Spin {
+	fn wait(&self, _stack: &S, _socket: &S::TcpSocket, _direction: Direction, waker: &Waker) {
+		waker.wake_by_ref();
+	}
+}
+
+impl<S: UdpStack> UdpWait<S> for Spin {
+	fn wait(&self, _stack: &S, _socket: &S::UdpSocket, _direction: Direction, waker: &Waker) {
+		waker.wake_by_ref();
+	}
+}
+
+/// A wait strategy that registers the task's waker with stacks implementing
+/// [`TcpWakers`] or [`UdpWakers`], so that the task sleeps until the stack
+/// signals readiness.
+///
+/// [`TcpWakers`]: trait.TcpWakers.html
+/// [`UdpWakers`]: trait.UdpWakers.html
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StackWakers;
+
+impl<S: TcpWakers> TcpWait<S> for StackWakers {
+	fn wait(&self, stack: &S, socket: &S::TcpSocket, direction: Direction, waker: &Waker) {
+		match direction {
+			Direction::Outgoing => stack.register_write_waker(socket, waker),
+			Direction::Incoming => stack.register_read_waker(socket, waker),
+		}
+	}
+}
+
+impl<S: UdpWakers> UdpWait<S> for StackWakers {
+	fn wait(&self, stack: &S, socket: &S::UdpSocket, direction: Direction, waker: &Waker) {
+		match direction {
+			Direction::Outgoing => stack.register_write_waker(socket, waker),
+			Direction::Incoming => stack.register_read_waker(socket, waker),
+		}
+	}
+}
+
+/// The TCP error type of an [`Awaitable`] stack.
+///
+/// [`Awaitable`]: struct.Awaitable.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum AwaitableError<E> {
+	/// The wrapped stack failed
+	Stack(E),
+	/// The connection wasn't up within the connect timeout
+	ConnectTimeout,
+}
+
+impl<E: TcpError> TcpError for AwaitableError<E> {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			AwaitableError::Stack(e) => e.kind(),
+			AwaitableError::ConnectTimeout => NetworkErrorKind::Timeout,
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for AwaitableError<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			AwaitableError::Stack(e) => e.fmt(f),
+			AwaitableError::ConnectTimeout => f.write_str("the connection timed out"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for AwaitableError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			AwaitableError::Stack(e) => Some(e),
+			AwaitableError::ConnectTimeout => None,
+		}
+	}
+}
+
+/// The clock of an [`Awaitable`] stack without a connect timeout, which
+/// never needs the time.
+///
+/// [`Awaitable`]: struct.Awaitable.html
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoClock;
+
+impl Clock for NoClock {
+	fn now_ms(&self) -> u64 {
+		0
+	}
+}
+
+/// An adapter giving an `nb` [`TcpStack`] or [`UdpStack`] the async
+/// [`TcpStackAsync`] and [`UdpStackAsync`] traits, so that existing drivers
+/// can be used from async code without being rewritten.
+///
+/// Sockets are opened in [`Mode::NonBlocking`]. When an operation returns
+/// `WouldBlock`, the adapter waits according to its strategy `W`, either
+/// [`Spin`] or [`StackWakers`], and tries again once woken.
+///
+/// Connecting waits for [`TcpStack::is_connected`] to report the connection
+/// up, and fails if it returns an error. Many stacks instead keep reporting
+/// a refused connection as not connected yet, so give the adapter a connect
+/// timeout with [`with_connect_timeout`](#method.with_connect_timeout) for
+/// such connections to fail with [`AwaitableError::ConnectTimeout`].
+/// Otherwise they are waited for until the future is dropped. The timeout
+/// is checked whenever the task is polled: with [`StackWakers`], a stack
+/// that doesn't wake the task when a connection fails still needs the
+/// future to be raced with a timer of the executor. A socket whose connect
+/// fails, times out or is dropped is closed.
+///
+/// [`TcpStack`]: ../trait.TcpStack.html
+/// [`UdpStack`]: ../trait.UdpStack.html
+/// [`TcpStackAsync`]: trait.TcpStackAsync.html
+/// [`UdpStackAsync`]: trait.UdpStackAsync.html
+/// [`Mode::NonBlocking`]: ../enum.Mode.html#variant.NonBlocking
+/// [`Spin`]: struct.Spin.html
+/// [`StackWakers`]: struct.StackWakers.html
+/// [`TcpStack::is_connected`]: ../trait.TcpStack.html#tymethod.is_connected
+/// [`AwaitableError::ConnectTimeout`]: enum.AwaitableError.html#variant.ConnectTimeout
+pub struct Awaitable<S, W, C = NoClock> {
+	stack: S,
+	wait: W,
+	clock: C,
+	connect_timeout: Option<Duration>,
+}
+
+impl<S, W> Awaitable<S, W> {
+	/// Wrap `stack`, waiting for its sockets according to `wait`, with no
+	/// connect timeout.
+	pub fn new(stack: S, wait: W) -> Self {
+		Awaitable {
+			stack,
+			wait,
+			clock: NoClock,
+			connect_timeout: None,
+		}
+	}
+}
+
+impl<S, W, C: Clock> Awaitable<S, W, C> {
+	/// Wrap `stack`, waiting for its sockets according to `wait`, and failing
+	/// connections that aren't up within `timeout` as measured by `clock`.
+	pub fn with_connect_timeout(stack: S, wait: W, clock: C, timeout: Duration) -> Self {
+		Awaitable {
+			stack,
+			wait,
+			clock,
+			connect_timeout: Some(timeout),
+		}
+	}
+}
+
+impl<S, W, C> Awaitable<S, W, C> {
+	/// Get a reference to the wrapped stack.
+	pub fn inner(&self) -> &S {
+		&self.stack
+	}
+
+	/// Destroy the adapter, returning the wrapped stack.
+	pub fn into_inner(self) -> S {
+		self.stack
+	}
+}
+
+/// A socket that is closed when dropped, unless taken out first, so that
+/// the socket of a connect future that is dropped is closed.
+struct CloseOnDrop<'a, S: TcpStack> {
+	stack: &'a S,
+	socket: Option<S::TcpSocket>,
+}
+
+impl<'a, S: TcpStack> Drop for CloseOnDrop<'a, S> {
+	fn drop(&mut self) {
+		if let Some(socket) = self.socket.take() {
+			self.stack.close(socket).ok();
+		}
+	}
+}
+
+/// Turn the result of an `nb` call on `socket` into a `Poll`, waiting with
+/// `wait` if it would block. The call is made again once the waker is
+/// registered, as the socket may have become ready in between.
+fn poll_nb<K, T, E>(
+	socket: &mut K,
+	mut call: impl FnMut(&mut K) -> nb::Result<T, E>,
+	wait: impl FnOnce(&K),
+) -> Poll<Result<T, E>> {
+	match call(socket) {
+		Err(nb::Error::WouldBlock) => {}
+		result => return Poll::Ready(result.map_err(unwrap_other)),
+	}
+	wait(socket);
+	match call(socket) {
+		Err(nb::Error::WouldBlock) => Poll::Pending,
+		result => Poll::Ready(result.map_err(unwrap_other)),
+	}
+}
+
+fn unwrap_other<E>(error: nb::Error<E>) -> E {
+	match error {
+		nb::Error::Other(e) => e,
+		nb::Error::WouldBlock => unreachable!(),
+	}
+}
+
+impl<S: TcpStack, W: TcpWait<S>, C: Clock> TcpStackAsync for Awaitable<S, W, C> {
+	type TcpSocket = S::TcpSocket;
+	type Error = AwaitableError<S::Error>;
+
+	async fn open(&self) -> Result<Self::TcpSocket, Self::Error> {
+		TcpStack::open(&self.stack, Mode::NonBlocking).map_err(AwaitableError::Stack)
+	}
+
+	async fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		let socket = self
+			.stack
+			.connect(socket, remote)
+			.map_err(AwaitableError::Stack)?;
+		let mut guard = CloseOnDrop {
+			stack: &self.stack,
+			socket: Some(socket),
+		};
+		let started = self.clock.now_ms();
+		poll_fn(|cx| {
+			if let Some(timeout) = self.connect_timeout {
+				if self.clock.now_ms().saturating_sub(started) >= millis(timeout) {
+					return Poll::Ready(Err(AwaitableError::ConnectTimeout));
+				}
+			}
+			let socket = guard
+				.socket
+				.as_mut()
+				.expect("socket taken before connecting");
+			poll_nb(
+				socket,
+				|socket| match self.stack.is_connected(socket) {
+					Ok(true) => Ok(()),
+					Ok(false) => Err(nb::Error::WouldBlock),
+					Err(e) => Err(nb::Error::Other(AwaitableError::Stack(e))),
+				},
+				|socket| {
+					self.wait
+						.wait(&self.stack, socket, Direction::Outgoing, cx.waker())
+				},
+			)
+		})
+		.await?;
+		Ok(guard.socket.take().expect("socket taken before connecting"))
+	}
+
+	async fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.stack
+			.is_connected(socket)
+			.map_err(AwaitableError::Stack)
+	}
+
+	async fn write(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &[u8],
+	) -> Result<usize, Self::Error> {
+		poll_fn(|cx| {
+			poll_nb(
+				socket,
+				|socket| TcpStack::write(&self.stack, socket, buffer),
+				|socket| {
+					self.wait
+						.wait(&self.stack, socket, Direction::Outgoing, cx.waker())
+				},
+			)
+		})
+		.await
+		.map_err(AwaitableError::Stack)
+	}
+
+	async fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> Result<usize, Self::Error> {
+		poll_fn(|cx| {
+			poll_nb(
+				socket,
+				|socket| TcpStack::read(&self.stack, socket, buffer),
+				|socket| {
+					self.wait
+						.wait(&self.stack, socket, Direction::Incoming, cx.waker())
+				},
+			)
+		})
+		.await
+		.map_err(AwaitableError::Stack)
+	}
+
+	async fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> Result<RecvOutcome, Self::Error> {
+		poll_fn(|cx| {
+			poll_nb(
+				socket,
+				|socket| self.stack.receive(socket, buffer),
+				|socket| {
+					self.wait
+						.wait(&self.stack, socket, Direction::Incoming, cx.waker())
+				},
+			)
+		})
+		.await
+		.map_err(AwaitableError::Stack)
+	}
+
+	async fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		TcpStack::close(&self.stack, socket).map_err(AwaitableError::Stack)
+	}
+}
+
+impl<S: UdpStack, W: UdpWait<S>, C> UdpStackAsync for Awaitable<S, W, C> {
+	type UdpSocket = S::UdpSocket;
+	type Error = S::Error;
+
+	async fn open(&self, remote: SocketAddr) -> Result<Self::UdpSocket, Self::Error> {
+		UdpStack::open(&self.stack, remote, Mode::NonBlocking)
+	}
+
+	async fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> Result<(), Self::Error> {
+		poll_fn(|cx| {
+			poll_nb(
+				socket,
+				|socket| UdpStack::write(&self.stack, socket, buffer),
+				|socket| {
+					self.wait
+						.wait(&self.stack, socket, Direction::Outgoing, cx.waker())
+				},
+			)
+		})
+		.await
+	}
+
+	async fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> Result<usize, Self::Error> {
+		poll_fn(|cx| {
+			poll_nb(
+				socket,
+				|socket| UdpStack::read(&self.stack, socket, buffer),
+				|socket| {
+					self.wait
+						.wait(&self.stack, socket, Direction::Incoming, cx.waker())
+				},
+			)
+		})
+		.await
+	}
+
+	async fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		UdpStack::close(&self.stack, socket)
+	}
+}
+
+/// The async version of [`connect_host`]: resolve `hostname` with `dns` and
+/// connect a new socket of `stack` to port `port` of it, trying each of its
+/// first few addresses in turn until one accepts the connection.
+///
+/// On success, the connected socket is returned along with the address and
+/// hostname it connected to, which a TLS handshake can then be started over.
+/// As with the blocking version, the handshake is left to the caller: there
+/// is no TLS trait in this crate for it to be driven through.
+///
+/// The next address is only tried once opening a socket for the previous one
+/// or connecting to it fails. With an [`Awaitable`] stack, refused connections often don't fail
+/// but stay pending, so give it a connect timeout with
+/// [`Awaitable::with_connect_timeout`] for the other addresses to be tried.
+///
+/// The future is cancellation safe: it only ever holds a socket across an
+/// `await` inside [`TcpStackAsync::connect`], which owns the socket of the
+/// attempt in progress. If the future is dropped, such as by a timeout, the
+/// socket is dropped with the connect future, which releases it, as
+/// [`Awaitable`] does by closing it with the wrapped stack. There is no
+/// guard that closes it here, as `TcpStackAsync::close` is async and can't
+/// be awaited on drop.
+///
+/// [`connect_host`]: ../fn.connect_host.html
+/// [`Awaitable`]: struct.Awaitable.html
+/// [`Awaitable::with_connect_timeout`]: struct.Awaitable.html#method.with_connect_timeout
+/// [`TcpStackAsync::connect`]: trait.TcpStackAsync.html#tymethod.connect
+pub async fn connect_host<S, D>(
+	stack: &S,
+	dns: &D,
+	hostname: &str,
+	port: u16,
+) -> Result<(S::TcpSocket, HostAddr), ConnectError<S::Error, D::Error>>
+where
+	S: TcpStackAsync,
+	D: DnsAsync,
+{
+	let mut addrs = [IpAddr::from([0; 4]); MAX_ADDRS];
+	let found = dns
+		.get_hosts_by_name(hostname, AddrType::Either, &mut addrs)
+		.await
+		.map_err(ConnectError::Dns)?;
+
+	let mut error = None;
+	for &addr in &addrs[..found] {
+		let socket = match stack.open().await {
+			Ok(socket) => socket,
+			Err(e) => {
+				error = Some(e);
+				continue;
+			}
+		};
+		match stack.connect(socket, SocketAddr::new(addr, port)).await {
+			Ok(socket) => return Ok((socket, HostAddr::new(addr, Some(hostname)))),
+			Err(e) => error = Some(e),
+		}
+	}
+	match error {
+		Some(e) => Err(ConnectError::Tcp(e)),
+		None => Err(ConnectError::NoAddress),
+	}
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+	use super::*;
+	use core::cell::Cell;
+	use core::future::Future;
+	use std::sync::Arc;
+	use std::task::Wake;
+
+	struct NoopWaker;
+
+	impl Wake for NoopWaker {
+		fn wake(self: Arc<Self>) {}
+	}
+
+	fn block_on<F: Future>(future: F) -> F::Output {
+		let waker = Waker::from(Arc::new(NoopWaker));
+		let mut cx = Context::from_waker(&waker);
+		let mut future = core::pin::pin!(future);
+		loop {
+			if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+				return output;
+			}
+		}
+	}
+
+	/// A stack whose connections never come up, like those of many stacks
+	/// when the connection is refused.
+	#[derive(Default)]
+	struct NeverConnects {
+		closed: Cell<usize>,
+	}
+
+	impl TcpStack for NeverConnects {
+		type TcpSocket = u8;
+		type Error = crate::MockError;
+
+		fn open(&self, _mode: Mode) -> Result<u8, Self::Error> {
+			Ok(0)
+		}
+
+		fn connect(&self, socket: u8, _remote: SocketAddr) -> Result<u8, Self::Error> {
+			Ok(socket)
+		}
+
+		fn is_connected(&self, _socket: &u8) -> Result<bool, Self::Error> {
+			Ok(false)
+		}
+
+		fn write(&self, _socket: &mut u8, _buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+			Err(nb::Error::WouldBlock)
+		}
+
+		fn read(&self, _socket: &mut u8, _buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+			Err(nb::Error::WouldBlock)
+		}
+
+		fn close(&self, _socket: u8) -> Result<(), Self::Error> {
+			self.closed.set(self.closed.get() + 1);
+			Ok(())
+		}
+	}
+
+	/// A clock that advances by 10 ms every time it is read.
+	#[derive(Default)]
+	struct Ticking(Cell<u64>);
+
+	impl Clock for Ticking {
+		fn now_ms(&self) -> u64 {
+			self.0.set(self.0.get() + 10);
+			self.0.get()
+		}
+	}
+
+	#[test]
+	fn connect_times_out_and_closes_the_socket() {
+		let stack = Awaitable::with_connect_timeout(
+			NeverConnects::default(),
+			Spin,
+			Ticking::default(),
+			Duration::from_millis(100),
+		);
+		let remote = SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 80);
+		let result = block_on(async {
+			let socket = TcpStackAsync::open(&stack).await?;
+			stack.connect(socket, remote).await
+		});
+		assert_eq!(result, Err(AwaitableError::ConnectTimeout));
+		assert_eq!(stack.inner().closed.get(), 1);
+	}
+
+	#[test]
+	fn dropped_connect_closes_the_socket() {
+		let stack = Awaitable::new(NeverConnects::default(), Spin);
+		let remote = SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 80);
+		{
+			let waker = Waker::from(Arc::new(NoopWaker));
+			let mut cx = Context::from_waker(&waker);
+			let connect = stack.connect(0, remote);
+			let mut connect = core::pin::pin!(connect);
+			assert!(connect.as_mut().poll(&mut cx).is_pending());
+		}
+		assert_eq!(stack.inner().closed.get(), 1);
+	}
+
+	struct TwoAddrs;
+
+	impl DnsAsync for TwoAddrs {
+		type Error = crate::LoopbackError;
+
+		async fn get_host_by_name(
+			&self,
+			_hostname: &str,
+			_addr_type: AddrType,
+		) -> Result<IpAddr, Self::Error> {
+			Ok(IpAddr::from([10, 0, 0, 1]))
+		}
+
+		async fn get_hosts_by_name(
+			&self,
+			_hostname: &str,
+			_addr_type: AddrType,
+			out: &mut [IpAddr],
+		) -> Result<usize, Self::Error> {
+			out[0] = IpAddr::from([10, 0, 0, 1]);
+			out[1] = IpAddr::from([10, 0, 0, 2]);
+			Ok(2)
+		}
+
+		async fn get_host_by_address(
+			&self,
+			_addr: IpAddr,
+		) -> Result<String<consts::U256>, Self::Error> {
+			Err(crate::LoopbackError::NxDomain)
+		}
+	}
+
+	#[test]
+	fn connect_host_tries_the_next_address_after_a_timeout() {
+		let stack = Awaitable::with_connect_timeout(
+			NeverConnects::default(),
+			Spin,
+			Ticking::default(),
+			Duration::from_millis(100),
+		);
+		let result = block_on(connect_host(&stack, &TwoAddrs, "example.com", 80));
+		match result {
+			Err(ConnectError::Tcp(AwaitableError::ConnectTimeout)) => {}
+			_ => panic!("expected a connect timeout"),
+		}
+		assert_eq!(stack.inner().closed.get(), 2);
+	}
+
+	#[test]
+	fn connect_host_tries_the_next_address_after_a_failed_open() {
+		let script = [
+			crate::Expectation::new(crate::Call::TcpOpen).fail(),
+			crate::Expectation::new(crate::Call::TcpOpen),
+			crate::Expectation::new(crate::Call::TcpConnect(SocketAddr::new(
+				IpAddr::from([10, 0, 0, 2]),
+				80,
+			))),
+		];
+		let stack = Awaitable::new(crate::MockStack::new(&script), Spin);
+		let (_, host) = block_on(connect_host(&stack, &TwoAddrs, "example.com", 80)).unwrap();
+		assert_eq!(host.ip, IpAddr::from([10, 0, 0, 2]));
+	}
+}
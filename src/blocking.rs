@@ -0,0 +1,250 @@
+use crate::{DatagramBuf, Delay, Mode, RecvOutcome, TcpStack, UdpStack};
+use core::cell::RefCell;
+use core::time::Duration;
+use no_std_net::SocketAddr;
+
+/// A wrapper around a [`TcpStack`] and [`UdpStack`] that waits out
+/// `WouldBlock`, so that simple firmware gets a blocking API over any `nb`
+/// stack.
+///
+/// Reads and writes that would block are tried again every `interval`,
+/// pausing in between with a [`Delay`], until they complete or, if a
+/// `timeout` is given, until it has been waited for. Only then is
+/// `WouldBlock` returned. The time is counted in intervals waited, so it
+/// leaves out the time taken by the calls to the stack. A zero interval
+/// tries again straight away, and each try then counts as a millisecond, so
+/// that the timeout still ends the wait.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`Delay`]: trait.Delay.html
+pub struct Blocking<S, D> {
+	stack: S,
+	delay: RefCell<D>,
+	interval: Duration,
+	timeout: Option<Duration>,
+}
+
+impl<S, D: Delay> Blocking<S, D> {
+	/// Wrap `stack`, trying operations that would block again every
+	/// `interval` as paused for by `delay`, for up to `timeout` or forever.
+	pub fn new(stack: S, delay: D, interval: Duration, timeout: Option<Duration>) -> Self {
+		Blocking {
+			stack,
+			delay: RefCell::new(delay),
+			interval,
+			timeout,
+		}
+	}
+
+	/// Get a reference to the wrapped stack.
+	pub fn inner(&self) -> &S {
+		&self.stack
+	}
+
+	/// Destroy the wrapper, returning the wrapped stack and delay.
+	pub fn into_inner(self) -> (S, D) {
+		(self.stack, self.delay.into_inner())
+	}
+
+	/// Call `operation` until it doesn't return `WouldBlock`, or the timeout
+	/// has been waited for.
+	fn block<T, E>(&self, mut operation: impl FnMut() -> nb::Result<T, E>) -> nb::Result<T, E> {
+		let mut waited = Duration::from_secs(0);
+		loop {
+			match operation() {
+				Err(nb::Error::WouldBlock) => {}
+				result => return result,
+			}
+			if let Some(timeout) = self.timeout {
+				if waited >= timeout {
+					return Err(nb::Error::WouldBlock);
+				}
+				let tick = core::cmp::max(self.interval, Duration::from_millis(1));
+				waited = waited.checked_add(tick).unwrap_or(timeout);
+			}
+			self.delay.borrow_mut().delay(self.interval);
+		}
+	}
+}
+
+impl<S: TcpStack, D: Delay> TcpStack for Blocking<S, D> {
+	type TcpSocket = S::TcpSocket;
+	type Error = S::Error;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		self.stack.open(mode)
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		self.stack.connect(socket, remote)
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.stack.is_connected(socket)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		self.block(|| self.stack.write(socket, buffer))
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		self.block(|| TcpStack::read(&self.stack, socket, buffer))
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		self.block(|| self.stack.receive(socket, buffer))
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		TcpStack::close(&self.stack, socket)
+	}
+}
+
+impl<S: UdpStack, D: Delay> UdpStack for Blocking<S, D> {
+	type UdpSocket = S::UdpSocket;
+	type Error = S::Error;
+
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		self.stack.open(remote, mode)
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		self.block(|| self.stack.write(socket, buffer))
+	}
+
+	fn write_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, Self::Error> {
+		// Only wait for the first datagram, like the stack itself does.
+		self.block(|| self.stack.write_batch(socket, datagrams))
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		self.block(|| UdpStack::read(&self.stack, socket, buffer))
+	}
+
+	fn read_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, Self::Error> {
+		self.block(|| self.stack.read_batch(socket, datagrams))
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		UdpStack::close(&self.stack, socket)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Call, Expectation, MockStack};
+	use no_std_net::IpAddr;
+
+	/// A delay that doesn't pause, and counts how often it was asked to.
+	struct Count(usize);
+
+	impl Delay for Count {
+		fn delay(&mut self, _duration: Duration) {
+			self.0 += 1;
+		}
+	}
+
+	/// A stack whose connections are always reset, which only `receive`
+	/// reports.
+	struct Resets;
+
+	impl TcpStack for Resets {
+		type TcpSocket = u8;
+		type Error = crate::MockError;
+
+		fn open(&self, _mode: Mode) -> Result<u8, Self::Error> {
+			Ok(0)
+		}
+
+		fn connect(&self, socket: u8, _remote: SocketAddr) -> Result<u8, Self::Error> {
+			Ok(socket)
+		}
+
+		fn is_connected(&self, _socket: &u8) -> Result<bool, Self::Error> {
+			Ok(false)
+		}
+
+		fn write(&self, _socket: &mut u8, _buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+			Err(nb::Error::WouldBlock)
+		}
+
+		fn read(&self, _socket: &mut u8, _buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+			Ok(0)
+		}
+
+		fn receive(
+			&self,
+			_socket: &mut u8,
+			_buffer: &mut [u8],
+		) -> nb::Result<RecvOutcome, Self::Error> {
+			Ok(RecvOutcome::Reset)
+		}
+
+		fn close(&self, _socket: u8) -> Result<(), Self::Error> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn receive_is_forwarded() {
+		let stack = Blocking::new(Resets, Count(0), Duration::from_millis(1), None);
+		let mut socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		let mut buffer = [0; 4];
+		assert_eq!(
+			stack.receive(&mut socket, &mut buffer),
+			Ok(RecvOutcome::Reset)
+		);
+	}
+
+	#[test]
+	fn zero_interval_still_times_out() {
+		let remote = SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 80);
+		let mut script = [Expectation::new(Call::TcpRead(b"")).blocked(); 8];
+		script[0] = Expectation::new(Call::TcpOpen);
+		script[1] = Expectation::new(Call::TcpConnect(remote));
+		script[7] = Expectation::new(Call::TcpRead(b"data"));
+		let stack = Blocking::new(
+			MockStack::new(&script),
+			Count(0),
+			Duration::from_secs(0),
+			Some(Duration::from_millis(3)),
+		);
+		let socket = TcpStack::open(&stack, Mode::NonBlocking).unwrap();
+		let mut socket = stack.connect(socket, remote).unwrap();
+		let mut buffer = [0; 4];
+		assert_eq!(
+			TcpStack::read(&stack, &mut socket, &mut buffer),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(TcpStack::read(&stack, &mut socket, &mut buffer), Ok(4));
+		// Three pauses before giving up, and one more before the data came.
+		let (_, delay) = stack.into_inner();
+		assert_eq!(delay.0, 4);
+	}
+}
@@ -0,0 +1,55 @@
+use crate::{TcpStack, UdpStack};
+
+/// Something that happened on a socket, reported to a [`SocketCallback`].
+///
+/// [`SocketCallback`]: type.SocketCallback.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SocketEvent {
+	/// Data arrived and can be read
+	DataReceived,
+	/// A TCP connection was established
+	Connected,
+	/// A TCP connection was closed, by the peer or because of an error
+	Closed,
+}
+
+/// A function called by the stack when something happens on a socket, with
+/// the token it was registered with.
+///
+/// It may be called from the stack's interrupt or URC handler, so it must
+/// return quickly and must not call back into the stack. Typically it sets a
+/// flag or wakes a task, using the token to tell sockets apart.
+pub type SocketCallback = fn(token: usize, event: SocketEvent);
+
+/// This trait is an extension trait for [`TcpStack`] for stacks that can
+/// notify the application of events on a socket, as URC-driven modem drivers
+/// naturally do, so that the application doesn't have to keep polling.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+pub trait TcpCallbacks: TcpStack {
+	/// Call `callback` with `token` whenever data arrives on `socket`, it
+	/// becomes connected, or it is closed. `None` removes the callback.
+	fn set_tcp_callback(
+		&self,
+		socket: &mut Self::TcpSocket,
+		callback: Option<SocketCallback>,
+		token: usize,
+	) -> Result<(), Self::Error>;
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that can
+/// notify the application when datagrams arrive on a socket.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpCallbacks: UdpStack {
+	/// Call `callback` with `token` and [`SocketEvent::DataReceived`]
+	/// whenever a datagram arrives on `socket`. `None` removes the callback.
+	///
+	/// [`SocketEvent::DataReceived`]: enum.SocketEvent.html#variant.DataReceived
+	fn set_udp_callback(
+		&self,
+		socket: &mut Self::UdpSocket,
+		callback: Option<SocketCallback>,
+		token: usize,
+	) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,106 @@
+use crate::proxy::{read_status, write_all, IoError};
+use crate::{AddrType, Dns, Mode, TcpStack};
+use no_std_net::SocketAddr;
+
+/// The HTTP endpoint probed by [`detect_captive_portal`]. It must answer a
+/// plain `GET` with a fixed status code.
+///
+/// The default probes `http://connectivitycheck.gstatic.com/generate_204`,
+/// which answers with status 204.
+///
+/// [`detect_captive_portal`]: fn.detect_captive_portal.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnectivityProbe<'a> {
+	/// The hostname of the endpoint
+	pub host: &'a str,
+	/// The TCP port of the endpoint, usually 80
+	pub port: u16,
+	/// The path to request
+	pub path: &'a str,
+	/// The status code the endpoint answers with
+	pub expected_status: u16,
+}
+
+impl<'a> Default for ConnectivityProbe<'a> {
+	fn default() -> Self {
+		ConnectivityProbe {
+			host: "connectivitycheck.gstatic.com",
+			port: 80,
+			path: "/generate_204",
+			expected_status: 204,
+		}
+	}
+}
+
+/// How a network connects to the internet, as found by
+/// [`detect_captive_portal`].
+///
+/// [`detect_captive_portal`]: fn.detect_captive_portal.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Connectivity {
+	/// The probe was answered as expected
+	Open,
+	/// Something else answered the probe, usually a captive portal
+	/// redirecting to its login page
+	CaptivePortal,
+	/// The probe could not be resolved, connected to, or got no answer
+	NoConnectivity,
+}
+
+/// Send the probe's request over `socket` and read the status of the answer.
+fn request_status<S: TcpStack>(
+	stack: &S,
+	socket: &mut S::TcpSocket,
+	probe: &ConnectivityProbe,
+) -> Result<Option<u16>, IoError<S::Error>> {
+	for part in &[
+		"GET ",
+		probe.path,
+		" HTTP/1.1\r\nHost: ",
+		probe.host,
+		"\r\nConnection: close\r\n\r\n",
+	] {
+		write_all(stack, socket, part.as_bytes())?;
+	}
+	read_status(stack, socket)
+}
+
+/// Probe `probe` over `stack`, resolving it with `dns`, and classify the
+/// network: open internet, captive portal, or no connectivity at all. Wi-Fi
+/// products should do this before attempting TLS to the cloud, which would
+/// otherwise fail with a confusing certificate error behind a portal.
+///
+/// The probe uses plain HTTP, as intercepting it is how portals are detected.
+/// Errors of the stack and resolver are reported as
+/// [`Connectivity::NoConnectivity`]. This waits for the resolver and the stack
+/// as long as needed.
+///
+/// [`Connectivity::NoConnectivity`]: enum.Connectivity.html#variant.NoConnectivity
+pub fn detect_captive_portal<S, D>(stack: &S, dns: &D, probe: &ConnectivityProbe) -> Connectivity
+where
+	S: TcpStack,
+	D: Dns,
+{
+	let addr = match nb::block!(dns.get_host_by_name(probe.host, AddrType::Either)) {
+		Ok(addr) => addr,
+		Err(_) => return Connectivity::NoConnectivity,
+	};
+	let mut socket = match stack
+		.open(Mode::Blocking)
+		.and_then(|socket| stack.connect(socket, SocketAddr::new(addr, probe.port)))
+	{
+		Ok(socket) => socket,
+		Err(_) => return Connectivity::NoConnectivity,
+	};
+
+	let status = request_status(stack, &mut socket, probe);
+	stack.close(socket).ok();
+
+	match status {
+		Ok(Some(status)) if status == probe.expected_status => Connectivity::Open,
+		// Portals answer with redirects or login pages, and sometimes with
+		// responses that aren't even valid HTTP.
+		Ok(_) => Connectivity::CaptivePortal,
+		Err(_) => Connectivity::NoConnectivity,
+	}
+}
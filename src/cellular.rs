@@ -0,0 +1,111 @@
+/// The kind of packet data a PDP context carries.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PdpType {
+	/// IPv4
+	Ip,
+	/// IPv6
+	Ipv6,
+	/// IPv4 and IPv6
+	Ipv4v6,
+	/// Non-IP data, as used by NB-IoT NIDD
+	NonIp,
+}
+
+/// How to authenticate with the access point name of a PDP context.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApnAuth<'a> {
+	/// No authentication
+	None,
+	/// PAP, which sends the password in the clear
+	Pap {
+		/// The user name
+		username: &'a str,
+		/// The password
+		password: &'a str,
+	},
+	/// CHAP
+	Chap {
+		/// The user name
+		username: &'a str,
+		/// The password
+		password: &'a str,
+	},
+}
+
+/// The configuration of a PDP context, see
+/// [`CellularControl::configure_context`].
+///
+/// [`CellularControl::configure_context`]: trait.CellularControl.html#tymethod.configure_context
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PdpContext<'a> {
+	/// The access point name, such as `internet`
+	pub apn: &'a str,
+	/// The kind of packet data to carry
+	pub pdp_type: PdpType,
+	/// How to authenticate with the access point
+	pub auth: ApnAuth<'a>,
+}
+
+/// Whether the modem is registered on a cellular network, as returned by
+/// [`CellularControl::registration_state`].
+///
+/// [`CellularControl::registration_state`]: trait.CellularControl.html#tymethod.registration_state
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RegistrationState {
+	/// Not registered, and not looking for a network
+	NotRegistered,
+	/// Not registered, but looking for a network
+	Searching,
+	/// Registered on the home network
+	Home,
+	/// Registered on a roaming network
+	Roaming,
+	/// The network refused registration
+	Denied,
+	/// The modem does not know
+	Unknown,
+}
+
+/// This trait is implemented by cellular stacks, to configure and activate
+/// their packet data (PDP) contexts. Contexts are identified by the context
+/// identifier `cid` of 3GPP TS 27.007, which starts at 1.
+pub trait CellularControl {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Set the configuration of context `cid`. The context must not be
+	/// active.
+	fn configure_context(&self, cid: u8, context: &PdpContext) -> Result<(), Self::Error>;
+
+	/// Activate context `cid`, so that data can be sent over it.
+	///
+	/// Returns `WouldBlock` until the network has accepted the activation;
+	/// calling it again keeps waiting.
+	fn activate_context(&self, cid: u8) -> nb::Result<(), Self::Error>;
+
+	/// Deactivate context `cid`, closing all sockets using it.
+	fn deactivate_context(&self, cid: u8) -> nb::Result<(), Self::Error>;
+
+	/// Report whether the modem is registered on a network.
+	fn registration_state(&self) -> Result<RegistrationState, Self::Error>;
+}
+
+/// This trait is implemented by NB-IoT stacks that can exchange non-IP data
+/// over the control plane (NIDD, 3GPP TS 23.682), which some deployments use
+/// instead of IP altogether. Data is sent over a PDP context configured with
+/// [`PdpType::NonIp`], and is delivered by the network to the application
+/// server through the operator's SCEF.
+///
+/// [`PdpType::NonIp`]: enum.PdpType.html#variant.NonIp
+pub trait Nidd {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Send one message of non-IP data over context `cid`.
+	fn write_nidd(&self, cid: u8, buffer: &[u8]) -> nb::Result<(), Self::Error>;
+
+	/// Read one message of non-IP data received over context `cid`. Returns
+	/// `Ok(n)`, which means a message of size `n` has been received and it
+	/// has been placed in `&buffer[0..n]`, or an error.
+	fn read_nidd(&self, cid: u8, buffer: &mut [u8]) -> nb::Result<usize, Self::Error>;
+}
@@ -0,0 +1,50 @@
+use core::time::Duration;
+
+/// A monotonic time source, used by the parts of this crate that need to know
+/// how much time has passed, such as [`CachedDns`]. Timeouts, intervals and
+/// delays are given to them, as throughout the crate, as a
+/// `core::time::Duration`.
+///
+/// [`CachedDns`]: struct.CachedDns.html
+pub trait Clock {
+	/// The number of milliseconds elapsed since an arbitrary, fixed point in
+	/// time. This must never go backwards.
+	fn now_ms(&self) -> u64;
+
+	/// The time elapsed since the same fixed point as
+	/// [`now_ms`](#tymethod.now_ms).
+	fn now(&self) -> Duration {
+		Duration::from_millis(self.now_ms())
+	}
+}
+
+impl<C: Clock> Clock for &C {
+	fn now_ms(&self) -> u64 {
+		C::now_ms(self)
+	}
+}
+
+/// The whole milliseconds of `duration`, saturating at `u64::MAX`.
+pub(crate) fn millis(duration: Duration) -> u64 {
+	let millis = duration.as_millis();
+	if millis > u128::from(core::u64::MAX) {
+		core::u64::MAX
+	} else {
+		millis as u64
+	}
+}
+
+/// A way to pause for a while, such as a busy-wait calibrated to the CPU
+/// clock or a sleep until a hardware timer fires, used by [`Blocking`].
+///
+/// [`Blocking`]: struct.Blocking.html
+pub trait Delay {
+	/// Pause for at least `duration`.
+	fn delay(&mut self, duration: Duration);
+}
+
+impl<D: Delay> Delay for &mut D {
+	fn delay(&mut self, duration: Duration) {
+		D::delay(self, duration)
+	}
+}
@@ -0,0 +1,87 @@
+use crate::{DnsConfig, NetworkType};
+use core::time::Duration;
+use heapless::{consts, String, Vec};
+use no_std_net::IpAddr;
+
+/// A version of the TLS protocol. Versions compare in the order they were
+/// published.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TlsVersion {
+	/// TLS 1.2 ([`rfc5246`])
+	///
+	/// [`rfc5246`]: https://tools.ietf.org/html/rfc5246
+	Tls12,
+	/// TLS 1.3 ([`rfc8446`])
+	///
+	/// [`rfc8446`]: https://tools.ietf.org/html/rfc8446
+	Tls13,
+}
+
+/// A server the device connects to, by hostname and port.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Endpoint {
+	/// The hostname, or an address in text form
+	pub host: String<consts::U256>,
+	/// The port
+	pub port: u16,
+}
+
+impl Endpoint {
+	/// Create an endpoint for port `port` of `host`, or return `None` if
+	/// `host` is longer than 255 bytes.
+	pub fn new(host: &str, port: u16) -> Option<Self> {
+		let mut owned = String::new();
+		owned.push_str(host).ok()?;
+		Some(Endpoint { host: owned, port })
+	}
+}
+
+/// The settings of the networking layer, as found in a provisioning blob, in
+/// one place so that every backend is configured from the same values.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NetConfig {
+	/// The servers to connect to, in order of preference
+	pub endpoints: Vec<Endpoint, consts::U4>,
+	/// The resolver addresses to use, as `(primary, secondary)`, or `None`
+	/// to keep the ones provided by the network
+	pub dns_servers: Option<(IpAddr, Option<IpAddr>)>,
+	/// The oldest TLS version to accept
+	pub tls_min: TlsVersion,
+	/// The newest TLS version to offer
+	pub tls_max: TlsVersion,
+	/// The network the device is on, which determines how often idle
+	/// connections need keepalives
+	pub network: NetworkType,
+}
+
+impl NetConfig {
+	/// A configuration for `network` with no endpoints, the resolvers of the
+	/// network, and TLS 1.2 to 1.3.
+	pub fn new(network: NetworkType) -> Self {
+		NetConfig {
+			endpoints: Vec::new(),
+			dns_servers: None,
+			tls_min: TlsVersion::Tls12,
+			tls_max: TlsVersion::Tls13,
+			network,
+		}
+	}
+
+	/// Whether `version` is within the configured TLS bounds.
+	pub fn allows_tls(&self, version: TlsVersion) -> bool {
+		self.tls_min <= version && version <= self.tls_max
+	}
+
+	/// How long a connection may be idle before it needs a keepalive.
+	pub fn keepalive_interval(&self) -> Duration {
+		self.network.keepalive_interval()
+	}
+
+	/// Set the resolver addresses of `dns`, if any are configured.
+	pub fn apply_dns<D: DnsConfig>(&self, dns: &D) -> Result<(), D::Error> {
+		match self.dns_servers {
+			Some((primary, secondary)) => dns.set_dns_servers(primary, secondary),
+			None => Ok(()),
+		}
+	}
+}
@@ -0,0 +1,264 @@
+use crate::{AddrType, Dns, HostAddr, Mode, TcpStack, UdpStack};
+use no_std_net::{IpAddr, SocketAddr};
+
+/// A UDP flow to a single remote endpoint, as returned by
+/// [`UdpConnect::connect`]. Unlike the sockets of a [`UdpStack`], it carries
+/// everything needed to exchange datagrams, so protocol crates can take it
+/// without also taking the stack.
+///
+/// [`UdpConnect::connect`]: trait.UdpConnect.html#tymethod.connect
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait ConnectedUdp {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Send a datagram to the remote endpoint.
+	fn write(&mut self, buffer: &[u8]) -> nb::Result<(), Self::Error>;
+
+	/// Read a datagram the remote endpoint has sent to us. Returns `Ok(n)`,
+	/// which means a datagram of size `n` has been received and it has been
+	/// placed in `&buffer[0..n]`, or an error.
+	fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error>;
+}
+
+/// This trait is implemented by anything that can hand out UDP flows to a
+/// given endpoint. It lets protocol crates such as SNTP, DNS or CoAP clients
+/// be generic over "give me a UDP flow to this endpoint", without caring how
+/// the flow is provided.
+///
+/// It is implemented for shared references to every [`UdpStack`].
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpConnect {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+	/// The flow returned by [`connect`](#tymethod.connect)
+	type Connection: ConnectedUdp<Error = Self::Error>;
+
+	/// Open a new UDP flow, bound to a local port and connected to `remote`.
+	fn connect(&self, remote: SocketAddr, mode: Mode) -> Result<Self::Connection, Self::Error>;
+}
+
+/// A UDP socket of a [`UdpStack`] together with the stack it belongs to. The
+/// socket is closed when this is dropped, ignoring any error; use
+/// [`close`](#method.close) to observe it.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub struct UdpConnection<'a, S: UdpStack> {
+	stack: &'a S,
+	socket: Option<S::UdpSocket>,
+}
+
+impl<'a, S: UdpStack> UdpConnection<'a, S> {
+	/// Close the underlying socket.
+	pub fn close(mut self) -> Result<(), S::Error> {
+		match self.socket.take() {
+			Some(socket) => self.stack.close(socket),
+			None => Ok(()),
+		}
+	}
+}
+
+impl<'a, S: UdpStack> ConnectedUdp for UdpConnection<'a, S> {
+	type Error = S::Error;
+
+	fn write(&mut self, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		let socket = self.socket.as_mut().expect("socket is only taken on close");
+		self.stack.write(socket, buffer)
+	}
+
+	fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+		let socket = self.socket.as_mut().expect("socket is only taken on close");
+		self.stack.read(socket, buffer)
+	}
+}
+
+impl<'a, S: UdpStack> Drop for UdpConnection<'a, S> {
+	fn drop(&mut self) {
+		if let Some(socket) = self.socket.take() {
+			self.stack.close(socket).ok();
+		}
+	}
+}
+
+impl<'a, S: UdpStack> UdpConnect for &'a S {
+	type Error = S::Error;
+	type Connection = UdpConnection<'a, S>;
+
+	fn connect(&self, remote: SocketAddr, mode: Mode) -> Result<Self::Connection, Self::Error> {
+		let stack = *self;
+		let socket = stack.open(remote, mode)?;
+		Ok(UdpConnection {
+			stack,
+			socket: Some(socket),
+		})
+	}
+}
+
+/// How many addresses of a host [`connect_host`] tries.
+///
+/// [`connect_host`]: fn.connect_host.html
+pub(crate) const MAX_ADDRS: usize = 4;
+
+/// The error type of [`connect_host`].
+///
+/// [`connect_host`]: fn.connect_host.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConnectError<T, D> {
+	/// The hostname could not be resolved
+	Dns(D),
+	/// The resolver found no address for the hostname
+	NoAddress,
+	/// No address of the host could be connected to; this is the error of
+	/// the last attempt, whether it failed to open a socket or to connect
+	Tcp(T),
+}
+
+impl<T: core::fmt::Display, D: core::fmt::Display> core::fmt::Display for ConnectError<T, D> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			ConnectError::Dns(e) => write!(f, "name resolution failed: {}", e),
+			ConnectError::NoAddress => f.write_str("the host has no address"),
+			ConnectError::Tcp(e) => write!(f, "connection failed: {}", e),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<T: std::error::Error + 'static, D: std::error::Error + 'static> std::error::Error
+	for ConnectError<T, D>
+{
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ConnectError::Dns(e) => Some(e),
+			ConnectError::NoAddress => None,
+			ConnectError::Tcp(e) => Some(e),
+		}
+	}
+}
+
+type ConnectResult<S, D> = nb::Result<
+	(<S as TcpStack>::TcpSocket, HostAddr),
+	ConnectError<<S as TcpStack>::Error, <D as Dns>::Error>,
+>;
+
+/// Resolve `hostname` with `dns` and connect a new socket of `stack` to port
+/// `port` of it, trying each of its first few addresses in turn until one
+/// accepts the connection. This is the boilerplate that precedes every
+/// connection to a cloud endpoint.
+///
+/// Returns `WouldBlock` while the name is being resolved; calling it again
+/// with the same arguments keeps waiting for the same query. On success, the
+/// connected socket is returned along with the address and hostname it
+/// connected to, which a TLS handshake can then be started over. The
+/// handshake itself is left to the caller, as this crate has no TLS trait to
+/// drive it with.
+///
+/// An address is skipped if no socket can be opened for it, as well as if
+/// connecting to it fails.
+pub fn connect_host<S, D>(
+	stack: &S,
+	dns: &D,
+	hostname: &str,
+	port: u16,
+	mode: Mode,
+) -> ConnectResult<S, D>
+where
+	S: TcpStack,
+	D: Dns,
+{
+	let mut addrs = [IpAddr::from([0; 4]); MAX_ADDRS];
+	let found = dns
+		.get_hosts_by_name(hostname, AddrType::Either, &mut addrs)
+		.map_err(|e| e.map(ConnectError::Dns))?;
+
+	let mut error = None;
+	for &addr in &addrs[..found] {
+		let socket = match stack.open(mode) {
+			Ok(socket) => socket,
+			Err(e) => {
+				error = Some(e);
+				continue;
+			}
+		};
+		match stack.connect(socket, SocketAddr::new(addr, port)) {
+			Ok(socket) => return Ok((socket, HostAddr::new(addr, Some(hostname)))),
+			Err(e) => error = Some(e),
+		}
+	}
+	match error {
+		Some(e) => Err(nb::Error::Other(ConnectError::Tcp(e))),
+		None => Err(nb::Error::Other(ConnectError::NoAddress)),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Call, Expectation, LoopbackError, LoopbackStack, MockError, MockStack};
+
+	fn addr(last: u8) -> IpAddr {
+		IpAddr::from([192, 0, 2, last])
+	}
+
+	/// A host table giving `example.com` three addresses.
+	fn hosts() -> LoopbackStack {
+		let hosts = LoopbackStack::new();
+		for last in 1..4 {
+			hosts.add_host("example.com", addr(last)).unwrap();
+		}
+		hosts
+	}
+
+	#[test]
+	fn falls_back_across_addresses() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(SocketAddr::new(addr(1), 443))).fail(),
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(SocketAddr::new(addr(2), 443))),
+		];
+		let stack = MockStack::new(&script);
+		let (_, host) = connect_host(&stack, &hosts(), "example.com", 443, Mode::Blocking).unwrap();
+		assert_eq!(host, HostAddr::new(addr(2), Some("example.com")));
+	}
+
+	#[test]
+	fn failed_opens_skip_to_the_next_address() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(SocketAddr::new(addr(1), 443))).fail(),
+			Expectation::new(Call::TcpOpen).fail(),
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(SocketAddr::new(addr(3), 443))),
+		];
+		let stack = MockStack::new(&script);
+		let (_, host) = connect_host(&stack, &hosts(), "example.com", 443, Mode::Blocking).unwrap();
+		assert_eq!(host.ip, addr(3));
+	}
+
+	#[test]
+	fn reports_the_last_failure() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(SocketAddr::new(addr(1), 443))).fail(),
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(SocketAddr::new(addr(2), 443))).fail(),
+			Expectation::new(Call::TcpOpen).fail(),
+		];
+		let stack = MockStack::new(&script);
+		assert_eq!(
+			connect_host(&stack, &hosts(), "example.com", 443, Mode::Blocking).map(|_| ()),
+			Err(nb::Error::Other(ConnectError::Tcp(MockError)))
+		);
+	}
+
+	#[test]
+	fn unknown_hosts_are_a_dns_error() {
+		let stack = MockStack::new(&[]);
+		assert_eq!(
+			connect_host(&stack, &hosts(), "unknown.com", 443, Mode::Blocking).map(|_| ()),
+			Err(nb::Error::Other(ConnectError::Dns(LoopbackError::NxDomain)))
+		);
+	}
+}
@@ -1,7 +1,22 @@
+use crate::Protocol;
 use heapless::{consts, String};
-use no_std_net::IpAddr;
+use no_std_net::{IpAddr, SocketAddr};
 
-/// This is the host address type to be returned by `gethostbyname`.
+mod cache;
+mod doh;
+mod dual_stack;
+mod llmnr;
+mod message;
+mod retry;
+mod validate;
+pub use cache::{CacheEntry, CacheError, CachedDns};
+pub use doh::{DohError, DohResolver, DOH_MAX_RESPONSE};
+pub use dual_stack::{resolve_dual_stack, AddrPreference, DualStackQuery};
+pub use llmnr::{LlmnrFallback, LlmnrFallbackError};
+pub use retry::{RetryError, RetryPolicy, RetryingDns};
+pub use validate::{validate_hostname, HostnameError, ValidatingDns, ValidatingDnsError};
+
+/// This is the host address type to be returned by `get_host_by_name`.
 ///
 /// An IPv4 address type always looks for `A` records, while IPv6 address type
 /// will look for `AAAA` records
@@ -15,23 +30,196 @@ pub enum AddrType {
 	Either,
 }
 
+/// A resolved address, together with the hostname it was resolved from, as
+/// returned by [`Dns::get_host_addr_by_name`].
+///
+/// Keeping the name around matters when connecting with TLS, which needs it
+/// for SNI and certificate verification.
+///
+/// [`Dns::get_host_addr_by_name`]: trait.Dns.html#method.get_host_addr_by_name
+#[derive(Clone, Debug, PartialEq)]
+pub struct HostAddr {
+	/// The resolved address
+	pub ip: IpAddr,
+	/// The name that was resolved, if known and no longer than 255 bytes
+	pub hostname: Option<String<consts::U256>>,
+}
+
+impl HostAddr {
+	/// Create a host address for `ip`, resolved from `hostname`. The hostname
+	/// is dropped if it is longer than 255 bytes.
+	pub fn new(ip: IpAddr, hostname: Option<&str>) -> Self {
+		HostAddr {
+			ip,
+			hostname: hostname.and_then(|name| {
+				let mut owned = String::new();
+				owned.push_str(name).ok().map(|_| owned)
+			}),
+		}
+	}
+}
+
+impl From<IpAddr> for HostAddr {
+	fn from(ip: IpAddr) -> Self {
+		HostAddr { ip, hostname: None }
+	}
+}
+
+impl From<HostAddr> for IpAddr {
+	fn from(addr: HostAddr) -> Self {
+		addr.ip
+	}
+}
+
+/// The broad categories of resolution errors, see [`DnsError`].
+///
+/// [`DnsError`]: trait.DnsError.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DnsErrorKind {
+	/// The name does not exist (`NXDOMAIN`)
+	NxDomain,
+	/// No answer was received in time, which usually means the network or the
+	/// resolver is unreachable
+	Timeout,
+	/// The resolver failed to answer the query (`SERVFAIL`, `REFUSED`, ...)
+	ServerFailure,
+	/// The name is longer than a domain name may be
+	NameTooLong,
+	/// The name is not a well formed domain name, see
+	/// [`validate_hostname`](fn.validate_hostname.html)
+	InvalidName,
+	/// The name exists, but has no record of the requested type
+	NoRecordOfType,
+	/// Any other error
+	Other,
+}
+
+impl core::fmt::Display for DnsErrorKind {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			DnsErrorKind::NxDomain => f.write_str("the name does not exist"),
+			DnsErrorKind::Timeout => f.write_str("the resolver did not answer in time"),
+			DnsErrorKind::ServerFailure => f.write_str("the resolver failed to answer"),
+			DnsErrorKind::NameTooLong => f.write_str("the name is too long"),
+			DnsErrorKind::InvalidName => f.write_str("the name is not a valid domain name"),
+			DnsErrorKind::NoRecordOfType => {
+				f.write_str("the name has no record of the requested type")
+			}
+			DnsErrorKind::Other => f.write_str("name resolution failed"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DnsErrorKind {}
+
+/// This trait is implemented by the error types of [`Dns`] implementations,
+/// so that applications can tell a name that doesn't exist from a network
+/// that is down, whichever stack they run on.
+///
+/// Implementations that have nothing more to report can use
+/// [`DnsErrorKind`] itself as their error type.
+///
+/// [`Dns`]: trait.Dns.html
+/// [`DnsErrorKind`]: enum.DnsErrorKind.html
+pub trait DnsError: core::fmt::Debug {
+	/// The category of this error
+	fn kind(&self) -> DnsErrorKind;
+}
+
+impl DnsError for DnsErrorKind {
+	fn kind(&self) -> DnsErrorKind {
+		*self
+	}
+}
+
 /// This trait is an extension trait for [`TcpStack`] and [`UdpStack`] for dns
 /// resolutions. It does not handle every DNS record type, but is meant as an
 /// embedded alternative to [`ToSocketAddrs`], and is as such meant to resolve
 /// an ip address from a hostname, or a hostname from an ip address. This means
 /// that it only deals in host address records `A` (IPv4) and `AAAA` (IPv6).
 ///
+/// Resolution often involves a network round trip, which on modems can take
+/// several seconds. The methods therefore return `nb::Result`: non-blocking
+/// implementations start the query on the first call and return
+/// `WouldBlock` until the answer is in, while blocking implementations just
+/// never return `WouldBlock`.
+///
 /// [`TcpStack`]: trait.TcpStack.html
 /// [`UdpStack`]: trait.UdpStack.html
 /// [`ToSocketAddrs`]:
 /// https://doc.rust-lang.org/std/net/trait.ToSocketAddrs.html
 pub trait Dns {
 	/// The type returned when we have an error
-	type Error: core::fmt::Debug;
+	type Error: DnsError;
 
 	/// Resolve the first ip address of a host, given its hostname and a desired
 	/// address record type to look for
-	fn gethostbyname(&self, hostname: &str, addr_type: AddrType) -> Result<IpAddr, Self::Error>;
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error>;
+
+	/// Resolve the first ip address of a host, like
+	/// [`get_host_by_name`](#tymethod.get_host_by_name), along with the
+	/// remaining time to live of the record in seconds, if known.
+	///
+	/// The default implementation does not report a TTL.
+	fn get_host_by_name_with_ttl(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<(IpAddr, Option<u32>), Self::Error> {
+		self.get_host_by_name(hostname, addr_type)
+			.map(|addr| (addr, None))
+	}
+
+	/// Resolve the first ip address of a host, like
+	/// [`get_host_by_name`](#tymethod.get_host_by_name), keeping the queried
+	/// hostname alongside it.
+	///
+	/// Prefer this when the address is going to be used for a TLS connection,
+	/// so that the name is still at hand for SNI and hostname verification.
+	fn get_host_addr_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<HostAddr, Self::Error> {
+		self.get_host_by_name(hostname, addr_type)
+			.map(|ip| HostAddr::new(ip, Some(hostname)))
+	}
+
+	/// Resolve all ip addresses of a host, given its hostname and a desired
+	/// address record type to look for. Returns `Ok(n)`, which means `n`
+	/// addresses have been placed in `&out[0..n]`. If the host has more
+	/// addresses than fit in `out`, only the first `out.len()` are returned.
+	///
+	/// This lets callers fail over to the next address when one is
+	/// unreachable. The default implementation only returns the single
+	/// address found by [`get_host_by_name`](#tymethod.get_host_by_name).
+	///
+	/// The results are plain `IpAddr`s rather than [`HostAddr`]s: they were
+	/// all resolved from `hostname`, so a copy of it in every entry, of up to
+	/// 256 bytes each, would only make `out` larger. Pair an address with the
+	/// name with [`HostAddr::new`] where it is needed, such as for TLS.
+	///
+	/// [`HostAddr`]: struct.HostAddr.html
+	/// [`HostAddr::new`]: struct.HostAddr.html#method.new
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		match out.first_mut() {
+			Some(first) => {
+				*first = self.get_host_by_name(hostname, addr_type)?;
+				Ok(1)
+			}
+			None => Ok(0),
+		}
+	}
 
 	/// Resolve the hostname of a host, given its ip address
 	///
@@ -39,5 +227,199 @@ pub trait Dns {
 	/// 255 bytes [`rfc1035`]
 	///
 	/// [`rfc1035`]: https://tools.ietf.org/html/rfc1035
-	fn gethostbyaddr(&self, addr: IpAddr) -> Result<String<consts::U256>, Self::Error>;
+	fn get_host_by_address(&self, addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error>;
+}
+
+/// This trait is an extension trait for [`Dns`] for stacks whose resolver
+/// addresses can be changed at runtime. Devices on private APNs or local
+/// networks often need to override the servers provided by DHCP or the
+/// network.
+///
+/// [`Dns`]: trait.Dns.html
+pub trait DnsConfig: Dns {
+	/// Use `primary`, and `secondary` if `primary` fails, as the resolver
+	/// addresses for all subsequent queries.
+	fn set_dns_servers(
+		&self,
+		primary: IpAddr,
+		secondary: Option<IpAddr>,
+	) -> Result<(), Self::Error>;
+
+	/// Get the resolver addresses currently in use, as `(primary, secondary)`.
+	fn dns_servers(&self) -> Result<(IpAddr, Option<IpAddr>), Self::Error>;
+}
+
+/// A service location record (`SRV`, [`rfc2782`]), as returned by
+/// [`DnsRecords::get_srv_records`].
+///
+/// [`rfc2782`]: https://tools.ietf.org/html/rfc2782
+/// [`DnsRecords::get_srv_records`]: trait.DnsRecords.html#tymethod.get_srv_records
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SrvRecord {
+	/// Priority of the target host; lower values are tried first
+	pub priority: u16,
+	/// Relative weight among targets with the same priority
+	pub weight: u16,
+	/// Port of the service on the target host
+	pub port: u16,
+	/// Hostname of the target host
+	pub target: String<consts::U256>,
+}
+
+/// An iterator over the `TXT` records written to a buffer by
+/// [`DnsRecords::get_txt_records`]. Each record is itself an iterator over
+/// its character-strings.
+///
+/// [`DnsRecords::get_txt_records`]: trait.DnsRecords.html#tymethod.get_txt_records
+#[derive(Clone, Debug)]
+pub struct TxtRecords<'a> {
+	buffer: &'a [u8],
+}
+
+impl<'a> TxtRecords<'a> {
+	/// Iterate over the records in `buffer`, which must be the part of the
+	/// buffer that has been filled in by `get_txt_records`.
+	pub fn new(buffer: &'a [u8]) -> Self {
+		TxtRecords { buffer }
+	}
+}
+
+impl<'a> Iterator for TxtRecords<'a> {
+	type Item = TxtStrings<'a>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.buffer.len() < 2 {
+			return None;
+		}
+		let len = usize::from(self.buffer[0]) << 8 | usize::from(self.buffer[1]);
+		let end = core::cmp::min(2 + len, self.buffer.len());
+		let record = &self.buffer[2..end];
+		self.buffer = &self.buffer[end..];
+		Some(TxtStrings { buffer: record })
+	}
+}
+
+/// An iterator over the character-strings of one `TXT` record, see
+/// [`TxtRecords`].
+///
+/// [`TxtRecords`]: struct.TxtRecords.html
+#[derive(Clone, Debug)]
+pub struct TxtStrings<'a> {
+	buffer: &'a [u8],
+}
+
+impl<'a> Iterator for TxtStrings<'a> {
+	type Item = &'a [u8];
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let (&len, rest) = self.buffer.split_first()?;
+		let end = core::cmp::min(usize::from(len), rest.len());
+		let string = &rest[..end];
+		self.buffer = &rest[end..];
+		Some(string)
+	}
+}
+
+/// This trait is an extension trait for [`Dns`] for resolvers that can look
+/// up records other than host addresses.
+///
+/// [`Dns`]: trait.Dns.html
+pub trait DnsRecords: Dns {
+	/// Look up the `SRV` records of `_service._proto.name`, to discover which
+	/// hosts and ports provide a service such as MQTT or SIP for a domain.
+	/// `service` is given without its leading underscore, such as `mqtt`.
+	/// Returns `Ok(n)`, which means `n` records have been placed in
+	/// `&out[0..n]`, in no particular order.
+	fn get_srv_records(
+		&self,
+		service: &str,
+		protocol: Protocol,
+		name: &str,
+		out: &mut [SrvRecord],
+	) -> nb::Result<usize, Self::Error>;
+
+	/// Look up the `TXT` records of `name`. Returns `Ok(n)`, which means the
+	/// records have been written to `&buffer[0..n]`, where they can be read
+	/// back with [`TxtRecords`].
+	///
+	/// Each record is written as its length, as two big endian bytes,
+	/// followed by its data in wire format: a sequence of character-strings,
+	/// each a length byte followed by that many bytes. Records that don't fit
+	/// in `buffer` are left out.
+	///
+	/// [`TxtRecords`]: struct.TxtRecords.html
+	fn get_txt_records(&self, name: &str, buffer: &mut [u8]) -> nb::Result<usize, Self::Error>;
+}
+
+/// How a resolver should use DNS over TLS, see [`DnsOverTls`].
+///
+/// [`DnsOverTls`]: trait.DnsOverTls.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DnsPrivacy {
+	/// Send queries in the clear
+	Off,
+	/// Use TLS when the server supports it, and fall back to clear text
+	/// otherwise
+	Opportunistic,
+	/// Only ever send queries over an authenticated TLS connection, and fail
+	/// them when none can be established
+	Strict,
+}
+
+/// This trait is an extension trait for [`Dns`] for resolvers that can send
+/// their queries over TLS ([`rfc7858`]), keeping name resolution private and
+/// tamper-resistant on hostile networks.
+///
+/// [`Dns`]: trait.Dns.html
+/// [`rfc7858`]: https://tools.ietf.org/html/rfc7858
+pub trait DnsOverTls: Dns {
+	/// Send all subsequent queries to `server`, usually on port 853, using
+	/// the given privacy mode. In [`DnsPrivacy::Strict`] mode, the server's
+	/// certificate must be valid for `auth_name`, such as `dns.google`.
+	///
+	/// [`DnsPrivacy::Strict`]: enum.DnsPrivacy.html#variant.Strict
+	fn set_tls_server(
+		&self,
+		server: SocketAddr,
+		auth_name: Option<&str>,
+		privacy: DnsPrivacy,
+	) -> Result<(), Self::Error>;
+}
+
+/// Whether an answer was validated with DNSSEC, see [`Dnssec`].
+///
+/// [`Dnssec`]: trait.Dnssec.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DnssecStatus {
+	/// The resolver validated the answer's signatures
+	Authenticated,
+	/// The answer was not validated, either because the zone isn't signed or
+	/// because validation is disabled or unsupported
+	Unauthenticated,
+}
+
+/// This trait is an extension trait for [`Dns`] for resolvers that can have
+/// answers validated with DNSSEC ([`rfc4033`]), so that security-sensitive
+/// endpoints can refuse to connect unless the address they resolved is known
+/// to be genuine.
+///
+/// Validation is done either by the resolver itself or by the upstream
+/// server, in which case the status is only as trustworthy as the channel to
+/// that server.
+///
+/// [`Dns`]: trait.Dns.html
+/// [`rfc4033`]: https://tools.ietf.org/html/rfc4033
+pub trait Dnssec: Dns {
+	/// Enable or disable DNSSEC validation of subsequent queries.
+	fn set_dnssec_validation(&self, enabled: bool) -> Result<(), Self::Error>;
+
+	/// Resolve `hostname` like [`get_host_by_name`], also reporting whether the
+	/// answer was validated.
+	///
+	/// [`get_host_by_name`]: trait.Dns.html#tymethod.get_host_by_name
+	fn get_host_by_name_validated(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<(IpAddr, DnssecStatus), Self::Error>;
 }
@@ -0,0 +1,379 @@
+use crate::{AddrType, Clock, Dns, DnsError, DnsErrorKind};
+use core::cell::RefCell;
+use heapless::{consts, ArrayLength, String, Vec};
+use no_std_net::IpAddr;
+
+/// One resolved host remembered by a [`CachedDns`]. This is only public so
+/// that the capacity of the cache can be named, as in
+/// `CachedDns<D, C, consts::U4>`.
+///
+/// [`CachedDns`]: struct.CachedDns.html
+pub struct CacheEntry {
+	hostname: String<consts::U256>,
+	addr_type: AddrType,
+	/// `None` for a name which is known not to exist
+	addr: Option<IpAddr>,
+	expires_at: u64,
+	last_used: u64,
+}
+
+/// The error type of a [`CachedDns`].
+///
+/// [`CachedDns`]: struct.CachedDns.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum CacheError<E> {
+	/// The name does not exist, as answered by the wrapped resolver with an
+	/// error of kind `NxDomain`, or remembered from such an earlier answer
+	NxDomain,
+	/// The wrapped resolver failed
+	Dns(E),
+}
+
+impl<E: DnsError> DnsError for CacheError<E> {
+	fn kind(&self) -> DnsErrorKind {
+		match self {
+			CacheError::NxDomain => DnsErrorKind::NxDomain,
+			CacheError::Dns(e) => e.kind(),
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for CacheError<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			CacheError::NxDomain => f.write_str("the name does not exist"),
+			CacheError::Dns(e) => write!(f, "name resolution failed: {}", e),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for CacheError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			CacheError::NxDomain => None,
+			CacheError::Dns(e) => Some(e),
+		}
+	}
+}
+
+/// A [`Dns`] wrapper that remembers up to `N` resolved hosts for as long as
+/// their records' TTL allows, so that repeated connections to the same host
+/// don't each cost a query. When the cache is full, the least recently used
+/// host is forgotten.
+///
+/// Names that don't exist can be remembered too, see
+/// [`set_negative_ttl`](#method.set_negative_ttl), so that firmware retrying
+/// a misconfigured endpoint doesn't keep the resolver and the radio busy.
+///
+/// Only [`get_host_by_name`] is cached; other queries are passed through.
+///
+/// [`Dns`]: trait.Dns.html
+/// [`get_host_by_name`]: trait.Dns.html#tymethod.get_host_by_name
+pub struct CachedDns<D, C, N>
+where
+	N: ArrayLength<CacheEntry>,
+{
+	dns: D,
+	clock: C,
+	default_ttl: Option<u32>,
+	negative_ttl: Option<u32>,
+	entries: RefCell<Vec<CacheEntry, N>>,
+}
+
+fn wrap<T, E>(result: nb::Result<T, E>) -> nb::Result<T, CacheError<E>> {
+	result.map_err(|e| e.map(CacheError::Dns))
+}
+
+impl<D, C, N> CachedDns<D, C, N>
+where
+	D: Dns,
+	C: Clock,
+	N: ArrayLength<CacheEntry>,
+{
+	/// Wrap `dns`, using `clock` to expire entries.
+	pub fn new(dns: D, clock: C) -> Self {
+		CachedDns {
+			dns,
+			clock,
+			default_ttl: None,
+			negative_ttl: None,
+			entries: RefCell::new(Vec::new()),
+		}
+	}
+
+	/// Set the TTL, in seconds, used for answers the wrapped resolver does
+	/// not report a TTL for. By default such answers are not cached.
+	pub fn set_default_ttl(&mut self, ttl: Option<u32>) {
+		self.default_ttl = ttl;
+	}
+
+	/// Set how long, in seconds, to remember that a name does not exist,
+	/// after the wrapped resolver answered with an error of kind
+	/// [`DnsErrorKind::NxDomain`]. While remembered, queries for the name
+	/// fail with [`CacheError::NxDomain`], as the first one did. By default
+	/// such answers are not cached.
+	///
+	/// [`DnsErrorKind::NxDomain`]: enum.DnsErrorKind.html#variant.NxDomain
+	/// [`CacheError::NxDomain`]: enum.CacheError.html#variant.NxDomain
+	pub fn set_negative_ttl(&mut self, ttl: Option<u32>) {
+		self.negative_ttl = ttl;
+	}
+
+	/// Forget every cached host.
+	pub fn clear(&self) {
+		// `Vec::clear` indexes past the end in this version of heapless.
+		let mut entries = self.entries.borrow_mut();
+		while entries.pop().is_some() {}
+	}
+
+	/// Get a reference to the wrapped resolver.
+	pub fn inner(&self) -> &D {
+		&self.dns
+	}
+
+	/// Destroy the cache, returning the wrapped resolver.
+	pub fn into_inner(self) -> D {
+		self.dns
+	}
+
+	fn lookup(
+		&self,
+		hostname: &str,
+		addr_type: &AddrType,
+		now: u64,
+	) -> Option<(Option<IpAddr>, u32)> {
+		let mut entries = self.entries.borrow_mut();
+		let entry = entries.iter_mut().find(|e| {
+			e.hostname.eq_ignore_ascii_case(hostname)
+				&& e.addr_type == *addr_type
+				&& e.expires_at > now
+		})?;
+		entry.last_used = now;
+		let remaining = (entry.expires_at - now) / 1000;
+		Some((entry.addr, remaining as u32))
+	}
+
+	fn insert(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		addr: Option<IpAddr>,
+		ttl: u32,
+		now: u64,
+	) {
+		let mut name = String::new();
+		if ttl == 0 || name.push_str(hostname).is_err() {
+			return;
+		}
+		let entry = CacheEntry {
+			hostname: name,
+			addr_type,
+			addr,
+			expires_at: now + u64::from(ttl) * 1000,
+			last_used: now,
+		};
+
+		let mut entries = self.entries.borrow_mut();
+		// Replace a stale entry for the same host, then an expired entry, and
+		// only evict the least recently used entry if the cache is full.
+		let slot = entries
+			.iter()
+			.position(|e| {
+				e.hostname.eq_ignore_ascii_case(hostname) && e.addr_type == entry.addr_type
+			})
+			.or_else(|| entries.iter().position(|e| e.expires_at <= now));
+		if let Some(slot) = slot {
+			entries[slot] = entry;
+		} else if let Err(entry) = entries.push(entry) {
+			if let Some(lru) = entries.iter_mut().min_by_key(|e| e.last_used) {
+				*lru = entry;
+			}
+		}
+	}
+}
+
+impl<D, C, N> Dns for CachedDns<D, C, N>
+where
+	D: Dns,
+	C: Clock,
+	N: ArrayLength<CacheEntry>,
+{
+	type Error = CacheError<D::Error>;
+
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error> {
+		self.get_host_by_name_with_ttl(hostname, addr_type)
+			.map(|(addr, _)| addr)
+	}
+
+	fn get_host_by_name_with_ttl(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<(IpAddr, Option<u32>), Self::Error> {
+		let now = self.clock.now_ms();
+		match self.lookup(hostname, &addr_type, now) {
+			Some((Some(addr), ttl)) => return Ok((addr, Some(ttl))),
+			Some((None, _)) => return Err(nb::Error::Other(CacheError::NxDomain)),
+			None => {}
+		}
+
+		match self
+			.dns
+			.get_host_by_name_with_ttl(hostname, addr_type.clone())
+		{
+			Ok((addr, ttl)) => {
+				if let Some(ttl) = ttl.or(self.default_ttl) {
+					self.insert(hostname, addr_type, Some(addr), ttl, now);
+				}
+				Ok((addr, ttl))
+			}
+			Err(nb::Error::Other(e)) => {
+				if e.kind() != DnsErrorKind::NxDomain {
+					return Err(nb::Error::Other(CacheError::Dns(e)));
+				}
+				if let Some(ttl) = self.negative_ttl {
+					self.insert(hostname, addr_type, None, ttl, now);
+				}
+				Err(nb::Error::Other(CacheError::NxDomain))
+			}
+			Err(nb::Error::WouldBlock) => Err(nb::Error::WouldBlock),
+		}
+	}
+
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		wrap(self.dns.get_hosts_by_name(hostname, addr_type, out))
+	}
+
+	fn get_host_by_address(&self, addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error> {
+		wrap(self.dns.get_host_by_address(addr))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::LoopbackStack;
+	use core::cell::Cell;
+
+	struct Manual(Cell<u64>);
+
+	impl Clock for Manual {
+		fn now_ms(&self) -> u64 {
+			self.0.get()
+		}
+	}
+
+	/// A resolver over a [`LoopbackStack`] host table that reports a TTL of
+	/// 60 seconds and counts its queries.
+	struct Counting {
+		hosts: LoopbackStack,
+		queries: Cell<usize>,
+	}
+
+	impl Dns for Counting {
+		type Error = DnsErrorKind;
+
+		fn get_host_by_name(
+			&self,
+			hostname: &str,
+			addr_type: AddrType,
+		) -> nb::Result<IpAddr, Self::Error> {
+			self.get_host_by_name_with_ttl(hostname, addr_type)
+				.map(|(addr, _)| addr)
+		}
+
+		fn get_host_by_name_with_ttl(
+			&self,
+			hostname: &str,
+			addr_type: AddrType,
+		) -> nb::Result<(IpAddr, Option<u32>), Self::Error> {
+			self.queries.set(self.queries.get() + 1);
+			self.hosts
+				.get_host_by_name(hostname, addr_type)
+				.map(|addr| (addr, Some(60)))
+				.map_err(|e| e.map(|e| DnsError::kind(&e)))
+		}
+
+		fn get_host_by_address(
+			&self,
+			_addr: IpAddr,
+		) -> nb::Result<String<consts::U256>, Self::Error> {
+			Err(nb::Error::Other(DnsErrorKind::Other))
+		}
+	}
+
+	fn resolver() -> Counting {
+		let hosts = LoopbackStack::new();
+		hosts
+			.add_host("example.com", IpAddr::from([10, 0, 0, 1]))
+			.unwrap();
+		Counting {
+			hosts,
+			queries: Cell::new(0),
+		}
+	}
+
+	#[test]
+	fn hits_ignore_case_and_expire() {
+		let clock = Manual(Cell::new(0));
+		let cache: CachedDns<_, _, consts::U2> = CachedDns::new(resolver(), &clock);
+		let addr = IpAddr::from([10, 0, 0, 1]);
+		assert_eq!(
+			cache.get_host_by_name_with_ttl("example.com", AddrType::IPv4),
+			Ok((addr, Some(60)))
+		);
+		clock.0.set(20_000);
+		assert_eq!(
+			cache.get_host_by_name_with_ttl("EXAMPLE.com", AddrType::IPv4),
+			Ok((addr, Some(40)))
+		);
+		assert_eq!(cache.inner().queries.get(), 1);
+
+		clock.0.set(60_000);
+		assert_eq!(
+			cache.get_host_by_name("example.com", AddrType::IPv4),
+			Ok(addr)
+		);
+		assert_eq!(cache.inner().queries.get(), 2);
+		cache.clear();
+		assert_eq!(
+			cache.get_host_by_name("example.com", AddrType::IPv4),
+			Ok(addr)
+		);
+		assert_eq!(cache.inner().queries.get(), 3);
+	}
+
+	#[test]
+	fn nxdomain_is_reported_the_same_way_when_cached() {
+		let clock = Manual(Cell::new(0));
+		let mut cache: CachedDns<_, _, consts::U2> = CachedDns::new(resolver(), &clock);
+		cache.set_negative_ttl(Some(30));
+		for _ in 0..2 {
+			assert_eq!(
+				cache.get_host_by_name("unknown.com", AddrType::IPv4),
+				Err(nb::Error::Other(CacheError::NxDomain))
+			);
+		}
+		assert_eq!(cache.inner().queries.get(), 1);
+
+		// Other errors are passed through, and not cached.
+		assert_eq!(
+			cache.get_host_by_name("example.com", AddrType::IPv6),
+			Err(nb::Error::Other(CacheError::Dns(
+				DnsErrorKind::NoRecordOfType
+			)))
+		);
+		cache.get_host_by_name("example.com", AddrType::IPv6).ok();
+		assert_eq!(cache.inner().queries.get(), 3);
+	}
+}
@@ -0,0 +1,439 @@
+use super::message::{self, MessageError, Record, TYPE_A, TYPE_AAAA, TYPE_PTR};
+use crate::{AddrType, Dns, DnsError, DnsErrorKind, Dnssec, DnssecStatus};
+use core::cell::{Cell, RefCell};
+use heapless::{consts, String};
+use no_std_net::IpAddr;
+
+/// The largest response a [`DohResolver`] accepts.
+///
+/// [`DohResolver`]: struct.DohResolver.html
+pub const DOH_MAX_RESPONSE: usize = 512;
+
+/// Header, longest possible name, type and class.
+const MAX_QUERY: usize = 12 + 255 + 4;
+
+/// The error type of a [`DohResolver`].
+///
+/// [`DohResolver`]: struct.DohResolver.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum DohError<E> {
+	/// The HTTPS request failed
+	Request(E),
+	/// The hostname is not a valid domain name
+	InvalidHostname,
+	/// The response is not a valid DNS message, or is larger than
+	/// [`DOH_MAX_RESPONSE`] bytes
+	///
+	/// [`DOH_MAX_RESPONSE`]: constant.DOH_MAX_RESPONSE.html
+	Malformed,
+	/// The name does not exist (`NXDOMAIN`)
+	NameError,
+	/// The server failed to answer, with the given DNS response code
+	ServerFailure(u8),
+	/// The name exists, but has no record of the requested type
+	NoRecord,
+}
+
+impl<E: core::fmt::Debug> DnsError for DohError<E> {
+	fn kind(&self) -> DnsErrorKind {
+		match self {
+			DohError::NameError => DnsErrorKind::NxDomain,
+			DohError::ServerFailure(_) => DnsErrorKind::ServerFailure,
+			DohError::NoRecord => DnsErrorKind::NoRecordOfType,
+			DohError::InvalidHostname => DnsErrorKind::InvalidName,
+			DohError::Request(_) | DohError::Malformed => DnsErrorKind::Other,
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for DohError<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			DohError::Request(e) => write!(f, "the HTTPS request failed: {}", e),
+			DohError::InvalidHostname => f.write_str("the hostname is not a valid domain name"),
+			DohError::Malformed => f.write_str("the response is not a valid DNS message"),
+			DohError::NameError => f.write_str("the name does not exist"),
+			DohError::ServerFailure(code) => write!(
+				f,
+				"the server failed to answer, with response code {}",
+				code
+			),
+			DohError::NoRecord => f.write_str("the name has no record of the requested type"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for DohError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			DohError::Request(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl<E> From<MessageError> for DohError<E> {
+	fn from(e: MessageError) -> Self {
+		match e {
+			MessageError::InvalidName => DohError::InvalidHostname,
+			MessageError::BufferTooSmall | MessageError::Malformed => DohError::Malformed,
+			MessageError::NameError => DohError::NameError,
+			MessageError::ServerFailure(rcode) => DohError::ServerFailure(rcode),
+		}
+	}
+}
+
+/// A [`Dns`] implementation that resolves names with DNS over HTTPS
+/// ([`rfc8484`]), for deployments that only allow HTTPS traffic out.
+///
+/// The resolver only deals with DNS messages; sending them is left to the
+/// `request` function it is created with, which is typically built on an
+/// HTTP client and TLS stack. It is called with a DNS query and must send it
+/// as the body of a `POST` request, with content type
+/// `application/dns-message`, to the DoH endpoint (such as
+/// `https://dns.example/dns-query`). It then writes the body of the response
+/// into the given buffer and returns its length.
+///
+/// The request function may return `WouldBlock` while the request is in
+/// flight, in which case it is called again with the same query. When
+/// resolving with [`AddrType::Either`], an `AAAA` query is only sent once the
+/// `A` query has turned up no address or failed, and later lookups of the
+/// same name resume with the `AAAA` query until it completes. A lookup of
+/// another name in between starts that one over.
+///
+/// With [`Dnssec`] validation enabled, queries ask the DoH server to report
+/// whether it validated the answer. The HTTPS channel is what makes that
+/// report trustworthy, so the endpoint's certificate must be verified.
+///
+/// [`Dns`]: trait.Dns.html
+/// [`Dnssec`]: trait.Dnssec.html
+/// [`rfc8484`]: https://tools.ietf.org/html/rfc8484
+/// [`AddrType::Either`]: enum.AddrType.html#variant.Either
+pub struct DohResolver<F, E> {
+	request: F,
+	validate: Cell<bool>,
+	/// The `AddrType::Either` lookup whose `AAAA` query is in flight
+	pending: RefCell<Option<PendingAaaa<E>>>,
+}
+
+/// An `AddrType::Either` lookup whose `A` query turned up no address.
+struct PendingAaaa<E> {
+	hostname: String<consts::U256>,
+	/// How the `A` query failed, if it did rather than finding no record,
+	/// reported if the `AAAA` query finds no record either
+	a_error: Option<DohError<E>>,
+}
+
+impl<F, E> DohResolver<F, E>
+where
+	F: Fn(&[u8], &mut [u8]) -> nb::Result<usize, E>,
+	E: core::fmt::Debug,
+{
+	/// Create a resolver which sends its queries with `request`.
+	pub fn new(request: F) -> Self {
+		DohResolver {
+			request,
+			validate: Cell::new(false),
+			pending: RefCell::new(None),
+		}
+	}
+
+	/// Send a query of the given type for `name`, and call `f` with every
+	/// record of the answer, returning whether the answer was validated.
+	fn query<G>(&self, name: &str, qtype: u16, mut f: G) -> nb::Result<bool, DohError<E>>
+	where
+		G: FnMut(&[u8], Record),
+	{
+		let mut query = [0; MAX_QUERY];
+		let len = message::encode_query(&mut query, name, qtype, self.validate.get())
+			.map_err(DohError::from)?;
+		let mut response = [0; DOH_MAX_RESPONSE];
+		let response_len = (self.request)(&query[..len], &mut response).map_err(|e| match e {
+			nb::Error::WouldBlock => nb::Error::WouldBlock,
+			nb::Error::Other(e) => nb::Error::Other(DohError::Request(e)),
+		})?;
+		let response = response.get(..response_len).ok_or(DohError::Malformed)?;
+		let authenticated = message::for_each_answer(response, |record| f(response, record))
+			.map_err(DohError::from)?;
+		Ok(authenticated && self.validate.get())
+	}
+
+	/// Collect the addresses of type `qtype` of `hostname` into `out`,
+	/// returning how many there are, the lowest TTL among them and whether
+	/// they were validated.
+	fn query_type(
+		&self,
+		hostname: &str,
+		qtype: u16,
+		out: &mut [IpAddr],
+	) -> nb::Result<(usize, u32, bool), DohError<E>> {
+		let mut found = 0;
+		let mut ttl = core::u32::MAX;
+		let authenticated = self.query(hostname, qtype, |_, record| {
+			if record.rtype != qtype {
+				return;
+			}
+			if let (Some(addr), Some(slot)) = (message::record_addr(&record), out.get_mut(found)) {
+				*slot = addr;
+				found += 1;
+				ttl = core::cmp::min(ttl, record.ttl);
+			}
+		})?;
+		if found == 0 {
+			return Err(nb::Error::Other(DohError::NoRecord));
+		}
+		Ok((found, ttl, authenticated))
+	}
+
+	/// Collect the addresses of `hostname` into `out`, returning how many
+	/// there are, the lowest TTL among them and whether they were validated.
+	fn query_addrs(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<(usize, u32, bool), DohError<E>> {
+		if out.is_empty() {
+			return Ok((0, 0, false));
+		}
+		match addr_type {
+			AddrType::IPv4 => return self.query_type(hostname, TYPE_A, out),
+			AddrType::IPv6 => return self.query_type(hostname, TYPE_AAAA, out),
+			AddrType::Either => {}
+		}
+		let resuming = match *self.pending.borrow() {
+			Some(ref pending) => pending.hostname == hostname,
+			None => false,
+		};
+		if !resuming {
+			let a_error = match self.query_type(hostname, TYPE_A, out) {
+				Err(nb::Error::Other(DohError::NoRecord)) => None,
+				// Neither stops the `AAAA` query from finding an address.
+				Err(nb::Error::Other(e @ DohError::Request(_)))
+				| Err(nb::Error::Other(e @ DohError::Malformed))
+				| Err(nb::Error::Other(e @ DohError::ServerFailure(_))) => Some(e),
+				result => return result,
+			};
+			let mut name = String::new();
+			// Names that could be queried are no longer than this.
+			name.push_str(hostname).ok();
+			*self.pending.borrow_mut() = Some(PendingAaaa {
+				hostname: name,
+				a_error,
+			});
+		}
+		let result = self.query_type(hostname, TYPE_AAAA, out);
+		if let Err(nb::Error::WouldBlock) = result {
+			return result;
+		}
+		let a_error = self.pending.borrow_mut().take().and_then(|p| p.a_error);
+		match (result, a_error) {
+			(Err(nb::Error::Other(DohError::NoRecord)), Some(e)) => Err(nb::Error::Other(e)),
+			(result, _) => result,
+		}
+	}
+}
+
+impl<F, E> Dns for DohResolver<F, E>
+where
+	F: Fn(&[u8], &mut [u8]) -> nb::Result<usize, E>,
+	E: core::fmt::Debug,
+{
+	type Error = DohError<E>;
+
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error> {
+		self.get_host_by_name_with_ttl(hostname, addr_type)
+			.map(|(addr, _)| addr)
+	}
+
+	fn get_host_by_name_with_ttl(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<(IpAddr, Option<u32>), Self::Error> {
+		let mut addr = [IpAddr::from([0; 4])];
+		let (_, ttl, _) = self.query_addrs(hostname, addr_type, &mut addr)?;
+		Ok((addr[0], Some(ttl)))
+	}
+
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		self.query_addrs(hostname, addr_type, out)
+			.map(|(found, _, _)| found)
+	}
+
+	fn get_host_by_address(&self, addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error> {
+		let mut hostname = None;
+		let mut result = Ok(());
+		self.query(
+			&message::reverse_name(addr),
+			TYPE_PTR,
+			|response, record| {
+				if record.rtype == TYPE_PTR && hostname.is_none() {
+					let mut name = String::new();
+					result = message::read_name(response, record.offset, &mut name);
+					hostname = Some(name);
+				}
+			},
+		)?;
+		result.map_err(DohError::from)?;
+		hostname.ok_or(nb::Error::Other(DohError::NoRecord))
+	}
+}
+
+impl<F, E> Dnssec for DohResolver<F, E>
+where
+	F: Fn(&[u8], &mut [u8]) -> nb::Result<usize, E>,
+	E: core::fmt::Debug,
+{
+	fn set_dnssec_validation(&self, enabled: bool) -> Result<(), Self::Error> {
+		self.validate.set(enabled);
+		Ok(())
+	}
+
+	fn get_host_by_name_validated(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<(IpAddr, DnssecStatus), Self::Error> {
+		let mut addr = [IpAddr::from([0; 4])];
+		let (_, _, authenticated) = self.query_addrs(hostname, addr_type, &mut addr)?;
+		let status = if authenticated {
+			DnssecStatus::Authenticated
+		} else {
+			DnssecStatus::Unauthenticated
+		};
+		Ok((addr[0], status))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::cell::RefCell;
+	use heapless::Vec;
+
+	/// Answer `query` with one record of type `rtype` holding `data`, or with
+	/// no record.
+	fn respond(query: &[u8], answer: Option<(u16, &[u8])>, out: &mut [u8]) -> usize {
+		out[..query.len()].copy_from_slice(query);
+		out[2] = 0x81;
+		out[3] = 0x80;
+		let mut len = query.len();
+		if let Some((rtype, data)) = answer {
+			out[7] = 1;
+			let record = [
+				0xC0,
+				12,
+				(rtype >> 8) as u8,
+				rtype as u8,
+				0,
+				1,
+				0,
+				0,
+				0,
+				60,
+				0,
+				data.len() as u8,
+			];
+			out[len..len + record.len()].copy_from_slice(&record);
+			len += record.len();
+			out[len..len + data.len()].copy_from_slice(data);
+			len += data.len();
+		}
+		len
+	}
+
+	fn qtype(query: &[u8]) -> u16 {
+		u16::from(query[query.len() - 4]) << 8 | u16::from(query[query.len() - 3])
+	}
+
+	#[test]
+	fn either_resumes_the_pending_aaaa_query() {
+		let sent: RefCell<Vec<u16, consts::U8>> = RefCell::new(Vec::new());
+		let v6 = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+		let resolver = DohResolver::new(|query: &[u8], out: &mut [u8]| {
+			let mut sent = sent.borrow_mut();
+			sent.push(qtype(query)).unwrap();
+			match qtype(query) {
+				TYPE_A => Ok(respond(query, None, out)),
+				_ if sent.len() == 2 => Err(nb::Error::<()>::WouldBlock),
+				_ => Ok(respond(query, Some((TYPE_AAAA, &v6)), out)),
+			}
+		});
+		assert_eq!(
+			resolver.get_host_by_name("example.com", AddrType::Either),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(
+			resolver.get_host_by_name("example.com", AddrType::Either),
+			Ok(IpAddr::from(v6))
+		);
+		assert_eq!(&sent.borrow()[..], &[TYPE_A, TYPE_AAAA, TYPE_AAAA]);
+
+		// The next lookup starts over with an `A` query.
+		resolver
+			.get_host_by_name("example.com", AddrType::Either)
+			.unwrap();
+		assert_eq!(sent.borrow()[3], TYPE_A);
+	}
+
+	#[test]
+	fn pending_aaaa_query_belongs_to_its_hostname() {
+		let sent: RefCell<Vec<u16, consts::U8>> = RefCell::new(Vec::new());
+		let v4 = [192, 0, 2, 1];
+		let resolver = DohResolver::new(|query: &[u8], out: &mut [u8]| {
+			let mut sent = sent.borrow_mut();
+			sent.push(qtype(query)).unwrap();
+			let v6_only = query[13..17] == *b"ipv6";
+			match qtype(query) {
+				TYPE_A if v6_only => Ok(respond(query, None, out)),
+				TYPE_A => Ok(respond(query, Some((TYPE_A, &v4)), out)),
+				_ => Err(nb::Error::<()>::WouldBlock),
+			}
+		});
+		assert_eq!(
+			resolver.get_host_by_name("ipv6.example.com", AddrType::Either),
+			Err(nb::Error::WouldBlock)
+		);
+		// Another name asks for its own `A` record first.
+		assert_eq!(
+			resolver.get_host_by_name("www.example.com", AddrType::Either),
+			Ok(IpAddr::from(v4))
+		);
+		assert_eq!(&sent.borrow()[..], &[TYPE_A, TYPE_AAAA, TYPE_A]);
+	}
+
+	#[test]
+	fn either_tries_aaaa_after_a_failed_a_query() {
+		let v6 = [0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1];
+		let resolver = DohResolver::new(|query: &[u8], out: &mut [u8]| match qtype(query) {
+			TYPE_A => Err(nb::Error::Other("refused")),
+			_ => Ok(respond(query, Some((TYPE_AAAA, &v6)), out)),
+		});
+		assert_eq!(
+			resolver.get_host_by_name("example.com", AddrType::Either),
+			Ok(IpAddr::from(v6))
+		);
+
+		// Without an `AAAA` record either, the `A` query's error is the one
+		// reported.
+		let resolver = DohResolver::new(|query: &[u8], out: &mut [u8]| match qtype(query) {
+			TYPE_A => Err(nb::Error::Other("refused")),
+			_ => Ok(respond(query, None, out)),
+		});
+		assert_eq!(
+			resolver.get_host_by_name("example.com", AddrType::Either),
+			Err(nb::Error::Other(DohError::Request("refused")))
+		);
+	}
+}
@@ -0,0 +1,236 @@
+use crate::{AddrType, Dns};
+use no_std_net::IpAddr;
+
+/// Which address family a [`DualStackQuery`] puts first.
+///
+/// [`DualStackQuery`]: struct.DualStackQuery.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddrPreference {
+	/// Order IPv6 addresses first, as recommended by [`rfc8305`]
+	///
+	/// [`rfc8305`]: https://tools.ietf.org/html/rfc8305
+	IPv6,
+	/// Order IPv4 addresses first
+	IPv4,
+}
+
+/// Resolves both the `AAAA` and `A` records of a host and writes its
+/// addresses to a caller-provided buffer, alternating between the families
+/// starting with the preferred one. When the buffer has room for more than
+/// one address, at least one slot is kept for the other family, if it has
+/// any address.
+///
+/// This is the resolution half of Happy Eyeballs ([`rfc8305`]): connecting to
+/// the addresses in order gives dual-stack devices a quick fallback when one
+/// family is broken. A family without addresses is skipped; an error is only
+/// returned if neither family resolves.
+///
+/// The query remembers which family has completed, so that with a
+/// non-blocking resolver the preferred family is not asked again while
+/// waiting for the other one.
+///
+/// [`rfc8305`]: https://tools.ietf.org/html/rfc8305
+pub struct DualStackQuery<'a, E> {
+	hostname: &'a str,
+	preference: AddrPreference,
+	/// The outcome of the preferred family's query, once it has completed
+	preferred: Option<Result<usize, E>>,
+}
+
+impl<'a, E> DualStackQuery<'a, E> {
+	/// Prepare to resolve `hostname`, putting the addresses of the family of
+	/// `preference` first.
+	pub fn new(hostname: &'a str, preference: AddrPreference) -> Self {
+		DualStackQuery {
+			hostname,
+			preference,
+			preferred: None,
+		}
+	}
+
+	/// Resolve the host with `dns`, returning `Ok(n)` once both families
+	/// have completed, which means `n` addresses have been placed in
+	/// `&out[0..n]`.
+	///
+	/// Returns `WouldBlock` while a query is in flight; it must be called
+	/// again with the same `dns` and `out` until the resolution completes.
+	/// Polling again after that starts a new resolution.
+	pub fn poll<D>(&mut self, dns: &D, out: &mut [IpAddr]) -> nb::Result<usize, E>
+	where
+		D: Dns<Error = E>,
+	{
+		let (first, second) = match self.preference {
+			AddrPreference::IPv6 => (AddrType::IPv6, AddrType::IPv4),
+			AddrPreference::IPv4 => (AddrType::IPv4, AddrType::IPv6),
+		};
+		let reserved = if out.len() > 1 { 1 } else { 0 };
+
+		if self.preferred.is_none() {
+			let len = out.len();
+			self.preferred = Some(
+				match dns.get_hosts_by_name(self.hostname, first, &mut out[..len - reserved]) {
+					Ok(n) => Ok(n),
+					Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+					Err(nb::Error::Other(e)) => Err(e),
+				},
+			);
+		}
+		let preferred = match self.preferred {
+			Some(Ok(n)) => n,
+			_ => 0,
+		};
+		let other = match dns.get_hosts_by_name(self.hostname, second, &mut out[preferred..]) {
+			Ok(n) => n,
+			Err(nb::Error::WouldBlock) => return Err(nb::Error::WouldBlock),
+			Err(nb::Error::Other(e)) if preferred == 0 => {
+				// Report the error of the preferred family if it failed too.
+				return match self.preferred.take() {
+					Some(Err(first)) => Err(nb::Error::Other(first)),
+					_ => Err(nb::Error::Other(e)),
+				};
+			}
+			Err(nb::Error::Other(_)) => 0,
+		};
+		self.preferred = None;
+
+		// Interleave the two families in place: the other family's addresses
+		// sit after the preferred ones, so move each into place in turn.
+		for i in 0..core::cmp::min(preferred, other) {
+			out[2 * i + 1..=preferred + i].rotate_right(1);
+		}
+		Ok(preferred + other)
+	}
+}
+
+/// Resolve both the `AAAA` and `A` records of a host and write its addresses
+/// to `out`, like a [`DualStackQuery`] polled once. Returns `Ok(n)`, which
+/// means `n` addresses have been placed in `&out[0..n]`.
+///
+/// Nothing is kept between calls, so when this returns `WouldBlock`, calling
+/// it again asks for the preferred family again. Use a [`DualStackQuery`]
+/// with non-blocking resolvers.
+///
+/// [`DualStackQuery`]: struct.DualStackQuery.html
+pub fn resolve_dual_stack<D: Dns>(
+	dns: &D,
+	hostname: &str,
+	preference: AddrPreference,
+	out: &mut [IpAddr],
+) -> nb::Result<usize, D::Error> {
+	DualStackQuery::new(hostname, preference).poll(dns, out)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{DnsErrorKind, LoopbackStack};
+	use core::cell::Cell;
+	use heapless::{consts, String};
+
+	/// A resolver over a [`LoopbackStack`] that answers `IPv4` queries only
+	/// on the second try, and counts the queries of each family. It only
+	/// supports `get_hosts_by_name`, which is all the dual-stack query uses.
+	struct SlowIpv4 {
+		hosts: LoopbackStack,
+		v4: Cell<usize>,
+		v6: Cell<usize>,
+	}
+
+	impl Dns for SlowIpv4 {
+		type Error = DnsErrorKind;
+
+		fn get_host_by_name(
+			&self,
+			_hostname: &str,
+			_addr_type: AddrType,
+		) -> nb::Result<IpAddr, Self::Error> {
+			Err(nb::Error::Other(DnsErrorKind::Other))
+		}
+
+		fn get_hosts_by_name(
+			&self,
+			hostname: &str,
+			addr_type: AddrType,
+			out: &mut [IpAddr],
+		) -> nb::Result<usize, Self::Error> {
+			let count = match addr_type {
+				AddrType::IPv4 => &self.v4,
+				_ => &self.v6,
+			};
+			count.set(count.get() + 1);
+			if addr_type == AddrType::IPv4 && self.v4.get() == 1 {
+				return Err(nb::Error::WouldBlock);
+			}
+			self.hosts
+				.get_hosts_by_name(hostname, addr_type, out)
+				.map_err(|e| e.map(|e| crate::DnsError::kind(&e)))
+		}
+
+		fn get_host_by_address(
+			&self,
+			_addr: IpAddr,
+		) -> nb::Result<String<consts::U256>, Self::Error> {
+			Err(nb::Error::Other(DnsErrorKind::Other))
+		}
+	}
+
+	fn resolver() -> SlowIpv4 {
+		let hosts = LoopbackStack::new();
+		for addr in &[
+			IpAddr::from([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+			IpAddr::from([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]),
+			IpAddr::from([10, 0, 0, 1]),
+			IpAddr::from([10, 0, 0, 2]),
+		] {
+			hosts.add_host("example.com", *addr).unwrap();
+		}
+		hosts
+			.add_host(
+				"v6.example.com",
+				IpAddr::from([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3]),
+			)
+			.unwrap();
+		SlowIpv4 {
+			hosts,
+			v4: Cell::new(0),
+			v6: Cell::new(0),
+		}
+	}
+
+	#[test]
+	fn preferred_family_is_not_asked_again() {
+		let dns = resolver();
+		let mut query = DualStackQuery::new("example.com", AddrPreference::IPv6);
+		let mut out = [IpAddr::from([0; 4]); 3];
+		assert_eq!(query.poll(&dns, &mut out), Err(nb::Error::WouldBlock));
+		assert_eq!(query.poll(&dns, &mut out), Ok(3));
+		assert_eq!((dns.v6.get(), dns.v4.get()), (1, 2));
+		assert_eq!(
+			out,
+			[
+				IpAddr::from([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]),
+				IpAddr::from([10, 0, 0, 1]),
+				IpAddr::from([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2]),
+			]
+		);
+	}
+
+	#[test]
+	fn missing_family_is_skipped() {
+		let dns = resolver();
+		let mut query = DualStackQuery::new("v6.example.com", AddrPreference::IPv4);
+		let mut out = [IpAddr::from([0; 4]); 4];
+		assert_eq!(query.poll(&dns, &mut out), Err(nb::Error::WouldBlock));
+		assert_eq!(query.poll(&dns, &mut out), Ok(1));
+		assert_eq!(
+			out[0],
+			IpAddr::from([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3])
+		);
+
+		let mut query = DualStackQuery::new("unknown.com", AddrPreference::IPv6);
+		assert_eq!(
+			query.poll(&dns, &mut out),
+			Err(nb::Error::Other(DnsErrorKind::NxDomain))
+		);
+	}
+}
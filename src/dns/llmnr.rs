@@ -0,0 +1,386 @@
+use crate::{AddrType, Dns, DnsError, DnsErrorKind};
+use core::cell::RefCell;
+use core::fmt::Write;
+use heapless::{consts, String};
+use no_std_net::IpAddr;
+
+/// The error type of an [`LlmnrFallback`].
+///
+/// [`LlmnrFallback`]: struct.LlmnrFallback.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum LlmnrFallbackError<D, L> {
+	/// The unicast resolver failed, and the name was not eligible for LLMNR
+	Dns(D),
+	/// The unicast resolver could not resolve the name, and neither could the
+	/// LLMNR resolver
+	Llmnr(L),
+}
+
+impl<D: DnsError, L: DnsError> DnsError for LlmnrFallbackError<D, L> {
+	fn kind(&self) -> DnsErrorKind {
+		match self {
+			LlmnrFallbackError::Dns(e) => e.kind(),
+			LlmnrFallbackError::Llmnr(e) => e.kind(),
+		}
+	}
+}
+
+impl<D: core::fmt::Display, L: core::fmt::Display> core::fmt::Display for LlmnrFallbackError<D, L> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			LlmnrFallbackError::Dns(e) => write!(f, "name resolution failed: {}", e),
+			LlmnrFallbackError::Llmnr(e) => write!(f, "LLMNR resolution failed: {}", e),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<D: std::error::Error + 'static, L: std::error::Error + 'static> std::error::Error
+	for LlmnrFallbackError<D, L>
+{
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			LlmnrFallbackError::Dns(e) => Some(e),
+			LlmnrFallbackError::Llmnr(e) => Some(e),
+		}
+	}
+}
+
+/// A [`Dns`] wrapper that falls back to Link-Local Multicast Name Resolution
+/// ([`rfc4795`]) for single-label names, such as `printer`, that the unicast
+/// resolver can't find. On LANs where machine names aren't in DNS, which is
+/// common on Windows networks, this lets devices find their peers by name.
+///
+/// `L` is the LLMNR resolver, which implements [`Dns`] by sending its queries
+/// to the LLMNR multicast group. It is only asked once the unicast resolver
+/// `D` has answered that the name doesn't exist or has no record of the
+/// requested type. Names with more than one label are only ever resolved by
+/// `D`.
+///
+/// [`Dns`]: trait.Dns.html
+/// [`rfc4795`]: https://tools.ietf.org/html/rfc4795
+pub struct LlmnrFallback<D, L> {
+	dns: D,
+	llmnr: L,
+	/// The query currently being resolved with LLMNR, so that the unicast
+	/// resolver isn't asked again while the LLMNR query is in flight.
+	falling_back: RefCell<Option<Pending>>,
+}
+
+/// A query in flight with the LLMNR resolver.
+struct Pending {
+	name: String<consts::U256>,
+	/// The type of address asked for, or `None` for a reverse lookup
+	addr_type: Option<AddrType>,
+}
+
+type FallbackResult<T, D, L> =
+	nb::Result<T, LlmnrFallbackError<<D as Dns>::Error, <L as Dns>::Error>>;
+
+fn is_single_label(hostname: &str) -> bool {
+	!hostname.trim_end_matches('.').contains('.')
+}
+
+impl<D, L> LlmnrFallback<D, L>
+where
+	D: Dns,
+	L: Dns,
+{
+	/// Resolve names with `dns`, falling back to `llmnr`.
+	pub fn new(dns: D, llmnr: L) -> Self {
+		LlmnrFallback {
+			dns,
+			llmnr,
+			falling_back: RefCell::new(None),
+		}
+	}
+
+	/// Destroy the wrapper, returning the unicast and LLMNR resolvers.
+	pub fn release(self) -> (D, L) {
+		(self.dns, self.llmnr)
+	}
+
+	/// Whether `name` is already being resolved with LLMNR for addresses of
+	/// type `addr_type`.
+	fn is_falling_back(&self, name: &str, addr_type: &Option<AddrType>) -> bool {
+		match &*self.falling_back.borrow() {
+			Some(pending) => pending.name == name && pending.addr_type == *addr_type,
+			None => false,
+		}
+	}
+
+	/// Handle the unicast resolver's answer, returning `None` if the query
+	/// should fall back to LLMNR.
+	fn after_dns<T>(
+		&self,
+		eligible: bool,
+		result: nb::Result<T, D::Error>,
+	) -> Option<FallbackResult<T, D, L>> {
+		match result {
+			Err(nb::Error::Other(ref e))
+				if eligible
+					&& (e.kind() == DnsErrorKind::NxDomain
+						|| e.kind() == DnsErrorKind::NoRecordOfType) =>
+			{
+				None
+			}
+			result => Some(result.map_err(|e| e.map(LlmnrFallbackError::Dns))),
+		}
+	}
+
+	/// Handle the LLMNR resolver's answer for `name`.
+	fn after_llmnr<T>(
+		&self,
+		name: &str,
+		addr_type: Option<AddrType>,
+		result: nb::Result<T, L::Error>,
+	) -> FallbackResult<T, D, L> {
+		let mut falling_back = self.falling_back.borrow_mut();
+		match result {
+			Err(nb::Error::WouldBlock) => {
+				let mut pending = String::new();
+				if pending.push_str(name).is_ok() {
+					*falling_back = Some(Pending {
+						name: pending,
+						addr_type,
+					});
+				}
+				Err(nb::Error::WouldBlock)
+			}
+			result => {
+				*falling_back = None;
+				result.map_err(|e| e.map(LlmnrFallbackError::Llmnr))
+			}
+		}
+	}
+}
+
+impl<D, L> Dns for LlmnrFallback<D, L>
+where
+	D: Dns,
+	L: Dns,
+{
+	type Error = LlmnrFallbackError<D::Error, L::Error>;
+
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error> {
+		let query = Some(addr_type.clone());
+		if !self.is_falling_back(hostname, &query) {
+			let result = self.dns.get_host_by_name(hostname, addr_type.clone());
+			if let Some(result) = self.after_dns(is_single_label(hostname), result) {
+				return result;
+			}
+		}
+		let result = self.llmnr.get_host_by_name(hostname, addr_type);
+		self.after_llmnr(hostname, query, result)
+	}
+
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		let query = Some(addr_type.clone());
+		if !self.is_falling_back(hostname, &query) {
+			let result = self.dns.get_hosts_by_name(hostname, addr_type.clone(), out);
+			if let Some(result) = self.after_dns(is_single_label(hostname), result) {
+				return result;
+			}
+		}
+		let result = self.llmnr.get_hosts_by_name(hostname, addr_type, out);
+		self.after_llmnr(hostname, query, result)
+	}
+
+	fn get_host_by_address(&self, addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error> {
+		let mut name: String<consts::U256> = String::new();
+		write!(name, "{}", addr).ok();
+		if !self.is_falling_back(&name, &None) {
+			let result = self.dns.get_host_by_address(addr);
+			if let Some(result) = self.after_dns(true, result) {
+				return result;
+			}
+		}
+		let result = self.llmnr.get_host_by_address(addr);
+		self.after_llmnr(&name, None, result)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::cell::Cell;
+
+	/// A unicast resolver that only knows `example.com`, counting its queries.
+	#[derive(Default)]
+	struct Unicast {
+		queries: Cell<usize>,
+	}
+
+	impl Dns for Unicast {
+		type Error = DnsErrorKind;
+
+		fn get_host_by_name(
+			&self,
+			hostname: &str,
+			_addr_type: AddrType,
+		) -> nb::Result<IpAddr, Self::Error> {
+			self.queries.set(self.queries.get() + 1);
+			match hostname {
+				"example.com" => Ok(IpAddr::from([192, 0, 2, 1])),
+				_ => Err(nb::Error::Other(DnsErrorKind::NxDomain)),
+			}
+		}
+
+		fn get_host_by_address(
+			&self,
+			_addr: IpAddr,
+		) -> nb::Result<String<consts::U256>, Self::Error> {
+			self.queries.set(self.queries.get() + 1);
+			Err(nb::Error::Other(DnsErrorKind::NxDomain))
+		}
+	}
+
+	/// An LLMNR resolver that knows `printer`, and whose queries block for
+	/// as many calls as `blocking` says.
+	#[derive(Default)]
+	struct Llmnr {
+		blocking: Cell<usize>,
+		queries: Cell<usize>,
+	}
+
+	impl Dns for Llmnr {
+		type Error = DnsErrorKind;
+
+		fn get_host_by_name(
+			&self,
+			hostname: &str,
+			addr_type: AddrType,
+		) -> nb::Result<IpAddr, Self::Error> {
+			self.query()?;
+			match (hostname, addr_type) {
+				("printer", AddrType::IPv6) => Ok(IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 1])),
+				("printer", _) => Ok(IpAddr::from([192, 168, 1, 10])),
+				_ => Err(nb::Error::Other(DnsErrorKind::NxDomain)),
+			}
+		}
+
+		fn get_host_by_address(
+			&self,
+			_addr: IpAddr,
+		) -> nb::Result<String<consts::U256>, Self::Error> {
+			self.query()?;
+			Err(nb::Error::Other(DnsErrorKind::NxDomain))
+		}
+	}
+
+	impl Llmnr {
+		/// Count a query, and block it if it should.
+		fn query(&self) -> nb::Result<(), DnsErrorKind> {
+			self.queries.set(self.queries.get() + 1);
+			if self.blocking.get() > 0 {
+				self.blocking.set(self.blocking.get() - 1);
+				return Err(nb::Error::WouldBlock);
+			}
+			Ok(())
+		}
+	}
+
+	fn resolver(blocking: usize) -> LlmnrFallback<Unicast, Llmnr> {
+		let llmnr = Llmnr::default();
+		llmnr.blocking.set(blocking);
+		LlmnrFallback::new(Unicast::default(), llmnr)
+	}
+
+	#[test]
+	fn single_label_names_fall_back_to_llmnr() {
+		let dns = resolver(1);
+		assert_eq!(
+			dns.get_host_by_name("printer", AddrType::IPv4),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(
+			dns.get_host_by_name("printer", AddrType::IPv4),
+			Ok(IpAddr::from([192, 168, 1, 10]))
+		);
+		// The unicast resolver isn't asked again while LLMNR is.
+		let (unicast, llmnr) = dns.release();
+		assert_eq!(unicast.queries.get(), 1);
+		assert_eq!(llmnr.queries.get(), 2);
+	}
+
+	#[test]
+	fn other_names_stay_with_the_unicast_resolver() {
+		let dns = resolver(0);
+		assert_eq!(
+			dns.get_host_by_name("example.com", AddrType::IPv4),
+			Ok(IpAddr::from([192, 0, 2, 1]))
+		);
+		assert_eq!(
+			dns.get_host_by_name("unknown.example.com", AddrType::IPv4),
+			Err(nb::Error::Other(LlmnrFallbackError::Dns(
+				DnsErrorKind::NxDomain
+			)))
+		);
+		assert_eq!(
+			dns.get_host_by_name("unknown", AddrType::IPv4),
+			Err(nb::Error::Other(LlmnrFallbackError::Llmnr(
+				DnsErrorKind::NxDomain
+			)))
+		);
+		let (unicast, llmnr) = dns.release();
+		assert_eq!(unicast.queries.get(), 3);
+		assert_eq!(llmnr.queries.get(), 1);
+	}
+
+	#[test]
+	fn pending_fallbacks_belong_to_their_address_type() {
+		let dns = resolver(2);
+		assert_eq!(
+			dns.get_host_by_name("printer", AddrType::IPv4),
+			Err(nb::Error::WouldBlock)
+		);
+		// An AAAA query for the same name starts over with the unicast
+		// resolver, rather than taking over the A query.
+		assert_eq!(
+			dns.get_host_by_name("printer", AddrType::IPv6),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(dns.dns.queries.get(), 2);
+		assert_eq!(
+			dns.get_host_by_name("printer", AddrType::IPv6),
+			Ok(IpAddr::from([0xfe80, 0, 0, 0, 0, 0, 0, 1]))
+		);
+		assert_eq!(dns.dns.queries.get(), 2);
+		assert_eq!(
+			dns.get_host_by_name("printer", AddrType::IPv4),
+			Ok(IpAddr::from([192, 168, 1, 10]))
+		);
+		assert_eq!(dns.dns.queries.get(), 3);
+	}
+
+	#[test]
+	fn reverse_lookups_are_pending_apart_from_forward_ones() {
+		let dns = resolver(1);
+		let addr = IpAddr::from([192, 168, 1, 10]);
+		assert_eq!(dns.get_host_by_address(addr), Err(nb::Error::WouldBlock));
+		// A forward lookup of the address as a name is its own query.
+		assert_eq!(
+			dns.get_host_by_name("192.168.1.10", AddrType::IPv4),
+			Err(nb::Error::Other(LlmnrFallbackError::Dns(
+				DnsErrorKind::NxDomain
+			)))
+		);
+		assert_eq!(dns.dns.queries.get(), 2);
+		assert_eq!(
+			dns.get_host_by_address(addr),
+			Err(nb::Error::Other(LlmnrFallbackError::Llmnr(
+				DnsErrorKind::NxDomain
+			)))
+		);
+		assert_eq!(dns.dns.queries.get(), 2);
+	}
+}
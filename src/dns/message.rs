@@ -0,0 +1,413 @@
+//! Just enough of the DNS wire format ([`rfc1035`]) to build queries and read
+//! the records out of their responses.
+//!
+//! [`rfc1035`]: https://tools.ietf.org/html/rfc1035
+
+use core::fmt::Write;
+use heapless::{consts, String};
+use no_std_net::IpAddr;
+
+pub(crate) const TYPE_A: u16 = 1;
+pub(crate) const TYPE_PTR: u16 = 12;
+pub(crate) const TYPE_AAAA: u16 = 28;
+
+const CLASS_IN: u16 = 1;
+const HEADER_LEN: usize = 12;
+const RCODE_NAME_ERROR: u8 = 3;
+/// The authenticated data flag in the second half of the header flags.
+const FLAG_AD: u16 = 0x0020;
+/// Bound on the number of compression pointers followed in one name, so that
+/// a malicious message can't make us loop forever.
+const MAX_POINTERS: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum MessageError {
+	/// The name to query is not a valid domain name
+	InvalidName,
+	/// The query does not fit in the buffer
+	BufferTooSmall,
+	/// The response could not be parsed
+	Malformed,
+	/// The server reported that the name does not exist
+	NameError,
+	/// The server reported another error, with the given response code
+	ServerFailure(u8),
+}
+
+/// A resource record from the answer section of a response.
+pub(crate) struct Record<'a> {
+	pub rtype: u16,
+	pub ttl: u32,
+	pub data: &'a [u8],
+	/// Offset of `data` in the message, needed to decompress names in it.
+	pub offset: usize,
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> Result<u16, MessageError> {
+	match msg.get(pos..pos + 2) {
+		Some(b) => Ok(u16::from(b[0]) << 8 | u16::from(b[1])),
+		None => Err(MessageError::Malformed),
+	}
+}
+
+fn read_u32(msg: &[u8], pos: usize) -> Result<u32, MessageError> {
+	Ok(u32::from(read_u16(msg, pos)?) << 16 | u32::from(read_u16(msg, pos + 2)?))
+}
+
+/// Write a query for `name` into `buf`, returning the length of the query.
+/// The query ID is always 0, as recommended for DNS over HTTPS. With
+/// `authenticated_data` set, the query asks the server to report whether it
+/// validated the answer with DNSSEC ([`rfc6840`] section 5.7).
+///
+/// [`rfc6840`]: https://tools.ietf.org/html/rfc6840
+pub(crate) fn encode_query(
+	buf: &mut [u8],
+	name: &str,
+	qtype: u16,
+	authenticated_data: bool,
+) -> Result<usize, MessageError> {
+	super::validate_hostname(name).map_err(|_| MessageError::InvalidName)?;
+	let name = name.trim_end_matches('.');
+	// Header, name with a length byte per label and the root label, type and
+	// class.
+	let len = HEADER_LEN + name.len() + 2 + 4;
+	if buf.len() < len {
+		return Err(MessageError::BufferTooSmall);
+	}
+
+	// ID 0, recursion desired, one question.
+	buf[..HEADER_LEN].copy_from_slice(&[0, 0, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+	if authenticated_data {
+		buf[3] |= FLAG_AD as u8;
+	}
+	let mut pos = HEADER_LEN;
+	for label in name.split('.') {
+		buf[pos] = label.len() as u8;
+		buf[pos + 1..pos + 1 + label.len()].copy_from_slice(label.as_bytes());
+		pos += 1 + label.len();
+	}
+	buf[pos] = 0;
+	buf[pos + 1..pos + 5].copy_from_slice(&[
+		(qtype >> 8) as u8,
+		qtype as u8,
+		(CLASS_IN >> 8) as u8,
+		CLASS_IN as u8,
+	]);
+	Ok(pos + 5)
+}
+
+/// Skip the possibly compressed name starting at `pos`, returning the
+/// position just after it.
+fn skip_name(msg: &[u8], mut pos: usize) -> Result<usize, MessageError> {
+	loop {
+		let len = *msg.get(pos).ok_or(MessageError::Malformed)?;
+		match len & 0xC0 {
+			0x00 if len == 0 => return Ok(pos + 1),
+			0x00 => pos += 1 + usize::from(len),
+			0xC0 => return Ok(pos + 2),
+			_ => return Err(MessageError::Malformed),
+		}
+	}
+}
+
+/// Read the possibly compressed name starting at `pos` into `out`, in dotted
+/// notation without the trailing dot.
+pub(crate) fn read_name(
+	msg: &[u8],
+	mut pos: usize,
+	out: &mut String<consts::U256>,
+) -> Result<(), MessageError> {
+	// `String::clear` indexes past the end in this version of heapless.
+	while out.pop().is_some() {}
+	let mut pointers = 0;
+	loop {
+		let len = *msg.get(pos).ok_or(MessageError::Malformed)?;
+		match len & 0xC0 {
+			0x00 if len == 0 => return Ok(()),
+			0x00 => {
+				let label = msg
+					.get(pos + 1..pos + 1 + usize::from(len))
+					.ok_or(MessageError::Malformed)?;
+				let label = core::str::from_utf8(label).map_err(|_| MessageError::Malformed)?;
+				if !out.is_empty() {
+					out.push('.').map_err(|_| MessageError::Malformed)?;
+				}
+				out.push_str(label).map_err(|_| MessageError::Malformed)?;
+				pos += 1 + usize::from(len);
+			}
+			0xC0 => {
+				pointers += 1;
+				if pointers > MAX_POINTERS {
+					return Err(MessageError::Malformed);
+				}
+				pos = usize::from(read_u16(msg, pos)? & 0x3FFF);
+			}
+			_ => return Err(MessageError::Malformed),
+		}
+	}
+}
+
+/// Check the header of the response `msg` and call `f` with every record in
+/// its answer section, returning whether the server set the authenticated
+/// data flag.
+pub(crate) fn for_each_answer<F>(msg: &[u8], mut f: F) -> Result<bool, MessageError>
+where
+	F: FnMut(Record),
+{
+	let flags = read_u16(msg, 2)?;
+	if flags & 0x8000 == 0 {
+		return Err(MessageError::Malformed);
+	}
+	match (flags & 0x000F) as u8 {
+		0 => {}
+		RCODE_NAME_ERROR => return Err(MessageError::NameError),
+		rcode => return Err(MessageError::ServerFailure(rcode)),
+	}
+
+	let questions = read_u16(msg, 4)?;
+	let answers = read_u16(msg, 6)?;
+	let mut pos = HEADER_LEN;
+	for _ in 0..questions {
+		pos = skip_name(msg, pos)? + 4;
+	}
+	for _ in 0..answers {
+		pos = skip_name(msg, pos)?;
+		let rtype = read_u16(msg, pos)?;
+		let ttl = read_u32(msg, pos + 4)?;
+		let len = usize::from(read_u16(msg, pos + 8)?);
+		let offset = pos + 10;
+		let data = msg
+			.get(offset..offset + len)
+			.ok_or(MessageError::Malformed)?;
+		f(Record {
+			rtype,
+			ttl,
+			data,
+			offset,
+		});
+		pos = offset + len;
+	}
+	Ok(flags & FLAG_AD != 0)
+}
+
+/// The address held by an `A` or `AAAA` record.
+pub(crate) fn record_addr(record: &Record) -> Option<IpAddr> {
+	match (record.rtype, record.data.len()) {
+		(TYPE_A, 4) => {
+			let mut octets = [0; 4];
+			octets.copy_from_slice(record.data);
+			Some(IpAddr::from(octets))
+		}
+		(TYPE_AAAA, 16) => {
+			let mut octets = [0; 16];
+			octets.copy_from_slice(record.data);
+			Some(IpAddr::from(octets))
+		}
+		_ => None,
+	}
+}
+
+/// The name under which the `PTR` record of `addr` is published, such as
+/// `4.3.2.1.in-addr.arpa` for `1.2.3.4`.
+pub(crate) fn reverse_name(addr: IpAddr) -> String<consts::U256> {
+	let mut name = String::new();
+	// The longest name, for IPv6, is 72 bytes, so writing can't fail.
+	match addr {
+		IpAddr::V4(addr) => {
+			let o = addr.octets();
+			write!(name, "{}.{}.{}.{}.in-addr.arpa", o[3], o[2], o[1], o[0]).ok();
+		}
+		IpAddr::V6(addr) => {
+			for octet in addr.octets().iter().rev() {
+				write!(name, "{:x}.{:x}.", octet & 0xF, octet >> 4).ok();
+			}
+			name.push_str("ip6.arpa").ok();
+		}
+	}
+	name
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// A response to "example.com A" with two answers, the second of which
+	// compresses its name to a pointer to the first.
+	const RESPONSE: &[u8] = &[
+		0x00, 0x00, 0x81, 0xA0, 0x00, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, //
+		7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, //
+		0x00, 0x01, 0x00, 0x01, //
+		0xC0, 0x0C, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0E, 0x10, 0x00, 0x04, //
+		93, 184, 216, 34, //
+		3, b'w', b'w', b'w', 0xC0, 0x0C, 0x00, 0x1C, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3C, 0x00,
+		0x10, //
+		0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1,
+	];
+
+	#[test]
+	fn encode_query_writes_header_and_question() {
+		let mut buf = [0xFF; 64];
+		let len = encode_query(&mut buf, "example.com.", TYPE_AAAA, true).unwrap();
+		assert_eq!(
+			&buf[..len],
+			&[
+				0x00, 0x00, 0x01, 0x20, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, //
+				7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, //
+				0x00, 0x1C, 0x00, 0x01,
+			][..]
+		);
+	}
+
+	#[test]
+	fn encode_query_rejects_bad_input() {
+		let mut buf = [0; 64];
+		assert_eq!(
+			encode_query(&mut buf, "exa mple.com", TYPE_A, false),
+			Err(MessageError::InvalidName)
+		);
+		assert_eq!(
+			encode_query(&mut buf[..20], "example.com", TYPE_A, false),
+			Err(MessageError::BufferTooSmall)
+		);
+	}
+
+	#[test]
+	fn read_name_follows_pointers() {
+		let mut name = String::new();
+		read_name(RESPONSE, 12, &mut name).unwrap();
+		assert_eq!(name.as_str(), "example.com");
+		read_name(RESPONSE, 45, &mut name).unwrap();
+		assert_eq!(name.as_str(), "www.example.com");
+	}
+
+	#[test]
+	fn read_name_rejects_pointer_loops() {
+		let mut name = String::new();
+		// A pointer to itself.
+		assert_eq!(
+			read_name(&[0xC0, 0], 0, &mut name),
+			Err(MessageError::Malformed)
+		);
+
+		// A chain of exactly `MAX_POINTERS` pointers is still followed.
+		let mut chain = [0u8; 2 * MAX_POINTERS + 1];
+		for i in 0..MAX_POINTERS {
+			chain[2 * i] = 0xC0;
+			chain[2 * i + 1] = (2 * i + 2) as u8;
+		}
+		assert_eq!(read_name(&chain, 0, &mut name), Ok(()));
+		assert_eq!(name.as_str(), "");
+		// One more is not.
+		let mut chain = [0u8; 2 * MAX_POINTERS + 3];
+		for i in 0..=MAX_POINTERS {
+			chain[2 * i] = 0xC0;
+			chain[2 * i + 1] = (2 * i + 2) as u8;
+		}
+		assert_eq!(
+			read_name(&chain, 0, &mut name),
+			Err(MessageError::Malformed)
+		);
+	}
+
+	#[test]
+	fn read_name_rejects_truncated_labels() {
+		let mut name = String::new();
+		assert_eq!(
+			read_name(&RESPONSE[..16], 12, &mut name),
+			Err(MessageError::Malformed)
+		);
+		assert_eq!(
+			read_name(&[0x80, 0], 0, &mut name),
+			Err(MessageError::Malformed)
+		);
+	}
+
+	#[test]
+	fn for_each_answer_reads_records() {
+		let mut addrs = [None; 2];
+		let mut ttls = [0; 2];
+		let mut count = 0;
+		let authenticated = for_each_answer(RESPONSE, |record| {
+			addrs[count] = record_addr(&record);
+			ttls[count] = record.ttl;
+			count += 1;
+		})
+		.unwrap();
+		assert!(authenticated);
+		assert_eq!(count, 2);
+		assert_eq!(ttls, [3600, 60]);
+		assert_eq!(addrs[0], Some(IpAddr::from([93, 184, 216, 34])));
+		assert_eq!(
+			addrs[1],
+			Some(IpAddr::from([
+				0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1
+			]))
+		);
+	}
+
+	#[test]
+	fn for_each_answer_rejects_bad_responses() {
+		// Truncated header.
+		assert_eq!(
+			for_each_answer(&RESPONSE[..3], |_| {}),
+			Err(MessageError::Malformed)
+		);
+		// Truncated record data.
+		assert_eq!(
+			for_each_answer(&RESPONSE[..RESPONSE.len() - 1], |_| {}),
+			Err(MessageError::Malformed)
+		);
+
+		let mut msg = [0; 128];
+		msg[..RESPONSE.len()].copy_from_slice(RESPONSE);
+		// Not a response.
+		msg[2] = 0x01;
+		assert_eq!(
+			for_each_answer(&msg[..RESPONSE.len()], |_| {}),
+			Err(MessageError::Malformed)
+		);
+		msg[2] = 0x81;
+		msg[3] = 0x83;
+		assert_eq!(
+			for_each_answer(&msg[..RESPONSE.len()], |_| {}),
+			Err(MessageError::NameError)
+		);
+		msg[3] = 0x82;
+		assert_eq!(
+			for_each_answer(&msg[..RESPONSE.len()], |_| {}),
+			Err(MessageError::ServerFailure(2))
+		);
+	}
+
+	#[test]
+	fn record_addr_checks_type_and_length() {
+		let record = |rtype, data| Record {
+			rtype,
+			ttl: 0,
+			data,
+			offset: 0,
+		};
+		assert_eq!(
+			record_addr(&record(TYPE_A, &[10, 0, 0, 1])),
+			Some(IpAddr::from([10, 0, 0, 1]))
+		);
+		assert_eq!(record_addr(&record(TYPE_A, &[10, 0, 0])), None);
+		assert_eq!(record_addr(&record(TYPE_AAAA, &[10, 0, 0, 1])), None);
+		assert_eq!(record_addr(&record(TYPE_PTR, &[10, 0, 0, 1])), None);
+	}
+
+	#[test]
+	fn reverse_name_of_v4_and_v6() {
+		assert_eq!(
+			reverse_name(IpAddr::from([1, 2, 3, 4])).as_str(),
+			"4.3.2.1.in-addr.arpa"
+		);
+		assert_eq!(
+			reverse_name(IpAddr::from([
+				0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0x1f
+			]))
+			.as_str(),
+			"f.1.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+		);
+	}
+}
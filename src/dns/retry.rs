@@ -0,0 +1,391 @@
+use crate::clock::millis;
+use crate::{AddrType, Clock, Dns, DnsConfig, DnsError, DnsErrorKind};
+use core::cell::RefCell;
+use core::time::Duration;
+use heapless::{consts, String};
+use no_std_net::IpAddr;
+
+/// How a [`RetryingDns`] retries failed queries.
+///
+/// [`RetryingDns`]: struct.RetryingDns.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RetryPolicy {
+	/// How long each attempt may take before it is considered to have timed
+	/// out
+	pub timeout: Duration,
+	/// How many times a query is retried after the first attempt failed
+	pub retries: u8,
+}
+
+/// The error type of a [`RetryingDns`].
+///
+/// [`RetryingDns`]: struct.RetryingDns.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum RetryError<E> {
+	/// The last attempt took longer than the policy allows
+	Timeout,
+	/// The hostname is longer than the 256 bytes a query can be tracked with
+	NameTooLong,
+	/// The wrapped resolver failed
+	Dns(E),
+}
+
+impl<E: DnsError> DnsError for RetryError<E> {
+	fn kind(&self) -> DnsErrorKind {
+		match self {
+			RetryError::Timeout => DnsErrorKind::Timeout,
+			RetryError::NameTooLong => DnsErrorKind::NameTooLong,
+			RetryError::Dns(e) => e.kind(),
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for RetryError<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			RetryError::Timeout => f.write_str("the resolver did not answer in time"),
+			RetryError::NameTooLong => f.write_str("the hostname is too long"),
+			RetryError::Dns(e) => write!(f, "name resolution failed: {}", e),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for RetryError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			RetryError::Timeout | RetryError::NameTooLong => None,
+			RetryError::Dns(e) => Some(e),
+		}
+	}
+}
+
+struct Query {
+	hostname: String<consts::U256>,
+	addr_type: AddrType,
+	started_at: u64,
+	attempt: u8,
+}
+
+/// A [`Dns`] wrapper that retries queries which time out or fail on the
+/// server side, according to a [`RetryPolicy`]. Lossy links, such as
+/// cellular, often need more aggressive retries than the driver's own.
+///
+/// An attempt fails when the wrapped resolver reports a
+/// [`DnsErrorKind::Timeout`] or [`DnsErrorKind::ServerFailure`] error, or
+/// keeps returning `WouldBlock` for longer than the policy's timeout. The
+/// next attempt is made by asking the wrapped resolver again, after moving on
+/// to the next resolver address if rotation is enabled. Other errors are
+/// returned straight away.
+///
+/// A resolver that keeps its query in flight while returning `WouldBlock`
+/// goes on waiting for the same query when asked again, so after a timeout
+/// the retry only extends the deadline. To have the query sent again, give
+/// the wrapper a way to cancel it with [`set_cancel`](#method.set_cancel).
+///
+/// Retries are tracked for one query, a hostname and address type, at a
+/// time; starting another query forgets about the previous one. Reverse
+/// lookups are passed through without retries.
+///
+/// [`Dns`]: trait.Dns.html
+/// [`RetryPolicy`]: struct.RetryPolicy.html
+/// [`DnsErrorKind::Timeout`]: enum.DnsErrorKind.html#variant.Timeout
+/// [`DnsErrorKind::ServerFailure`]: enum.DnsErrorKind.html#variant.ServerFailure
+pub struct RetryingDns<D, C> {
+	dns: D,
+	clock: C,
+	policy: RetryPolicy,
+	rotate: Option<fn(&D)>,
+	cancel: Option<fn(&D)>,
+	query: RefCell<Option<Query>>,
+}
+
+/// Swap the primary and secondary resolver addresses of `dns`.
+fn swap_servers<D: DnsConfig>(dns: &D) {
+	if let Ok((primary, Some(secondary))) = dns.dns_servers() {
+		dns.set_dns_servers(secondary, Some(primary)).ok();
+	}
+}
+
+impl<D, C> RetryingDns<D, C>
+where
+	D: Dns,
+	C: Clock,
+{
+	/// Wrap `dns`, using `clock` to time attempts.
+	pub fn new(dns: D, clock: C, policy: RetryPolicy) -> Self {
+		RetryingDns {
+			dns,
+			clock,
+			policy,
+			rotate: None,
+			cancel: None,
+			query: RefCell::new(None),
+		}
+	}
+
+	/// Set how to cancel the query the wrapped resolver has in flight, so
+	/// that the attempt after a timeout sends the query again instead of
+	/// waiting longer for the same one.
+	pub fn set_cancel(&mut self, cancel: Option<fn(&D)>) {
+		self.cancel = cancel;
+	}
+
+	/// Get a reference to the wrapped resolver.
+	pub fn inner(&self) -> &D {
+		&self.dns
+	}
+
+	/// Destroy the wrapper, returning the wrapped resolver.
+	pub fn into_inner(self) -> D {
+		self.dns
+	}
+
+	fn retry<T, F>(
+		&self,
+		hostname: &str,
+		addr_type: &AddrType,
+		query: F,
+	) -> nb::Result<T, RetryError<D::Error>>
+	where
+		F: FnOnce(&D) -> nb::Result<T, D::Error>,
+	{
+		let now = self.clock.now_ms();
+		let mut state = self.query.borrow_mut();
+		let fresh = match &*state {
+			Some(q) => q.hostname != hostname || q.addr_type != *addr_type,
+			None => true,
+		};
+		if fresh {
+			let mut name = String::new();
+			// A name that can't be told apart from the next one would never
+			// time out.
+			if name.push_str(hostname).is_err() {
+				*state = None;
+				return Err(nb::Error::Other(RetryError::NameTooLong));
+			}
+			*state = Some(Query {
+				hostname: name,
+				addr_type: addr_type.clone(),
+				started_at: now,
+				attempt: 0,
+			});
+		}
+
+		let error = match query(&self.dns) {
+			Ok(value) => {
+				*state = None;
+				return Ok(value);
+			}
+			Err(nb::Error::WouldBlock) => {
+				let q = state.as_ref().expect("query state was just set");
+				if now.saturating_sub(q.started_at) < millis(self.policy.timeout) {
+					return Err(nb::Error::WouldBlock);
+				}
+				RetryError::Timeout
+			}
+			Err(nb::Error::Other(e)) => match e.kind() {
+				DnsErrorKind::Timeout | DnsErrorKind::ServerFailure => RetryError::Dns(e),
+				_ => {
+					*state = None;
+					return Err(nb::Error::Other(RetryError::Dns(e)));
+				}
+			},
+		};
+
+		let timed_out = match error {
+			RetryError::Timeout => true,
+			_ => false,
+		};
+		let q = state.as_mut().expect("query state was just set");
+		if q.attempt >= self.policy.retries {
+			*state = None;
+			if let (true, Some(cancel)) = (timed_out, self.cancel) {
+				cancel(&self.dns);
+			}
+			return Err(nb::Error::Other(error));
+		}
+		q.attempt += 1;
+		q.started_at = now;
+		if let (true, Some(cancel)) = (timed_out, self.cancel) {
+			cancel(&self.dns);
+		}
+		if let Some(rotate) = self.rotate {
+			rotate(&self.dns);
+		}
+		Err(nb::Error::WouldBlock)
+	}
+}
+
+impl<D, C> RetryingDns<D, C>
+where
+	D: DnsConfig,
+	C: Clock,
+{
+	/// Wrap `dns` like [`new`](#method.new), additionally swapping its primary
+	/// and secondary resolver addresses before each retry.
+	pub fn with_rotation(dns: D, clock: C, policy: RetryPolicy) -> Self {
+		let mut retrying = Self::new(dns, clock, policy);
+		retrying.rotate = Some(swap_servers::<D>);
+		retrying
+	}
+}
+
+impl<D, C> Dns for RetryingDns<D, C>
+where
+	D: Dns,
+	C: Clock,
+{
+	type Error = RetryError<D::Error>;
+
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error> {
+		self.retry(hostname, &addr_type, |dns| {
+			dns.get_host_by_name(hostname, addr_type.clone())
+		})
+	}
+
+	fn get_host_by_name_with_ttl(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<(IpAddr, Option<u32>), Self::Error> {
+		self.retry(hostname, &addr_type, |dns| {
+			dns.get_host_by_name_with_ttl(hostname, addr_type.clone())
+		})
+	}
+
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		self.retry(hostname, &addr_type, |dns| {
+			dns.get_hosts_by_name(hostname, addr_type.clone(), out)
+		})
+	}
+
+	fn get_host_by_address(&self, addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error> {
+		self.dns.get_host_by_address(addr).map_err(|e| match e {
+			nb::Error::WouldBlock => nb::Error::WouldBlock,
+			nb::Error::Other(e) => nb::Error::Other(RetryError::Dns(e)),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::cell::Cell;
+
+	/// A resolver whose queries never complete.
+	#[derive(Default)]
+	struct Stuck {
+		queries: Cell<usize>,
+		cancelled: Cell<usize>,
+	}
+
+	impl Dns for Stuck {
+		type Error = DnsErrorKind;
+
+		fn get_host_by_name(
+			&self,
+			_hostname: &str,
+			_addr_type: AddrType,
+		) -> nb::Result<IpAddr, Self::Error> {
+			self.queries.set(self.queries.get() + 1);
+			Err(nb::Error::WouldBlock)
+		}
+
+		fn get_host_by_address(
+			&self,
+			_addr: IpAddr,
+		) -> nb::Result<String<consts::U256>, Self::Error> {
+			Err(nb::Error::Other(DnsErrorKind::Other))
+		}
+	}
+
+	struct Manual(Cell<u64>);
+
+	impl Clock for Manual {
+		fn now_ms(&self) -> u64 {
+			self.0.get()
+		}
+	}
+
+	#[test]
+	fn timeouts_cancel_and_are_tracked_per_address_type() {
+		let clock = Manual(Cell::new(0));
+		let policy = RetryPolicy {
+			timeout: Duration::from_millis(100),
+			retries: 1,
+		};
+		let mut dns = RetryingDns::new(Stuck::default(), &clock, policy);
+		dns.set_cancel(Some(|dns: &Stuck| {
+			dns.cancelled.set(dns.cancelled.get() + 1)
+		}));
+		let query = |addr_type| dns.get_host_by_name("example.com", addr_type);
+
+		assert_eq!(query(AddrType::IPv4), Err(nb::Error::WouldBlock));
+		clock.0.set(90);
+		// Another address type is another query, started afresh.
+		assert_eq!(query(AddrType::IPv6), Err(nb::Error::WouldBlock));
+		clock.0.set(150);
+		assert_eq!(query(AddrType::IPv6), Err(nb::Error::WouldBlock));
+		assert_eq!(dns.inner().cancelled.get(), 0);
+
+		clock.0.set(200);
+		assert_eq!(query(AddrType::IPv6), Err(nb::Error::WouldBlock));
+		assert_eq!(dns.inner().cancelled.get(), 1);
+		clock.0.set(300);
+		assert_eq!(
+			query(AddrType::IPv6),
+			Err(nb::Error::Other(RetryError::Timeout))
+		);
+		assert_eq!(dns.inner().cancelled.get(), 2);
+		assert_eq!(dns.inner().queries.get(), 5);
+	}
+
+	#[test]
+	fn overlong_names_are_rejected() {
+		let policy = RetryPolicy {
+			timeout: Duration::from_millis(100),
+			retries: 1,
+		};
+		let dns = RetryingDns::new(Stuck::default(), Manual(Cell::new(0)), policy);
+		let name = core::str::from_utf8(&[b'a'; 257]).unwrap();
+		assert_eq!(
+			dns.get_host_by_name(name, AddrType::Either),
+			Err(nb::Error::Other(RetryError::NameTooLong))
+		);
+		assert_eq!(dns.inner().queries.get(), 0);
+	}
+
+	#[test]
+	fn clock_going_backwards_keeps_waiting() {
+		let clock = Manual(Cell::new(1000));
+		let policy = RetryPolicy {
+			timeout: Duration::from_millis(100),
+			retries: 0,
+		};
+		let dns = RetryingDns::new(Stuck::default(), &clock, policy);
+		assert_eq!(
+			dns.get_host_by_name("example.com", AddrType::IPv4),
+			Err(nb::Error::WouldBlock)
+		);
+		clock.0.set(500);
+		assert_eq!(
+			dns.get_host_by_name("example.com", AddrType::IPv4),
+			Err(nb::Error::WouldBlock)
+		);
+		clock.0.set(1100);
+		assert_eq!(
+			dns.get_host_by_name("example.com", AddrType::IPv4),
+			Err(nb::Error::Other(RetryError::Timeout))
+		);
+	}
+}
@@ -0,0 +1,304 @@
+use crate::{AddrType, Dns, DnsError, DnsErrorKind};
+use heapless::{consts, String};
+use no_std_net::IpAddr;
+
+/// The longest domain name, in dotted notation without the trailing dot.
+const MAX_NAME_LEN: usize = 253;
+const MAX_LABEL_LEN: usize = 63;
+
+/// Why a hostname was rejected by [`validate_hostname`].
+///
+/// [`validate_hostname`]: fn.validate_hostname.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HostnameError {
+	/// The hostname is empty
+	Empty,
+	/// The hostname is longer than 253 bytes, not counting a trailing dot
+	TooLong,
+	/// A label is longer than 63 bytes
+	LabelTooLong,
+	/// A label is empty, as in `a..b` or `.a`
+	EmptyLabel,
+	/// A label contains something other than ASCII letters, digits, hyphens
+	/// and underscores
+	InvalidCharacter,
+	/// A label starts or ends with a hyphen
+	InvalidHyphen,
+}
+
+impl DnsError for HostnameError {
+	fn kind(&self) -> DnsErrorKind {
+		match self {
+			HostnameError::TooLong | HostnameError::LabelTooLong => DnsErrorKind::NameTooLong,
+			_ => DnsErrorKind::InvalidName,
+		}
+	}
+}
+
+impl core::fmt::Display for HostnameError {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			HostnameError::Empty => f.write_str("the hostname is empty"),
+			HostnameError::TooLong => f.write_str("the hostname is longer than 253 bytes"),
+			HostnameError::LabelTooLong => f.write_str("a label is longer than 63 bytes"),
+			HostnameError::EmptyLabel => f.write_str("a label is empty"),
+			HostnameError::InvalidCharacter => f.write_str("a label contains an invalid character"),
+			HostnameError::InvalidHyphen => f.write_str("a label starts or ends with a hyphen"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for HostnameError {}
+
+/// Check that `hostname` is a well formed domain name: at most 253 bytes, not
+/// counting an optional trailing dot, made of dot-separated labels of 1 to 63
+/// letters, digits, hyphens and underscores, that don't start or end with a
+/// hyphen ([`rfc1123`]). Underscores are not valid in hostnames, but are
+/// accepted since they are common in practice.
+///
+/// [`rfc1123`]: https://tools.ietf.org/html/rfc1123#page-13
+pub fn validate_hostname(hostname: &str) -> Result<(), HostnameError> {
+	let name = if hostname.ends_with('.') {
+		&hostname[..hostname.len() - 1]
+	} else {
+		hostname
+	};
+	if name.is_empty() {
+		return Err(HostnameError::Empty);
+	}
+	if name.len() > MAX_NAME_LEN {
+		return Err(HostnameError::TooLong);
+	}
+	for label in name.split('.') {
+		let bytes = label.as_bytes();
+		if bytes.is_empty() {
+			return Err(HostnameError::EmptyLabel);
+		}
+		if bytes.len() > MAX_LABEL_LEN {
+			return Err(HostnameError::LabelTooLong);
+		}
+		if !bytes
+			.iter()
+			.all(|&b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+		{
+			return Err(HostnameError::InvalidCharacter);
+		}
+		if bytes[0] == b'-' || bytes[bytes.len() - 1] == b'-' {
+			return Err(HostnameError::InvalidHyphen);
+		}
+	}
+	Ok(())
+}
+
+/// The error type of a [`ValidatingDns`].
+///
+/// [`ValidatingDns`]: struct.ValidatingDns.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidatingDnsError<E> {
+	/// The hostname was rejected before reaching the wrapped resolver
+	InvalidHostname(HostnameError),
+	/// The wrapped resolver failed
+	Dns(E),
+}
+
+impl<E: DnsError> DnsError for ValidatingDnsError<E> {
+	fn kind(&self) -> DnsErrorKind {
+		match self {
+			ValidatingDnsError::InvalidHostname(e) => e.kind(),
+			ValidatingDnsError::Dns(e) => e.kind(),
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for ValidatingDnsError<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			ValidatingDnsError::InvalidHostname(e) => write!(f, "invalid hostname: {}", e),
+			ValidatingDnsError::Dns(e) => write!(f, "name resolution failed: {}", e),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for ValidatingDnsError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			ValidatingDnsError::InvalidHostname(e) => Some(e),
+			ValidatingDnsError::Dns(e) => Some(e),
+		}
+	}
+}
+
+/// A [`Dns`] wrapper that checks every hostname with [`validate_hostname`]
+/// before handing it to the wrapped resolver, so that malformed names fail
+/// the same way on every stack instead of with driver specific errors.
+///
+/// [`Dns`]: trait.Dns.html
+/// [`validate_hostname`]: fn.validate_hostname.html
+pub struct ValidatingDns<D> {
+	dns: D,
+}
+
+impl<D: Dns> ValidatingDns<D> {
+	/// Wrap `dns`.
+	pub fn new(dns: D) -> Self {
+		ValidatingDns { dns }
+	}
+
+	/// Get a reference to the wrapped resolver.
+	pub fn inner(&self) -> &D {
+		&self.dns
+	}
+
+	/// Destroy the wrapper, returning the wrapped resolver.
+	pub fn into_inner(self) -> D {
+		self.dns
+	}
+}
+
+fn check<E>(hostname: &str) -> nb::Result<(), ValidatingDnsError<E>> {
+	validate_hostname(hostname)
+		.map_err(|e| nb::Error::Other(ValidatingDnsError::InvalidHostname(e)))
+}
+
+fn wrap<T, E>(result: nb::Result<T, E>) -> nb::Result<T, ValidatingDnsError<E>> {
+	result.map_err(|e| match e {
+		nb::Error::WouldBlock => nb::Error::WouldBlock,
+		nb::Error::Other(e) => nb::Error::Other(ValidatingDnsError::Dns(e)),
+	})
+}
+
+impl<D: Dns> Dns for ValidatingDns<D> {
+	type Error = ValidatingDnsError<D::Error>;
+
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error> {
+		check(hostname)?;
+		wrap(self.dns.get_host_by_name(hostname, addr_type))
+	}
+
+	fn get_host_by_name_with_ttl(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<(IpAddr, Option<u32>), Self::Error> {
+		check(hostname)?;
+		wrap(self.dns.get_host_by_name_with_ttl(hostname, addr_type))
+	}
+
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		check(hostname)?;
+		wrap(self.dns.get_hosts_by_name(hostname, addr_type, out))
+	}
+
+	fn get_host_by_address(&self, addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error> {
+		wrap(self.dns.get_host_by_address(addr))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::LoopbackStack;
+
+	/// Write a name of labels of `a`s of the given lengths into `buffer`.
+	fn name<'a>(buffer: &'a mut [u8; 300], labels: &[usize]) -> &'a str {
+		let mut len = 0;
+		for (i, &label) in labels.iter().enumerate() {
+			if i > 0 {
+				buffer[len] = b'.';
+				len += 1;
+			}
+			for b in &mut buffer[len..len + label] {
+				*b = b'a';
+			}
+			len += label;
+		}
+		core::str::from_utf8(&buffer[..len]).unwrap()
+	}
+
+	#[test]
+	fn hostnames_are_checked() {
+		let cases = [
+			("example.com", Ok(())),
+			("example.com.", Ok(())),
+			("a", Ok(())),
+			("_dmarc.example.com", Ok(())),
+			("xn--bcher-kva.example", Ok(())),
+			("", Err(HostnameError::Empty)),
+			(".", Err(HostnameError::Empty)),
+			("a..b", Err(HostnameError::EmptyLabel)),
+			(".example.com", Err(HostnameError::EmptyLabel)),
+			("example.com..", Err(HostnameError::EmptyLabel)),
+			("-example.com", Err(HostnameError::InvalidHyphen)),
+			("example-.com", Err(HostnameError::InvalidHyphen)),
+			("example.com-", Err(HostnameError::InvalidHyphen)),
+			("exa mple.com", Err(HostnameError::InvalidCharacter)),
+			("exämple.com", Err(HostnameError::InvalidCharacter)),
+		];
+		for &(hostname, expected) in &cases {
+			assert_eq!(validate_hostname(hostname), expected, "{:?}", hostname);
+		}
+	}
+
+	#[test]
+	fn lengths_are_checked() {
+		let cases: [(&[usize], _); 5] = [
+			(&[63, 3], Ok(())),
+			(&[64, 3], Err(HostnameError::LabelTooLong)),
+			// 253 bytes, then 254.
+			(&[63, 63, 63, 61], Ok(())),
+			(&[63, 63, 63, 62], Err(HostnameError::TooLong)),
+			(&[63, 63, 63, 63, 1], Err(HostnameError::TooLong)),
+		];
+		for &(labels, expected) in &cases {
+			let mut buffer = [0; 300];
+			let hostname = name(&mut buffer, labels);
+			assert_eq!(validate_hostname(hostname), expected, "{:?}", labels);
+		}
+
+		// The trailing dot doesn't count towards the length.
+		let mut buffer = [0; 300];
+		buffer[253] = b'.';
+		name(&mut buffer, &[63, 63, 63, 61]);
+		let hostname = core::str::from_utf8(&buffer[..254]).unwrap();
+		assert_eq!(validate_hostname(hostname), Ok(()));
+	}
+
+	#[test]
+	fn invalid_names_dont_reach_the_resolver() {
+		let hosts = LoopbackStack::new();
+		hosts
+			.add_host("example.com", IpAddr::from([192, 0, 2, 1]))
+			.unwrap();
+		let dns = ValidatingDns::new(hosts);
+		assert_eq!(
+			dns.get_host_by_name("example.com", AddrType::IPv4),
+			Ok(IpAddr::from([192, 0, 2, 1]))
+		);
+		let error = dns
+			.get_host_by_name("-example.com", AddrType::IPv4)
+			.unwrap_err();
+		assert_eq!(
+			error,
+			nb::Error::Other(ValidatingDnsError::InvalidHostname(
+				HostnameError::InvalidHyphen
+			))
+		);
+		match dns.get_host_by_name("unknown.com", AddrType::IPv4) {
+			Err(nb::Error::Other(ValidatingDnsError::Dns(e))) => {
+				assert_eq!(e.kind(), DnsErrorKind::NxDomain)
+			}
+			result => panic!("unexpected result {:?}", result),
+		}
+	}
+}
@@ -0,0 +1,574 @@
+use crate::{
+	DatagramBuf, Mode, NetworkErrorKind, RecvOutcome, TcpError, TcpStack, UdpError, UdpStack,
+};
+use core::cell::RefCell;
+use heapless::{ArrayLength, Vec};
+use no_std_net::SocketAddr;
+
+/// The error type of the [`AnyTcpStack`] and [`AnyUdpStack`] traits.
+///
+/// [`AnyTcpStack`]: trait.AnyTcpStack.html
+/// [`AnyUdpStack`]: trait.AnyUdpStack.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnyError {
+	/// The socket is not known to the stack, or was closed
+	InvalidSocket,
+	/// The stack can't hold more sockets
+	Exhausted,
+	/// The underlying stack failed, with an error of the given kind
+	Stack(NetworkErrorKind),
+}
+
+impl TcpError for AnyError {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			AnyError::InvalidSocket => NetworkErrorKind::InvalidInput,
+			AnyError::Exhausted => NetworkErrorKind::Exhausted,
+			AnyError::Stack(kind) => *kind,
+		}
+	}
+}
+
+impl UdpError for AnyError {
+	fn kind(&self) -> NetworkErrorKind {
+		TcpError::kind(self)
+	}
+}
+
+impl core::fmt::Display for AnyError {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			AnyError::InvalidSocket => f.write_str("the socket is not known to the stack"),
+			AnyError::Exhausted => f.write_str("all sockets are in use"),
+			AnyError::Stack(kind) => write!(f, "the stack failed: {}", kind),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AnyError {}
+
+/// A socket of an [`AnyTcpStack`] or [`AnyUdpStack`].
+///
+/// [`AnyTcpStack`]: trait.AnyTcpStack.html
+/// [`AnyUdpStack`]: trait.AnyUdpStack.html
+#[derive(Debug, PartialEq)]
+pub struct SocketHandle {
+	index: u8,
+	generation: u16,
+}
+
+/// A socket of one of several [`AnyTcpStack`]s or [`AnyUdpStack`]s, tagged
+/// with which, so that one connection table can hold the sockets of every
+/// stack in use.
+///
+/// The tag is chosen by the application, usually as the index of the stack
+/// in an array of trait objects, as used by [`stack_in`](#method.stack_in).
+///
+/// [`AnyTcpStack`]: trait.AnyTcpStack.html
+/// [`AnyUdpStack`]: trait.AnyUdpStack.html
+#[derive(Debug, PartialEq)]
+pub struct AnySocket {
+	/// The tag of the stack the socket belongs to
+	pub stack: u8,
+	/// The socket
+	pub socket: SocketHandle,
+}
+
+impl AnySocket {
+	/// Tag `socket` as belonging to stack `stack`.
+	pub fn new(stack: u8, socket: SocketHandle) -> Self {
+		AnySocket { stack, socket }
+	}
+
+	/// The stack the socket belongs to, taking the tag as an index into
+	/// `stacks`, or `None` if it is out of range.
+	pub fn stack_in<'a, T: ?Sized>(&self, stacks: &[&'a T]) -> Option<&'a T> {
+		stacks.get(usize::from(self.stack)).cloned()
+	}
+}
+
+/// A version of [`TcpStack`] that can be used as a trait object, such as
+/// `&dyn AnyTcpStack`, so that applications can switch between stacks at
+/// runtime, as when failing over from Ethernet to cellular. Sockets are
+/// [`SocketHandle`]s and errors are [`AnyError`]s, whatever the stack.
+///
+/// [`ErasedTcp`] implements it for any [`TcpStack`].
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`SocketHandle`]: struct.SocketHandle.html
+/// [`AnyError`]: enum.AnyError.html
+/// [`ErasedTcp`]: struct.ErasedTcp.html
+pub trait AnyTcpStack {
+	/// Open a new TCP socket. The socket starts in the unconnected state.
+	fn open(&self, mode: Mode) -> Result<SocketHandle, AnyError>;
+
+	/// Connect to the given remote host and port. If this fails, the socket
+	/// is closed.
+	fn connect(&self, socket: SocketHandle, remote: SocketAddr) -> Result<SocketHandle, AnyError>;
+
+	/// Check if this socket is connected
+	fn is_connected(&self, socket: &SocketHandle) -> Result<bool, AnyError>;
+
+	/// Write to the stream. Returns the number of bytes written is returned
+	/// (which may be less than `buffer.len()`), or an error.
+	fn write(&self, socket: &mut SocketHandle, buffer: &[u8]) -> nb::Result<usize, AnyError>;
+
+	/// Read from the stream. Returns `Ok(n)`, which means `n` bytes of
+	/// data have been received and they have been placed in
+	/// `&buffer[0..n]`, or an error.
+	fn read(&self, socket: &mut SocketHandle, buffer: &mut [u8]) -> nb::Result<usize, AnyError>;
+
+	/// Read from the stream, telling data apart from the end of the stream
+	/// and from a reset connection, as [`TcpStack::receive`] does. The
+	/// default implementation is the same as that of `TcpStack`.
+	///
+	/// [`TcpStack::receive`]: trait.TcpStack.html#method.receive
+	fn receive(
+		&self,
+		socket: &mut SocketHandle,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, AnyError> {
+		let empty = buffer.is_empty();
+		match self.read(socket, buffer) {
+			Ok(0) if !empty => Ok(RecvOutcome::Closed),
+			Ok(n) => Ok(RecvOutcome::Data(n)),
+			Err(nb::Error::Other(AnyError::Stack(NetworkErrorKind::ConnectionReset))) => {
+				Ok(RecvOutcome::Reset)
+			}
+			Err(e) => Err(e),
+		}
+	}
+
+	/// Close an existing TCP socket.
+	fn close(&self, socket: SocketHandle) -> Result<(), AnyError>;
+}
+
+/// A version of [`UdpStack`] that can be used as a trait object, like
+/// [`AnyTcpStack`] is for TCP. [`ErasedUdp`] implements it for any
+/// [`UdpStack`].
+///
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`AnyTcpStack`]: trait.AnyTcpStack.html
+/// [`ErasedUdp`]: struct.ErasedUdp.html
+pub trait AnyUdpStack {
+	/// Open a new UDP socket to the given address and port.
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<SocketHandle, AnyError>;
+
+	/// Send a datagram to the remote host.
+	fn write(&self, socket: &mut SocketHandle, buffer: &[u8]) -> nb::Result<(), AnyError>;
+
+	/// Send several datagrams to the remote host in one call, as
+	/// [`UdpStack::write_batch`] does. The default implementation sends them
+	/// one by one.
+	///
+	/// [`UdpStack::write_batch`]: trait.UdpStack.html#method.write_batch
+	fn write_batch(
+		&self,
+		socket: &mut SocketHandle,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, AnyError> {
+		for (sent, datagram) in datagrams.iter().enumerate() {
+			match self.write(socket, datagram) {
+				Ok(()) => {}
+				Err(e) if sent == 0 => return Err(e),
+				Err(_) => return Ok(sent),
+			}
+		}
+		Ok(datagrams.len())
+	}
+
+	/// Read a datagram the remote host has sent to us. Returns `Ok(n)`, which
+	/// means a datagram of size `n` has been received and it has been placed
+	/// in `&buffer[0..n]`, or an error.
+	fn read(&self, socket: &mut SocketHandle, buffer: &mut [u8]) -> nb::Result<usize, AnyError>;
+
+	/// Read several datagrams the remote host has sent to us in one call, as
+	/// [`UdpStack::read_batch`] does. The default implementation reads them
+	/// one by one.
+	///
+	/// [`UdpStack::read_batch`]: trait.UdpStack.html#method.read_batch
+	fn read_batch(
+		&self,
+		socket: &mut SocketHandle,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, AnyError> {
+		for (received, datagram) in datagrams.iter_mut().enumerate() {
+			match self.read(socket, datagram.buffer) {
+				Ok(len) => datagram.len = len,
+				Err(e) if received == 0 => return Err(e),
+				Err(_) => return Ok(received),
+			}
+		}
+		Ok(datagrams.len())
+	}
+
+	/// Close an existing UDP socket.
+	fn close(&self, socket: SocketHandle) -> Result<(), AnyError>;
+}
+
+/// One socket held by an [`ErasedTcp`] or [`ErasedUdp`]. This is only public
+/// so that the capacity of the adapter can be named, as in
+/// `ErasedTcp<S, consts::U4>`.
+///
+/// [`ErasedTcp`]: struct.ErasedTcp.html
+/// [`ErasedUdp`]: struct.ErasedUdp.html
+pub struct ErasedSlot<T> {
+	socket: Option<T>,
+	/// How many times the slot has been freed
+	generation: u16,
+}
+
+/// The sockets of an adapter, which it hands out handles to.
+struct Table<T, N: ArrayLength<ErasedSlot<T>>> {
+	slots: RefCell<Vec<ErasedSlot<T>, N>>,
+}
+
+impl<T, N: ArrayLength<ErasedSlot<T>>> Table<T, N> {
+	fn new() -> Self {
+		Table {
+			slots: RefCell::new(Vec::new()),
+		}
+	}
+
+	/// Put `socket` in a free slot, or hand it back if there is none.
+	fn insert(&self, socket: T) -> Result<SocketHandle, T> {
+		let mut slots = self.slots.borrow_mut();
+		let index = match slots.iter().position(|slot| slot.socket.is_none()) {
+			Some(index) => {
+				slots[index].socket = Some(socket);
+				index
+			}
+			None => {
+				// Handles only have room for 256 indices.
+				if slots.len() > usize::from(core::u8::MAX) {
+					return Err(socket);
+				}
+				let slot = ErasedSlot {
+					socket: Some(socket),
+					generation: 0,
+				};
+				if let Err(slot) = slots.push(slot) {
+					return Err(slot.socket.unwrap());
+				}
+				slots.len() - 1
+			}
+		};
+		Ok(SocketHandle {
+			index: index as u8,
+			generation: slots[index].generation,
+		})
+	}
+
+	/// Call `f` with the socket of `handle`, if it is still open.
+	fn with<R>(&self, handle: &SocketHandle, f: impl FnOnce(&mut T) -> R) -> Result<R, AnyError> {
+		let mut slots = self.slots.borrow_mut();
+		match slots.get_mut(usize::from(handle.index)) {
+			Some(ErasedSlot {
+				socket: Some(socket),
+				generation,
+			}) if *generation == handle.generation => Ok(f(socket)),
+			_ => Err(AnyError::InvalidSocket),
+		}
+	}
+
+	/// Take the socket of `handle` out of the table, freeing its slot.
+	fn remove(&self, handle: &SocketHandle) -> Result<T, AnyError> {
+		let mut slots = self.slots.borrow_mut();
+		match slots.get_mut(usize::from(handle.index)) {
+			Some(slot) if slot.generation == handle.generation && slot.socket.is_some() => {
+				slot.generation = slot.generation.wrapping_add(1);
+				Ok(slot.socket.take().unwrap())
+			}
+			_ => Err(AnyError::InvalidSocket),
+		}
+	}
+}
+
+fn stack_error<T, E>(
+	result: nb::Result<T, E>,
+	kind: fn(&E) -> NetworkErrorKind,
+) -> nb::Result<T, AnyError> {
+	result.map_err(|e| match e {
+		nb::Error::WouldBlock => nb::Error::WouldBlock,
+		nb::Error::Other(e) => nb::Error::Other(AnyError::Stack(kind(&e))),
+	})
+}
+
+/// An adapter implementing [`AnyTcpStack`] for a [`TcpStack`], holding up
+/// to `N` of its sockets at a time.
+///
+/// [`AnyTcpStack`]: trait.AnyTcpStack.html
+/// [`TcpStack`]: trait.TcpStack.html
+pub struct ErasedTcp<'a, S, N>
+where
+	S: TcpStack,
+	N: ArrayLength<ErasedSlot<S::TcpSocket>>,
+{
+	stack: &'a S,
+	sockets: Table<S::TcpSocket, N>,
+}
+
+impl<'a, S, N> ErasedTcp<'a, S, N>
+where
+	S: TcpStack,
+	N: ArrayLength<ErasedSlot<S::TcpSocket>>,
+{
+	/// Adapt `stack`, with no socket open.
+	pub fn new(stack: &'a S) -> Self {
+		ErasedTcp {
+			stack,
+			sockets: Table::new(),
+		}
+	}
+
+	/// Get a reference to the adapted stack.
+	pub fn inner(&self) -> &S {
+		self.stack
+	}
+}
+
+impl<'a, S, N> AnyTcpStack for ErasedTcp<'a, S, N>
+where
+	S: TcpStack,
+	N: ArrayLength<ErasedSlot<S::TcpSocket>>,
+{
+	fn open(&self, mode: Mode) -> Result<SocketHandle, AnyError> {
+		let socket = self
+			.stack
+			.open(mode)
+			.map_err(|e| AnyError::Stack(e.kind()))?;
+		self.sockets.insert(socket).map_err(|socket| {
+			self.stack.close(socket).ok();
+			AnyError::Exhausted
+		})
+	}
+
+	fn connect(&self, socket: SocketHandle, remote: SocketAddr) -> Result<SocketHandle, AnyError> {
+		let inner = self.sockets.remove(&socket)?;
+		let inner = self
+			.stack
+			.connect(inner, remote)
+			.map_err(|e| AnyError::Stack(e.kind()))?;
+		// The slot the socket was taken from is free again.
+		self.sockets.insert(inner).map_err(|socket| {
+			self.stack.close(socket).ok();
+			AnyError::Exhausted
+		})
+	}
+
+	fn is_connected(&self, socket: &SocketHandle) -> Result<bool, AnyError> {
+		self.sockets
+			.with(socket, |socket| self.stack.is_connected(socket))?
+			.map_err(|e| AnyError::Stack(e.kind()))
+	}
+
+	fn write(&self, socket: &mut SocketHandle, buffer: &[u8]) -> nb::Result<usize, AnyError> {
+		stack_error(
+			self.sockets
+				.with(socket, |socket| self.stack.write(socket, buffer))?,
+			TcpError::kind,
+		)
+	}
+
+	fn read(&self, socket: &mut SocketHandle, buffer: &mut [u8]) -> nb::Result<usize, AnyError> {
+		stack_error(
+			self.sockets
+				.with(socket, |socket| self.stack.read(socket, buffer))?,
+			TcpError::kind,
+		)
+	}
+
+	fn receive(
+		&self,
+		socket: &mut SocketHandle,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, AnyError> {
+		stack_error(
+			self.sockets
+				.with(socket, |socket| self.stack.receive(socket, buffer))?,
+			TcpError::kind,
+		)
+	}
+
+	fn close(&self, socket: SocketHandle) -> Result<(), AnyError> {
+		let socket = self.sockets.remove(&socket)?;
+		self.stack
+			.close(socket)
+			.map_err(|e| AnyError::Stack(e.kind()))
+	}
+}
+
+/// An adapter implementing [`AnyUdpStack`] for a [`UdpStack`], holding up
+/// to `N` of its sockets at a time.
+///
+/// [`AnyUdpStack`]: trait.AnyUdpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+pub struct ErasedUdp<'a, S, N>
+where
+	S: UdpStack,
+	N: ArrayLength<ErasedSlot<S::UdpSocket>>,
+{
+	stack: &'a S,
+	sockets: Table<S::UdpSocket, N>,
+}
+
+impl<'a, S, N> ErasedUdp<'a, S, N>
+where
+	S: UdpStack,
+	N: ArrayLength<ErasedSlot<S::UdpSocket>>,
+{
+	/// Adapt `stack`, with no socket open.
+	pub fn new(stack: &'a S) -> Self {
+		ErasedUdp {
+			stack,
+			sockets: Table::new(),
+		}
+	}
+
+	/// Get a reference to the adapted stack.
+	pub fn inner(&self) -> &S {
+		self.stack
+	}
+}
+
+impl<'a, S, N> AnyUdpStack for ErasedUdp<'a, S, N>
+where
+	S: UdpStack,
+	N: ArrayLength<ErasedSlot<S::UdpSocket>>,
+{
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<SocketHandle, AnyError> {
+		let socket = self
+			.stack
+			.open(remote, mode)
+			.map_err(|e| AnyError::Stack(e.kind()))?;
+		self.sockets.insert(socket).map_err(|socket| {
+			self.stack.close(socket).ok();
+			AnyError::Exhausted
+		})
+	}
+
+	fn write(&self, socket: &mut SocketHandle, buffer: &[u8]) -> nb::Result<(), AnyError> {
+		stack_error(
+			self.sockets
+				.with(socket, |socket| self.stack.write(socket, buffer))?,
+			UdpError::kind,
+		)
+	}
+
+	fn write_batch(
+		&self,
+		socket: &mut SocketHandle,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, AnyError> {
+		stack_error(
+			self.sockets
+				.with(socket, |socket| self.stack.write_batch(socket, datagrams))?,
+			UdpError::kind,
+		)
+	}
+
+	fn read(&self, socket: &mut SocketHandle, buffer: &mut [u8]) -> nb::Result<usize, AnyError> {
+		stack_error(
+			self.sockets
+				.with(socket, |socket| self.stack.read(socket, buffer))?,
+			UdpError::kind,
+		)
+	}
+
+	fn read_batch(
+		&self,
+		socket: &mut SocketHandle,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, AnyError> {
+		stack_error(
+			self.sockets
+				.with(socket, |socket| self.stack.read_batch(socket, datagrams))?,
+			UdpError::kind,
+		)
+	}
+
+	fn close(&self, socket: SocketHandle) -> Result<(), AnyError> {
+		let socket = self.sockets.remove(&socket)?;
+		self.stack
+			.close(socket)
+			.map_err(|e| AnyError::Stack(e.kind()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Call, Expectation, MockStack};
+	use heapless::consts;
+
+	fn remote() -> SocketAddr {
+		SocketAddr::new([192, 0, 2, 1].into(), 80)
+	}
+
+	#[test]
+	fn stale_handles_are_rejected() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpClose),
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpClose),
+		];
+		let stack = MockStack::new(&script);
+		let erased = ErasedTcp::<_, consts::U1>::new(&stack);
+		let first = erased.open(Mode::Blocking).unwrap();
+		erased.close(first).unwrap();
+
+		// The slot is reused, under a new generation.
+		let second = erased.open(Mode::Blocking).unwrap();
+		let stale = SocketHandle {
+			index: second.index,
+			generation: second.generation - 1,
+		};
+		assert_eq!(erased.is_connected(&stale), Err(AnyError::InvalidSocket));
+		assert_eq!(erased.close(stale), Err(AnyError::InvalidSocket));
+		assert_eq!(erased.is_connected(&second), Ok(false));
+		erased.close(second).unwrap();
+		assert!(stack.remaining().is_empty());
+	}
+
+	#[test]
+	fn failed_connects_free_their_slot() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(remote())).fail(),
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(remote())),
+		];
+		let stack = MockStack::new(&script);
+		let erased = ErasedTcp::<_, consts::U1>::new(&stack);
+		let socket = erased.open(Mode::Blocking).unwrap();
+		assert_eq!(
+			erased.connect(socket, remote()),
+			Err(AnyError::Stack(NetworkErrorKind::Other))
+		);
+
+		let socket = erased.open(Mode::Blocking).unwrap();
+		let socket = erased.connect(socket, remote()).unwrap();
+		assert_eq!(erased.is_connected(&socket), Ok(true));
+		assert!(stack.remaining().is_empty());
+	}
+
+	#[test]
+	fn full_tables_are_exhausted() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpOpen),
+			// The socket there is no room for is closed again.
+			Expectation::new(Call::TcpClose),
+		];
+		let stack = MockStack::new(&script);
+		let erased = ErasedTcp::<_, consts::U2>::new(&stack);
+		let first = erased.open(Mode::Blocking).unwrap();
+		let second = erased.open(Mode::Blocking).unwrap();
+		assert_ne!(first, second);
+		assert_eq!(erased.open(Mode::Blocking), Err(AnyError::Exhausted));
+		assert!(stack.remaining().is_empty());
+	}
+}
@@ -0,0 +1,125 @@
+use crate::DnsErrorKind;
+
+/// The broad categories of network errors, so that retry policies and error
+/// reporting can be written once rather than for every driver's error type.
+///
+/// Implementations should map their errors to the most specific kind that
+/// applies, and [`Other`](#variant.Other) only when none does.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetworkErrorKind {
+	/// The operation can't complete yet; this is the kind of
+	/// `nb::Error::WouldBlock`
+	WouldBlock,
+	/// The operation did not complete in time
+	Timeout,
+	/// The remote host refused the connection
+	ConnectionRefused,
+	/// The remote host reset the connection
+	ConnectionReset,
+	/// The peer closed the connection, or it was shut down locally, so no
+	/// more data can be written to it
+	PipeClosed,
+	/// The socket is not connected
+	NotConnected,
+	/// Another socket already uses the local address
+	AddrInUse,
+	/// The remote host or network can't be reached, such as while the link
+	/// is down
+	Unreachable,
+	/// The stack has no socket or buffer left
+	Exhausted,
+	/// The operation or its arguments are not valid for the socket
+	InvalidInput,
+	/// A name could not be resolved
+	Dns(DnsErrorKind),
+	/// Any other error
+	Other,
+}
+
+impl NetworkErrorKind {
+	/// Whether the operation should be tried again later.
+	pub fn is_would_block(&self) -> bool {
+		*self == NetworkErrorKind::WouldBlock
+	}
+
+	/// Whether the operation, or the name resolution it needed, timed out.
+	pub fn is_timeout(&self) -> bool {
+		match self {
+			NetworkErrorKind::Timeout | NetworkErrorKind::Dns(DnsErrorKind::Timeout) => true,
+			_ => false,
+		}
+	}
+
+	/// Whether the connection was reset by the remote host.
+	pub fn is_connection_reset(&self) -> bool {
+		*self == NetworkErrorKind::ConnectionReset
+	}
+
+	/// Whether a name could not be resolved.
+	pub fn is_dns_failure(&self) -> bool {
+		match self {
+			NetworkErrorKind::Dns(_) => true,
+			_ => false,
+		}
+	}
+}
+
+impl From<DnsErrorKind> for NetworkErrorKind {
+	fn from(kind: DnsErrorKind) -> Self {
+		NetworkErrorKind::Dns(kind)
+	}
+}
+
+impl core::fmt::Display for NetworkErrorKind {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			NetworkErrorKind::WouldBlock => f.write_str("the operation would block"),
+			NetworkErrorKind::Timeout => f.write_str("the operation timed out"),
+			NetworkErrorKind::ConnectionRefused => f.write_str("connection refused"),
+			NetworkErrorKind::ConnectionReset => f.write_str("connection reset"),
+			NetworkErrorKind::PipeClosed => f.write_str("the connection is closed"),
+			NetworkErrorKind::NotConnected => f.write_str("the socket is not connected"),
+			NetworkErrorKind::AddrInUse => f.write_str("the address is in use"),
+			NetworkErrorKind::Unreachable => f.write_str("the host is unreachable"),
+			NetworkErrorKind::Exhausted => f.write_str("no socket or buffer left"),
+			NetworkErrorKind::InvalidInput => f.write_str("invalid argument"),
+			NetworkErrorKind::Dns(kind) => kind.fmt(f),
+			NetworkErrorKind::Other => f.write_str("network error"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for NetworkErrorKind {}
+
+/// This trait is implemented by the error types of [`TcpStack`]
+/// implementations, so that protocol crates can tell, whatever the stack, a
+/// connection the peer closed from one that failed otherwise.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+pub trait TcpError: core::fmt::Debug {
+	/// The category of this error
+	fn kind(&self) -> NetworkErrorKind;
+}
+
+/// This trait is implemented by the error types of [`UdpStack`]
+/// implementations, like [`TcpError`] is for TCP.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`TcpError`]: trait.TcpError.html
+pub trait UdpError: core::fmt::Debug {
+	/// The category of this error
+	fn kind(&self) -> NetworkErrorKind;
+}
+
+impl TcpError for NetworkErrorKind {
+	fn kind(&self) -> NetworkErrorKind {
+		*self
+	}
+}
+
+impl UdpError for NetworkErrorKind {
+	fn kind(&self) -> NetworkErrorKind {
+		*self
+	}
+}
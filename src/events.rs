@@ -0,0 +1,36 @@
+use no_std_net::IpAddr;
+
+/// A change in connectivity, as reported by [`NetworkEvents::poll_event`].
+///
+/// [`NetworkEvents::poll_event`]: trait.NetworkEvents.html#tymethod.poll_event
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetworkEvent {
+	/// The link came up, such as joining a Wi-Fi network or registering on a
+	/// cellular network
+	LinkUp,
+	/// The link went down; all sockets should be considered lost
+	LinkDown,
+	/// The given address was assigned, by DHCP, SLAAC or the cellular network
+	IpAcquired(IpAddr),
+	/// The given address was lost or its lease expired
+	IpLost(IpAddr),
+	/// The resolver addresses changed, see [`DnsConfig`]
+	///
+	/// [`DnsConfig`]: trait.DnsConfig.html
+	DnsChanged,
+}
+
+/// This trait is implemented by stacks that report changes in connectivity,
+/// so that reconnect logic can react to events instead of polling a dozen
+/// driver-specific status calls.
+pub trait NetworkEvents {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Take the oldest event that has not been taken yet, or return
+	/// `WouldBlock` if there is none.
+	///
+	/// Stacks only queue a limited number of events, and drop the oldest
+	/// ones if they aren't taken in time.
+	fn poll_event(&self) -> nb::Result<NetworkEvent, Self::Error>;
+}
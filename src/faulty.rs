@@ -0,0 +1,507 @@
+use crate::{
+	DatagramBuf, Mode, NetworkErrorKind, RecvOutcome, TcpError, TcpStack, UdpError, UdpStack,
+};
+use core::cell::Cell;
+use no_std_net::SocketAddr;
+
+/// How often a [`FaultyStack`] injects each kind of fault, as a rate per
+/// thousand calls. The default injects no faults.
+///
+/// [`FaultyStack`]: struct.FaultyStack.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FaultConfig {
+	/// Fail a call with [`FaultError::Injected`]. This applies to every call
+	///
+	/// [`FaultError::Injected`]: enum.FaultError.html#variant.Injected
+	pub error: u16,
+	/// The kind of the errors injected, such as
+	/// [`NetworkErrorKind::ConnectionReset`] to exercise reconnects. The
+	/// default is [`NetworkErrorKind::Other`]
+	///
+	/// [`NetworkErrorKind::ConnectionReset`]: enum.NetworkErrorKind.html#variant.ConnectionReset
+	/// [`NetworkErrorKind::Other`]: enum.NetworkErrorKind.html#variant.Other
+	pub error_kind: NetworkErrorKind,
+	/// Return `WouldBlock` from a read or write without calling the wrapped
+	/// stack, as if the data were delayed
+	pub delay: u16,
+	/// Silently drop a datagram that is written or read
+	pub drop: u16,
+	/// Only write or read part of the data: TCP writes and reads are cut
+	/// short, and datagrams lose their tail
+	pub truncate: u16,
+}
+
+impl Default for FaultConfig {
+	fn default() -> Self {
+		FaultConfig {
+			error: 0,
+			error_kind: NetworkErrorKind::Other,
+			delay: 0,
+			drop: 0,
+			truncate: 0,
+		}
+	}
+}
+
+/// The error type of a [`FaultyStack`].
+///
+/// [`FaultyStack`]: struct.FaultyStack.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum FaultError<E> {
+	/// The fault injector failed the call, with an error of the given kind
+	Injected(NetworkErrorKind),
+	/// The wrapped stack failed
+	Stack(E),
+}
+
+impl<E: TcpError> TcpError for FaultError<E> {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			FaultError::Injected(kind) => *kind,
+			FaultError::Stack(e) => e.kind(),
+		}
+	}
+}
+
+impl<E: UdpError> UdpError for FaultError<E> {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			FaultError::Injected(kind) => *kind,
+			FaultError::Stack(e) => e.kind(),
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for FaultError<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			FaultError::Injected(kind) => write!(f, "injected fault: {}", kind),
+			FaultError::Stack(e) => e.fmt(f),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for FaultError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			FaultError::Injected(_) => None,
+			FaultError::Stack(e) => Some(e),
+		}
+	}
+}
+
+/// A wrapper around a [`TcpStack`] and [`UdpStack`] that injects faults:
+/// errors, delays, dropped datagrams and truncated data. It lets host tests
+/// exercise the reconnect and retry logic of applications.
+///
+/// Faults are drawn from a pseudo-random sequence determined by the seed the
+/// stack is created with, so a test sees the same faults every run.
+/// [`fail_next`](#method.fail_next) forces the next call to fail, for tests
+/// that need a fault at a precise point. Batched UDP writes and reads draw
+/// their faults once for the whole batch: a dropped batch loses all of its
+/// datagrams, and only the datagrams read are truncated.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+pub struct FaultyStack<S> {
+	stack: S,
+	config: FaultConfig,
+	state: Cell<u32>,
+	fail_next: Cell<Option<NetworkErrorKind>>,
+}
+
+impl<S> FaultyStack<S> {
+	/// Wrap `stack`, injecting faults as configured by `config`, drawn from
+	/// the sequence determined by `seed`.
+	pub fn new(stack: S, config: FaultConfig, seed: u32) -> Self {
+		FaultyStack {
+			stack,
+			config,
+			// The generator gets stuck at zero.
+			state: Cell::new(if seed == 0 { 0x9E37_79B9 } else { seed }),
+			fail_next: Cell::new(None),
+		}
+	}
+
+	/// Change how often faults are injected.
+	pub fn set_config(&mut self, config: FaultConfig) {
+		self.config = config;
+	}
+
+	/// Fail the next call with [`FaultError::Injected`] of kind `kind`,
+	/// whatever the configuration.
+	///
+	/// [`FaultError::Injected`]: enum.FaultError.html#variant.Injected
+	pub fn fail_next(&self, kind: NetworkErrorKind) {
+		self.fail_next.set(Some(kind));
+	}
+
+	/// Get a reference to the wrapped stack.
+	pub fn inner(&self) -> &S {
+		&self.stack
+	}
+
+	/// Destroy the wrapper, returning the wrapped stack.
+	pub fn into_inner(self) -> S {
+		self.stack
+	}
+
+	/// The next number of the xorshift32 sequence.
+	fn next_random(&self) -> u32 {
+		let mut x = self.state.get();
+		x ^= x << 13;
+		x ^= x >> 17;
+		x ^= x << 5;
+		self.state.set(x);
+		x
+	}
+
+	/// Decide whether to inject a fault that happens `rate` times per
+	/// thousand calls.
+	fn happens(&self, rate: u16) -> bool {
+		rate > 0 && self.next_random() % 1000 < u32::from(rate)
+	}
+
+	/// A length between 1 and `len - 1` to truncate `len` bytes to, or `len`
+	/// if they can't be truncated.
+	fn truncated(&self, len: usize) -> usize {
+		if len > 1 && self.happens(self.config.truncate) {
+			1 + self.next_random() as usize % (len - 1)
+		} else {
+			len
+		}
+	}
+
+	fn check<E>(&self) -> Result<(), FaultError<E>> {
+		if let Some(kind) = self.fail_next.take() {
+			return Err(FaultError::Injected(kind));
+		}
+		if self.happens(self.config.error) {
+			return Err(FaultError::Injected(self.config.error_kind));
+		}
+		Ok(())
+	}
+
+	fn check_io<E>(&self) -> nb::Result<(), FaultError<E>> {
+		self.check()?;
+		if self.happens(self.config.delay) {
+			return Err(nb::Error::WouldBlock);
+		}
+		Ok(())
+	}
+}
+
+fn stack_error<E>(e: nb::Error<E>) -> nb::Error<FaultError<E>> {
+	e.map(FaultError::Stack)
+}
+
+impl<S: TcpStack> TcpStack for FaultyStack<S> {
+	type TcpSocket = S::TcpSocket;
+	type Error = FaultError<S::Error>;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		self.check()?;
+		self.stack.open(mode).map_err(FaultError::Stack)
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		if let Err(e) = self.check() {
+			// The socket is consumed either way, so don't leak it.
+			TcpStack::close(&self.stack, socket).ok();
+			return Err(e);
+		}
+		self.stack
+			.connect(socket, remote)
+			.map_err(FaultError::Stack)
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.check()?;
+		self.stack.is_connected(socket).map_err(FaultError::Stack)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		self.check_io()?;
+		let len = self.truncated(buffer.len());
+		self.stack
+			.write(socket, &buffer[..len])
+			.map_err(stack_error)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		self.check_io()?;
+		let len = self.truncated(buffer.len());
+		TcpStack::read(&self.stack, socket, &mut buffer[..len]).map_err(stack_error)
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		match self.check_io() {
+			// Injected resets are reported the way `receive` reports resets.
+			Err(nb::Error::Other(FaultError::Injected(NetworkErrorKind::ConnectionReset))) => {
+				return Ok(RecvOutcome::Reset)
+			}
+			result => result?,
+		}
+		let len = self.truncated(buffer.len());
+		self.stack
+			.receive(socket, &mut buffer[..len])
+			.map_err(stack_error)
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		let result = self.check();
+		TcpStack::close(&self.stack, socket).map_err(FaultError::Stack)?;
+		result
+	}
+}
+
+impl<S: UdpStack> UdpStack for FaultyStack<S> {
+	type UdpSocket = S::UdpSocket;
+	type Error = FaultError<S::Error>;
+
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		self.check()?;
+		self.stack.open(remote, mode).map_err(FaultError::Stack)
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		self.check_io()?;
+		if self.happens(self.config.drop) {
+			return Ok(());
+		}
+		let len = self.truncated(buffer.len());
+		self.stack
+			.write(socket, &buffer[..len])
+			.map_err(stack_error)
+	}
+
+	fn write_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, Self::Error> {
+		self.check_io()?;
+		if self.happens(self.config.drop) {
+			return Ok(datagrams.len());
+		}
+		self.stack
+			.write_batch(socket, datagrams)
+			.map_err(stack_error)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		self.check_io()?;
+		let len = UdpStack::read(&self.stack, socket, buffer).map_err(stack_error)?;
+		if self.happens(self.config.drop) {
+			return Err(nb::Error::WouldBlock);
+		}
+		Ok(self.truncated(len))
+	}
+
+	fn read_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, Self::Error> {
+		self.check_io()?;
+		let received = self
+			.stack
+			.read_batch(socket, datagrams)
+			.map_err(stack_error)?;
+		if self.happens(self.config.drop) {
+			return Err(nb::Error::WouldBlock);
+		}
+		for datagram in datagrams[..received].iter_mut() {
+			datagram.len = self.truncated(datagram.len);
+		}
+		Ok(received)
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		let result = self.check();
+		UdpStack::close(&self.stack, socket).map_err(FaultError::Stack)?;
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A stack that accepts everything, counting the writes that reach it.
+	#[derive(Default)]
+	struct Sink {
+		writes: Cell<usize>,
+	}
+
+	impl TcpStack for Sink {
+		type TcpSocket = ();
+		type Error = NetworkErrorKind;
+
+		fn open(&self, _mode: Mode) -> Result<(), Self::Error> {
+			Ok(())
+		}
+
+		fn connect(&self, _socket: (), _remote: SocketAddr) -> Result<(), Self::Error> {
+			Ok(())
+		}
+
+		fn is_connected(&self, _socket: &()) -> Result<bool, Self::Error> {
+			Ok(true)
+		}
+
+		fn write(&self, _socket: &mut (), buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+			self.writes.set(self.writes.get() + 1);
+			Ok(buffer.len())
+		}
+
+		fn read(&self, _socket: &mut (), buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+			Ok(buffer.len())
+		}
+
+		fn close(&self, _socket: ()) -> Result<(), Self::Error> {
+			Ok(())
+		}
+	}
+
+	impl UdpStack for Sink {
+		type UdpSocket = ();
+		type Error = NetworkErrorKind;
+
+		fn open(&self, _remote: SocketAddr, _mode: Mode) -> Result<(), Self::Error> {
+			Ok(())
+		}
+
+		fn write(&self, _socket: &mut (), _buffer: &[u8]) -> nb::Result<(), Self::Error> {
+			self.writes.set(self.writes.get() + 1);
+			Ok(())
+		}
+
+		fn read(&self, _socket: &mut (), buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+			Ok(buffer.len())
+		}
+
+		fn close(&self, _socket: ()) -> Result<(), Self::Error> {
+			Ok(())
+		}
+	}
+
+	fn faulty(config: FaultConfig) -> FaultyStack<Sink> {
+		FaultyStack::new(Sink::default(), config, 1)
+	}
+
+	#[test]
+	fn error_rate_and_kind_are_applied() {
+		let stack = faulty(FaultConfig {
+			error: 250,
+			error_kind: NetworkErrorKind::ConnectionReset,
+			..FaultConfig::default()
+		});
+		let mut failed = 0;
+		for _ in 0..1000 {
+			match TcpStack::write(&stack, &mut (), b"data") {
+				Ok(4) => {}
+				Err(nb::Error::Other(e)) => {
+					assert_eq!(TcpError::kind(&e), NetworkErrorKind::ConnectionReset);
+					failed += 1;
+				}
+				other => panic!("unexpected {:?}", other),
+			}
+		}
+		assert!((201..300).contains(&failed), "{} failed", failed);
+		assert_eq!(stack.inner().writes.get(), 1000 - failed);
+	}
+
+	#[test]
+	fn injected_resets_are_received_as_resets() {
+		let stack = faulty(FaultConfig::default());
+		let mut buffer = [0; 4];
+		stack.fail_next(NetworkErrorKind::ConnectionReset);
+		assert_eq!(stack.receive(&mut (), &mut buffer), Ok(RecvOutcome::Reset));
+		stack.fail_next(NetworkErrorKind::Timeout);
+		assert_eq!(
+			stack.receive(&mut (), &mut buffer),
+			Err(nb::Error::Other(FaultError::Injected(
+				NetworkErrorKind::Timeout
+			)))
+		);
+		assert_eq!(
+			stack.receive(&mut (), &mut buffer),
+			Ok(RecvOutcome::Data(4))
+		);
+	}
+
+	#[test]
+	fn delays_drops_and_truncation_are_applied() {
+		let stack = faulty(FaultConfig {
+			delay: 1000,
+			..FaultConfig::default()
+		});
+		assert_eq!(
+			TcpStack::write(&stack, &mut (), b"data"),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(stack.inner().writes.get(), 0);
+
+		let stack = faulty(FaultConfig {
+			drop: 1000,
+			..FaultConfig::default()
+		});
+		let mut buffer = [0; 4];
+		assert_eq!(UdpStack::write(&stack, &mut (), b"data"), Ok(()));
+		assert_eq!(stack.inner().writes.get(), 0);
+		assert_eq!(
+			UdpStack::read(&stack, &mut (), &mut buffer),
+			Err(nb::Error::WouldBlock)
+		);
+
+		let stack = faulty(FaultConfig {
+			truncate: 1000,
+			..FaultConfig::default()
+		});
+		for _ in 0..100 {
+			let written = TcpStack::write(&stack, &mut (), b"0123456789").unwrap();
+			assert!((1..10).contains(&written));
+			let read = UdpStack::read(&stack, &mut (), &mut buffer).unwrap();
+			assert!((1..4).contains(&read));
+		}
+	}
+
+	#[test]
+	fn faults_follow_the_seed() {
+		let config = FaultConfig {
+			error: 500,
+			..FaultConfig::default()
+		};
+		let first = FaultyStack::new(Sink::default(), config, 7);
+		let second = FaultyStack::new(Sink::default(), config, 7);
+		for _ in 0..100 {
+			assert_eq!(
+				TcpStack::write(&first, &mut (), b"x").is_ok(),
+				TcpStack::write(&second, &mut (), b"x").is_ok()
+			);
+		}
+
+		let stack = faulty(FaultConfig::default());
+		for _ in 0..1000 {
+			assert_eq!(TcpStack::write(&stack, &mut (), b"x"), Ok(1));
+		}
+	}
+}
@@ -0,0 +1,31 @@
+use heapless::{consts, String};
+
+/// Identifies a network stack, as returned by [`StackInfo::identify`].
+///
+/// [`StackInfo::identify`]: trait.StackInfo.html#tymethod.identify
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StackIdentity {
+	/// The name of the implementation, usually the driver crate, such as
+	/// `ublox-cellular-rs`
+	pub implementation: String<consts::U32>,
+	/// The version of the implementation
+	pub version: String<consts::U32>,
+	/// The vendor of the hardware or network processor, if any
+	pub vendor: String<consts::U32>,
+	/// The model of the hardware or network processor, if any
+	pub model: String<consts::U32>,
+	/// The version of the firmware running on the network processor, if any
+	pub firmware_version: String<consts::U64>,
+}
+
+/// This trait is implemented by stacks that can tell which implementation,
+/// hardware and firmware they are, so that fleet telemetry and bug reports
+/// can identify the network backend a device is actually running.
+pub trait StackInfo {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Identify the stack. Offload stacks may have to query their network
+	/// processor for this.
+	fn identify(&self) -> nb::Result<StackIdentity, Self::Error>;
+}
@@ -0,0 +1,430 @@
+use crate::{DatagramBuf, Mode, RecvOutcome, TcpStack, UdpStack};
+use core::cell::Cell;
+use no_std_net::SocketAddr;
+
+/// A socket operation, as reported in a [`TraceEvent`].
+///
+/// [`TraceEvent`]: struct.TraceEvent.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+	/// [`TcpStack::open`](trait.TcpStack.html#tymethod.open)
+	TcpOpen,
+	/// [`TcpStack::connect`](trait.TcpStack.html#tymethod.connect)
+	TcpConnect,
+	/// [`TcpStack::write`](trait.TcpStack.html#tymethod.write)
+	TcpWrite,
+	/// [`TcpStack::read`](trait.TcpStack.html#tymethod.read) or
+	/// [`TcpStack::receive`](trait.TcpStack.html#method.receive)
+	TcpRead,
+	/// [`TcpStack::close`](trait.TcpStack.html#tymethod.close)
+	TcpClose,
+	/// [`UdpStack::open`](trait.UdpStack.html#tymethod.open)
+	UdpOpen,
+	/// [`UdpStack::write`](trait.UdpStack.html#tymethod.write), or a whole
+	/// [`UdpStack::write_batch`](trait.UdpStack.html#method.write_batch)
+	UdpWrite,
+	/// [`UdpStack::read`](trait.UdpStack.html#tymethod.read), or a whole
+	/// [`UdpStack::read_batch`](trait.UdpStack.html#method.read_batch)
+	UdpRead,
+	/// [`UdpStack::close`](trait.UdpStack.html#tymethod.close)
+	UdpClose,
+}
+
+/// Which way data moves, see [`Operation::direction`].
+///
+/// [`Operation::direction`]: enum.Operation.html#method.direction
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+	/// Sent to the remote host
+	Outgoing,
+	/// Received from the remote host
+	Incoming,
+}
+
+impl Operation {
+	/// The direction data moves in for writes and reads, or `None` for the
+	/// other operations.
+	pub fn direction(&self) -> Option<Direction> {
+		match self {
+			Operation::TcpWrite | Operation::UdpWrite => Some(Direction::Outgoing),
+			Operation::TcpRead | Operation::UdpRead => Some(Direction::Incoming),
+			_ => None,
+		}
+	}
+}
+
+/// How a socket operation ended, as reported in a [`TraceEvent`].
+///
+/// [`TraceEvent`]: struct.TraceEvent.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TraceResult {
+	/// The operation succeeded
+	Ok,
+	/// The operation returned `WouldBlock`
+	WouldBlock,
+	/// The operation failed
+	Error,
+}
+
+/// A socket operation performed through an [`Instrumented`] stack.
+///
+/// [`Instrumented`]: struct.Instrumented.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TraceEvent {
+	/// The id of the socket, numbered in the order sockets are opened
+	pub socket: u16,
+	/// The operation performed
+	pub operation: Operation,
+	/// The remote address, for connects and UDP opens
+	pub remote: Option<SocketAddr>,
+	/// The number of bytes written or read, 0 for the other operations
+	pub len: usize,
+	/// How the operation ended
+	pub result: TraceResult,
+}
+
+/// This trait is implemented by the destinations of the events of an
+/// [`Instrumented`] stack, such as a logger or a `defmt` channel. It is
+/// implemented for closures taking a `&TraceEvent`.
+///
+/// [`Instrumented`]: struct.Instrumented.html
+pub trait TraceSink {
+	/// Handle one event.
+	fn event(&self, event: &TraceEvent);
+}
+
+impl<F: Fn(&TraceEvent)> TraceSink for F {
+	fn event(&self, event: &TraceEvent) {
+		self(event)
+	}
+}
+
+/// A socket of an [`Instrumented`] stack, which is the wrapped stack's socket
+/// tagged with the id events report for it.
+///
+/// [`Instrumented`]: struct.Instrumented.html
+#[derive(Debug)]
+pub struct TracedSocket<T> {
+	id: u16,
+	socket: T,
+}
+
+/// A wrapper around a [`TcpStack`] and [`UdpStack`] that reports every socket
+/// operation to a [`TraceSink`]: the socket, the operation, the number of
+/// bytes and the result. This makes protocol issues in the field debuggable
+/// without changes to the driver.
+///
+/// Reporting can be turned on and off at runtime with
+/// [`set_enabled`](#method.set_enabled); it starts enabled. Sockets are
+/// numbered in the order they are opened, wrapping around after 65536.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`TraceSink`]: trait.TraceSink.html
+pub struct Instrumented<S, K> {
+	stack: S,
+	sink: K,
+	enabled: Cell<bool>,
+	next_id: Cell<u16>,
+}
+
+impl<S, K: TraceSink> Instrumented<S, K> {
+	/// Wrap `stack`, reporting its operations to `sink`.
+	pub fn new(stack: S, sink: K) -> Self {
+		Instrumented {
+			stack,
+			sink,
+			enabled: Cell::new(true),
+			next_id: Cell::new(0),
+		}
+	}
+
+	/// Turn reporting on or off.
+	pub fn set_enabled(&self, enabled: bool) {
+		self.enabled.set(enabled);
+	}
+
+	/// Whether operations are being reported.
+	pub fn is_enabled(&self) -> bool {
+		self.enabled.get()
+	}
+
+	/// Get a reference to the wrapped stack.
+	pub fn inner(&self) -> &S {
+		&self.stack
+	}
+
+	/// Get a reference to the sink.
+	pub fn sink(&self) -> &K {
+		&self.sink
+	}
+
+	/// Destroy the wrapper, returning the wrapped stack and the sink.
+	pub fn into_inner(self) -> (S, K) {
+		(self.stack, self.sink)
+	}
+
+	fn report(
+		&self,
+		socket: u16,
+		operation: Operation,
+		remote: Option<SocketAddr>,
+		len: usize,
+		result: TraceResult,
+	) {
+		if self.enabled.get() {
+			self.sink.event(&TraceEvent {
+				socket,
+				operation,
+				remote,
+				len,
+				result,
+			});
+		}
+	}
+
+	fn new_id(&self) -> u16 {
+		let id = self.next_id.get();
+		self.next_id.set(id.wrapping_add(1));
+		id
+	}
+}
+
+fn outcome<T, E>(result: &nb::Result<T, E>) -> TraceResult {
+	match result {
+		Ok(_) => TraceResult::Ok,
+		Err(nb::Error::WouldBlock) => TraceResult::WouldBlock,
+		Err(nb::Error::Other(_)) => TraceResult::Error,
+	}
+}
+
+/// The outcome of a call that can't block.
+fn plain_outcome<T, E>(result: &Result<T, E>) -> TraceResult {
+	match result {
+		Ok(_) => TraceResult::Ok,
+		Err(_) => TraceResult::Error,
+	}
+}
+
+impl<S: TcpStack, K: TraceSink> TcpStack for Instrumented<S, K> {
+	type TcpSocket = TracedSocket<S::TcpSocket>;
+	type Error = S::Error;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		let id = self.new_id();
+		let result = self.stack.open(mode);
+		self.report(id, Operation::TcpOpen, None, 0, plain_outcome(&result));
+		result.map(|socket| TracedSocket { id, socket })
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		let id = socket.id;
+		let result = self.stack.connect(socket.socket, remote);
+		let outcome = plain_outcome(&result);
+		self.report(id, Operation::TcpConnect, Some(remote), 0, outcome);
+		result.map(|socket| TracedSocket { id, socket })
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.stack.is_connected(&socket.socket)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.write(&mut socket.socket, buffer);
+		let len = *result.as_ref().unwrap_or(&0);
+		self.report(socket.id, Operation::TcpWrite, None, len, outcome(&result));
+		result
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let result = TcpStack::read(&self.stack, &mut socket.socket, buffer);
+		let len = *result.as_ref().unwrap_or(&0);
+		self.report(socket.id, Operation::TcpRead, None, len, outcome(&result));
+		result
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		let result = self.stack.receive(&mut socket.socket, buffer);
+		let len = match result {
+			Ok(RecvOutcome::Data(n)) => n,
+			_ => 0,
+		};
+		self.report(socket.id, Operation::TcpRead, None, len, outcome(&result));
+		result
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		let result = TcpStack::close(&self.stack, socket.socket);
+		self.report(
+			socket.id,
+			Operation::TcpClose,
+			None,
+			0,
+			plain_outcome(&result),
+		);
+		result
+	}
+}
+
+impl<S: UdpStack, K: TraceSink> UdpStack for Instrumented<S, K> {
+	type UdpSocket = TracedSocket<S::UdpSocket>;
+	type Error = S::Error;
+
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		let id = self.new_id();
+		let result = self.stack.open(remote, mode);
+		let outcome = plain_outcome(&result);
+		self.report(id, Operation::UdpOpen, Some(remote), 0, outcome);
+		result.map(|socket| TracedSocket { id, socket })
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		let result = self.stack.write(&mut socket.socket, buffer);
+		let len = if result.is_ok() { buffer.len() } else { 0 };
+		self.report(socket.id, Operation::UdpWrite, None, len, outcome(&result));
+		result
+	}
+
+	fn write_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.write_batch(&mut socket.socket, datagrams);
+		let sent = *result.as_ref().unwrap_or(&0);
+		let len = datagrams[..sent].iter().map(|d| d.len()).sum();
+		self.report(socket.id, Operation::UdpWrite, None, len, outcome(&result));
+		result
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let result = UdpStack::read(&self.stack, &mut socket.socket, buffer);
+		let len = *result.as_ref().unwrap_or(&0);
+		self.report(socket.id, Operation::UdpRead, None, len, outcome(&result));
+		result
+	}
+
+	fn read_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.read_batch(&mut socket.socket, datagrams);
+		let received = *result.as_ref().unwrap_or(&0);
+		let len = datagrams[..received].iter().map(|d| d.len).sum();
+		self.report(socket.id, Operation::UdpRead, None, len, outcome(&result));
+		result
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		let result = UdpStack::close(&self.stack, socket.socket);
+		self.report(
+			socket.id,
+			Operation::UdpClose,
+			None,
+			0,
+			plain_outcome(&result),
+		);
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Call, Expectation, MockStack};
+	use core::cell::RefCell;
+	use heapless::{consts, Vec};
+
+	fn remote() -> SocketAddr {
+		SocketAddr::new([192, 0, 2, 1].into(), 80)
+	}
+
+	fn event(
+		socket: u16,
+		operation: Operation,
+		remote: Option<SocketAddr>,
+		len: usize,
+		result: TraceResult,
+	) -> TraceEvent {
+		TraceEvent {
+			socket,
+			operation,
+			remote,
+			len,
+			result,
+		}
+	}
+
+	#[test]
+	fn operations_are_reported() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(remote())),
+			Expectation::new(Call::TcpWrite(b"hi")),
+			Expectation::new(Call::TcpRead(b"ok")).blocked(),
+			Expectation::new(Call::TcpRead(b"ok")),
+			Expectation::new(Call::TcpClose),
+			Expectation::new(Call::UdpOpen(remote())),
+			Expectation::new(Call::UdpWrite(b"x")).fail(),
+			Expectation::new(Call::UdpWrite(b"y")),
+			Expectation::new(Call::UdpClose),
+		];
+		let events = RefCell::new(Vec::<TraceEvent, consts::U16>::new());
+		let stack = Instrumented::new(MockStack::new(&script), |e: &TraceEvent| {
+			events.borrow_mut().push(*e).unwrap();
+		});
+
+		let socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		let mut socket = stack.connect(socket, remote()).unwrap();
+		assert_eq!(TcpStack::write(&stack, &mut socket, b"hi"), Ok(2));
+		let mut buffer = [0; 4];
+		assert!(stack.receive(&mut socket, &mut buffer).is_err());
+		assert_eq!(TcpStack::read(&stack, &mut socket, &mut buffer), Ok(2));
+		TcpStack::close(&stack, socket).unwrap();
+
+		let mut socket = UdpStack::open(&stack, remote(), Mode::Blocking).unwrap();
+		assert!(UdpStack::write(&stack, &mut socket, b"x").is_err());
+		stack.set_enabled(false);
+		assert!(!stack.is_enabled());
+		UdpStack::write(&stack, &mut socket, b"y").unwrap();
+		stack.set_enabled(true);
+		UdpStack::close(&stack, socket).unwrap();
+
+		use Operation::*;
+		use TraceResult::{Error, Ok as Done, WouldBlock};
+		assert_eq!(
+			&events.borrow()[..],
+			&[
+				event(0, TcpOpen, None, 0, Done),
+				event(0, TcpConnect, Some(remote()), 0, Done),
+				event(0, TcpWrite, None, 2, Done),
+				event(0, TcpRead, None, 0, WouldBlock),
+				event(0, TcpRead, None, 2, Done),
+				event(0, TcpClose, None, 0, Done),
+				event(1, UdpOpen, Some(remote()), 0, Done),
+				event(1, UdpWrite, None, 0, Error),
+				event(1, UdpClose, None, 0, Done),
+			][..]
+		);
+		assert_eq!(TcpWrite.direction(), Some(Direction::Outgoing));
+		assert_eq!(UdpRead.direction(), Some(Direction::Incoming));
+		assert_eq!(TcpClose.direction(), None);
+	}
+}
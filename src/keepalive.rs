@@ -0,0 +1,206 @@
+use crate::clock::{millis, Clock};
+use core::time::Duration;
+use heapless::{ArrayLength, Vec};
+
+/// The kind of network a connection goes through, which determines how often
+/// it needs keepalives to keep NAT mappings and firewall state alive.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetworkType {
+	/// A cellular network. Carrier-grade NATs can drop idle mappings after as
+	/// little as 30 seconds, so keepalives are sent every 25 seconds.
+	Cellular,
+	/// A Wi-Fi network, usually behind a home or office router; keepalives
+	/// are sent every 60 seconds
+	Wifi,
+	/// A wired network; keepalives are sent every 120 seconds, the shortest
+	/// UDP mapping timeout [`rfc4787`] allows
+	///
+	/// [`rfc4787`]: https://tools.ietf.org/html/rfc4787
+	Ethernet,
+	/// Send keepalives after the given time of inactivity
+	Custom(Duration),
+}
+
+impl NetworkType {
+	/// How long a connection may be idle before it needs a keepalive.
+	pub fn keepalive_interval(&self) -> Duration {
+		match self {
+			NetworkType::Cellular => Duration::from_secs(25),
+			NetworkType::Wifi => Duration::from_secs(60),
+			NetworkType::Ethernet => Duration::from_secs(120),
+			NetworkType::Custom(interval) => *interval,
+		}
+	}
+}
+
+/// One connection tracked by a [`KeepaliveScheduler`]. This is only public so
+/// that the capacity of the scheduler can be named, as in
+/// `KeepaliveScheduler<C, consts::U4>`.
+///
+/// [`KeepaliveScheduler`]: struct.KeepaliveScheduler.html
+pub struct KeepaliveSlot {
+	id: usize,
+	interval_ms: u64,
+	last_activity: u64,
+}
+
+impl KeepaliveSlot {
+	fn due_at(&self) -> u64 {
+		self.last_activity.saturating_add(self.interval_ms)
+	}
+}
+
+/// Tracks how long up to `N` connections have been idle, and tells the
+/// application when one needs a keepalive before NAT or firewall state along
+/// the path expires.
+///
+/// Connections are identified by an application-chosen id, such as the index
+/// of the socket. The application reports activity on a connection with
+/// [`activity`](#method.activity), and polls [`due`](#method.due) to find the
+/// connections that need a keepalive; sending one counts as activity.
+pub struct KeepaliveScheduler<C, N>
+where
+	N: ArrayLength<KeepaliveSlot>,
+{
+	clock: C,
+	slots: Vec<KeepaliveSlot, N>,
+}
+
+impl<C, N> KeepaliveScheduler<C, N>
+where
+	C: Clock,
+	N: ArrayLength<KeepaliveSlot>,
+{
+	/// Create a scheduler tracking no connection, using `clock` to measure
+	/// idle time.
+	pub fn new(clock: C) -> Self {
+		KeepaliveScheduler {
+			clock,
+			slots: Vec::new(),
+		}
+	}
+
+	/// Start tracking connection `id`, which goes through a network of the
+	/// given type, counting it as active now. Tracking an id again changes
+	/// its network type. Returns `Err(id)` if `N` connections are already
+	/// tracked.
+	pub fn track(&mut self, id: usize, network: NetworkType) -> Result<(), usize> {
+		let slot = KeepaliveSlot {
+			id,
+			interval_ms: millis(network.keepalive_interval()),
+			last_activity: self.clock.now_ms(),
+		};
+		match self.slots.iter_mut().find(|s| s.id == id) {
+			Some(existing) => {
+				*existing = slot;
+				Ok(())
+			}
+			None => self.slots.push(slot).map_err(|slot| slot.id),
+		}
+	}
+
+	/// Stop tracking connection `id`, such as when it is closed.
+	pub fn untrack(&mut self, id: usize) {
+		if let Some(index) = self.slots.iter().position(|s| s.id == id) {
+			self.slots.swap_remove(index);
+		}
+	}
+
+	/// Record that data was sent or received on connection `id`.
+	pub fn activity(&mut self, id: usize) {
+		let now = self.clock.now_ms();
+		if let Some(slot) = self.slots.iter_mut().find(|s| s.id == id) {
+			slot.last_activity = now;
+		}
+	}
+
+	/// The id of a connection that has been idle long enough to need a
+	/// keepalive, if any. After sending it, report it with
+	/// [`activity`](#method.activity), or this keeps returning the same id.
+	pub fn due(&self) -> Option<usize> {
+		let now = self.clock.now_ms();
+		self.slots
+			.iter()
+			.filter(|s| s.due_at() <= now)
+			.min_by_key(|s| s.due_at())
+			.map(|s| s.id)
+	}
+
+	/// How long until the next keepalive is due, or `None` if no connection
+	/// is tracked. This is how long the application can sleep, as far as
+	/// keepalives are concerned.
+	pub fn next_due_in(&self) -> Option<Duration> {
+		let now = self.clock.now_ms();
+		self.slots
+			.iter()
+			.map(|s| s.due_at().saturating_sub(now))
+			.min()
+			.map(Duration::from_millis)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::cell::Cell;
+	use heapless::consts;
+
+	struct Manual(Cell<u64>);
+
+	impl Manual {
+		fn advance(&self, ms: u64) {
+			self.0.set(self.0.get() + ms);
+		}
+	}
+
+	impl Clock for Manual {
+		fn now_ms(&self) -> u64 {
+			self.0.get()
+		}
+	}
+
+	#[test]
+	fn idle_connections_become_due() {
+		let clock = Manual(Cell::new(0));
+		let mut scheduler = KeepaliveScheduler::<_, consts::U2>::new(&clock);
+		assert_eq!(scheduler.next_due_in(), None);
+		scheduler.track(1, NetworkType::Cellular).unwrap();
+		scheduler.track(2, NetworkType::Wifi).unwrap();
+		assert_eq!(scheduler.next_due_in(), Some(Duration::from_secs(25)));
+		assert_eq!(scheduler.due(), None);
+
+		clock.advance(25_000);
+		assert_eq!(scheduler.due(), Some(1));
+		assert_eq!(scheduler.due(), Some(1));
+		scheduler.activity(1);
+		assert_eq!(scheduler.due(), None);
+		assert_eq!(scheduler.next_due_in(), Some(Duration::from_secs(25)));
+
+		// The one overdue longest comes first.
+		clock.advance(40_000);
+		assert_eq!(scheduler.due(), Some(1));
+		scheduler.activity(1);
+		assert_eq!(scheduler.due(), Some(2));
+		assert_eq!(scheduler.next_due_in(), Some(Duration::from_secs(0)));
+	}
+
+	#[test]
+	fn tracking_is_bounded_and_can_be_changed() {
+		let clock = Manual(Cell::new(0));
+		let mut scheduler = KeepaliveScheduler::<_, consts::U2>::new(&clock);
+		scheduler.track(1, NetworkType::Ethernet).unwrap();
+		scheduler.track(2, NetworkType::Ethernet).unwrap();
+		assert_eq!(scheduler.track(3, NetworkType::Ethernet), Err(3));
+
+		// Tracking an id again changes its interval.
+		let custom = NetworkType::Custom(Duration::from_secs(5));
+		scheduler.track(1, custom).unwrap();
+		assert_eq!(scheduler.next_due_in(), Some(Duration::from_secs(5)));
+
+		scheduler.untrack(1);
+		scheduler.track(3, NetworkType::Ethernet).unwrap();
+		clock.advance(5_000);
+		assert_eq!(scheduler.due(), None);
+		assert_eq!(scheduler.next_due_in(), Some(Duration::from_secs(115)));
+	}
+}
@@ -1,16 +1,118 @@
 //! # embedded-nal - A Network Abstraction Layer for Embedded Systems
+//!
+//! ## Sharing a stack
+//!
+//! The stack traits take `&self`, so that several clients, such as an MQTT
+//! connection and an NTP client, can use one stack at the same time.
+//! Implementations keep their state behind interior mutability, such as a
+//! `RefCell` for a stack used from one thread, the way the wrappers of this
+//! crate do. Sharing a stack between threads takes a stack that is `Sync`,
+//! or a wrapper like `SharedStack` with the `std` feature.
 
-#![no_std]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![deny(missing_docs)]
 #![deny(unsafe_code)]
 
 pub use nb;
+#[cfg(feature = "async")]
+pub mod asynch;
+mod blocking;
+mod callback;
+mod captive;
+mod cellular;
+mod clock;
+mod config;
+mod connect;
 mod dns;
-pub use dns::{AddrType, Dns};
+mod erased;
+mod error;
+mod events;
+mod faulty;
+mod info;
+mod instrument;
+mod keepalive;
+mod loopback;
+mod mdns;
+mod metered;
+mod mock;
+mod multicast;
+mod neighbor;
+mod ping;
+mod poll;
+mod pool;
+mod power;
+mod proxy;
+mod replay;
+#[cfg(feature = "std")]
+mod shared;
+mod signal;
+mod stats;
+#[cfg(feature = "std")]
+mod std_net;
+mod supervisor;
+mod throttle;
+mod time;
+mod udp;
+mod wifi;
+pub use blocking::Blocking;
+pub use callback::{SocketCallback, SocketEvent, TcpCallbacks, UdpCallbacks};
+pub use captive::{detect_captive_portal, Connectivity, ConnectivityProbe};
+pub use cellular::{ApnAuth, CellularControl, Nidd, PdpContext, PdpType, RegistrationState};
+pub use clock::{Clock, Delay};
+pub use config::{Endpoint, NetConfig, TlsVersion};
+pub use connect::{connect_host, ConnectError, ConnectedUdp, UdpConnect, UdpConnection};
+pub use dns::{
+	resolve_dual_stack, validate_hostname, AddrPreference, AddrType, CacheEntry, CacheError,
+	CachedDns, Dns, DnsConfig, DnsError, DnsErrorKind, DnsOverTls, DnsPrivacy, DnsRecords, Dnssec,
+	DnssecStatus, DohError, DohResolver, DualStackQuery, HostAddr, HostnameError, LlmnrFallback,
+	LlmnrFallbackError, RetryError, RetryPolicy, RetryingDns, SrvRecord, TxtRecords, TxtStrings,
+	ValidatingDns, ValidatingDnsError, DOH_MAX_RESPONSE,
+};
+pub use erased::{
+	AnyError, AnySocket, AnyTcpStack, AnyUdpStack, ErasedSlot, ErasedTcp, ErasedUdp, SocketHandle,
+};
+pub use error::{NetworkErrorKind, TcpError, UdpError};
+pub use events::{NetworkEvent, NetworkEvents};
+pub use faulty::{FaultConfig, FaultError, FaultyStack};
+pub use info::{StackIdentity, StackInfo};
+pub use instrument::{
+	Direction, Instrumented, Operation, TraceEvent, TraceResult, TraceSink, TracedSocket,
+};
+pub use keepalive::{KeepaliveScheduler, KeepaliveSlot, NetworkType};
+pub use loopback::{LoopbackError, LoopbackSocket, LoopbackStack};
+pub use mdns::{MdnsResolve, ServiceAnnounce, ServiceDiscovery, ServiceInfo, ServiceInstance};
+pub use metered::{Metered, Metrics};
+pub use mock::{Call, Expectation, MockError, MockSocket, MockStack, Outcome};
+pub use multicast::{MulticastGroups, UdpMulticast, UdpSourceMulticast};
+pub use neighbor::{Neighbor, NeighborState, NeighborTable, StaticNeighbors};
+pub use ping::{Hop, Ping, PingReply, PingTtl, RttInfo, Traceroute};
+pub use poll::{PollEntry, PollEntryOf, PollSocket, Poller, Readiness};
+pub use pool::{BufferPool, PoolBuffer, Pooled, Rejected, SlicePool, TcpPooled, UdpPooled};
+pub use power::{
+	EdrxConfig, PowerSave, PowerSaveConfig, PsmConfig, SocketRetention, WifiPowerSaveConfig,
+};
+pub use proxy::{HttpConnectProxy, HttpProxyError, Socks5Connector, Socks5Error};
+pub use replay::{RecordedSocket, Recorder, Replay, ReplayError, ReplaySocket};
+#[cfg(feature = "std")]
+pub use shared::SharedStack;
+pub use signal::{SignalInfo, SignalQuality};
+pub use stats::{InterfaceStats, NetStats};
+#[cfg(feature = "std")]
+pub use std_net::{StdDns, StdTcpSocket, StdTcpStack, StdUdpStack};
+pub use supervisor::{BackoffPolicy, ConnectionSupervisor, SupervisorState};
+pub use throttle::Throttled;
+pub use time::{TimeSync, UnixTimestamp};
+pub use udp::{
+	BindOptions, ChecksumCoverage, DatagramBuf, Ecn, PacketInfo, PathMtu, TrafficClass,
+	UdpChecksum, UdpOptions, UdpPacketInfo, UdpServer, UdpSourceFilter, UdpStatistics, UdpStats,
+	UdpZeroCopy,
+};
 
 pub use no_std_net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+pub use wifi::{AccessPoint, WifiControl, WifiCredentials, WifiSecurity, WifiStatus};
 
 /// Whether a socket should block when a read/write can't be performed, or return early.
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Mode {
 	/// The function call will wait as long as necessary to complete the operation
 	Blocking,
@@ -20,15 +122,63 @@ pub enum Mode {
 	Timeout(u16),
 }
 
+/// Selects the local network interface an operation applies to, on stacks
+/// that have more than one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interface {
+	/// Let the stack pick the interface
+	Any,
+	/// The interface that has been assigned the given local address. This is
+	/// how IPv4 identifies interfaces.
+	Address(IpAddr),
+	/// The interface with the given index. This is how IPv6 identifies
+	/// interfaces.
+	Index(u32),
+}
+
+/// A 48-bit IEEE 802 MAC address, such as the BSSID of a Wi-Fi access point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MacAddr(pub [u8; 6]);
+
+/// A transport protocol carried over IP.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+	/// Transmission Control Protocol
+	Tcp,
+	/// User Datagram Protocol
+	Udp,
+}
+
+/// What a read from a TCP stream found, as returned by
+/// [`TcpStack::receive`].
+///
+/// [`TcpStack::receive`]: trait.TcpStack.html#method.receive
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RecvOutcome {
+	/// The given number of bytes were received, which is only zero if the
+	/// buffer read into was empty
+	Data(usize),
+	/// The peer closed its side of the connection in an orderly way (with a
+	/// `FIN`), after everything it sent has been read
+	Closed,
+	/// The peer reset the connection, possibly losing data it sent
+	Reset,
+}
+
 /// This trait is implemented by TCP/IP stacks. You could, for example, have an implementation
 /// which knows how to send AT commands to an ESP8266 WiFi module. You could have another implemenation
 /// which knows how to driver the Rust Standard Library's `std::net` module. Given this trait, you can how
 /// write a portable HTTP client which can work with either implementation.
 pub trait TcpStack {
 	/// The type returned when we create a new TCP socket
+	///
+	/// Stacks whose sockets are indices into a table of their own should tag
+	/// them with the generation of their slot, and reject sockets of an
+	/// earlier generation, so that a socket kept past its close can't reach
+	/// the one that reused its slot.
 	type TcpSocket;
 	/// The type returned when we have an error
-	type Error: core::fmt::Debug;
+	type Error: TcpError;
 
 	/// Open a new TCP socket. The socket starts in the unconnected state.
 	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error>;
@@ -50,12 +200,44 @@ pub trait TcpStack {
 	/// Read from the stream. Returns `Ok(n)`, which means `n` bytes of
 	/// data have been received and they have been placed in
 	/// `&buffer[0..n]`, or an error.
+	///
+	/// `Ok(0)` into a non-empty `buffer` means the peer closed the stream,
+	/// and implementations must not return it for anything else: a read that
+	/// found no data returns `WouldBlock`. [`receive`](#method.receive)
+	/// reports the end of the stream explicitly.
 	fn read(
 		&self,
 		socket: &mut Self::TcpSocket,
 		buffer: &mut [u8],
 	) -> nb::Result<usize, Self::Error>;
 
+	/// Read from the stream, like [`read`](#tymethod.read), telling data
+	/// apart from the end of the stream and from a reset connection, which
+	/// HTTP keep-alive and MQTT clients need to handle differently. Unlike
+	/// with `read`, `Data(0)` is only ever returned into an empty `buffer`.
+	///
+	/// The default implementation takes an empty read into a non-empty
+	/// `buffer` as the end of the stream, and an error of kind
+	/// [`NetworkErrorKind::ConnectionReset`] as a reset. Stacks that wrap
+	/// another forward it, so that the wrapped stack's override isn't lost.
+	///
+	/// [`NetworkErrorKind::ConnectionReset`]: enum.NetworkErrorKind.html#variant.ConnectionReset
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		let empty = buffer.is_empty();
+		match self.read(socket, buffer) {
+			Ok(0) if !empty => Ok(RecvOutcome::Closed),
+			Ok(n) => Ok(RecvOutcome::Data(n)),
+			Err(nb::Error::Other(ref e)) if e.kind() == NetworkErrorKind::ConnectionReset => {
+				Ok(RecvOutcome::Reset)
+			}
+			Err(e) => Err(e),
+		}
+	}
+
 	/// Close an existing TCP socket.
 	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error>;
 }
@@ -65,28 +247,88 @@ pub trait TcpStack {
 /// module. You could have another implemenation which knows how to driver the
 /// Rust Standard Library's `std::net` module. Given this trait, you can how
 /// write a portable CoAP client which can work with either implementation.
+///
+/// Sockets opened through this trait are *connected*: the remote address is
+/// fixed when the socket is opened, [`write`](#tymethod.write) always sends to
+/// it and [`read`](#tymethod.read) only ever returns datagrams that came from
+/// it. Datagrams from any other source are discarded by the stack. This
+/// matches what DTLS and NTP clients expect, and what most modems provide
+/// natively.
 pub trait UdpStack {
 	/// The type returned when we create a new UDP socket
+	///
+	/// Stacks whose sockets are indices into a table of their own should tag
+	/// them with the generation of their slot, and reject sockets of an
+	/// earlier generation, so that a socket kept past its close can't reach
+	/// the one that reused its slot.
 	type UdpSocket;
 	/// The type returned when we have an error
-	type Error: core::fmt::Debug;
+	type Error: UdpError;
 
 	/// Open a new UDP socket to the given address and port. UDP is connectionless,
-	/// so unlike `TcpStack` no `connect()` is required.
+	/// so unlike `TcpStack` no `connect()` is required: the returned socket is
+	/// already connected to `remote`.
 	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error>;
 
 	/// Send a datagram to the remote host.
 	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error>;
 
+	/// Send several datagrams to the remote host in one call. Returns
+	/// `Ok(n)`, which means the first `n` datagrams of `datagrams` have been
+	/// sent. An error (including `WouldBlock`) is only returned if not even
+	/// the first datagram could be sent.
+	///
+	/// The default implementation sends the datagrams one by one with
+	/// [`write`](#tymethod.write). Stacks that can queue several datagrams
+	/// per driver call should override it.
+	fn write_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, Self::Error> {
+		for (sent, datagram) in datagrams.iter().enumerate() {
+			match self.write(socket, datagram) {
+				Ok(()) => {}
+				Err(e) if sent == 0 => return Err(e),
+				Err(_) => return Ok(sent),
+			}
+		}
+		Ok(datagrams.len())
+	}
+
 	/// Read a datagram the remote host has sent to us. Returns `Ok(n)`, which
 	/// means a datagram of size `n` has been received and it has been placed
-	/// in `&buffer[0..n]`, or an error.
+	/// in `&buffer[0..n]`, or an error. Datagrams from hosts other than the
+	/// one the socket was opened to are never returned.
 	fn read(
 		&self,
 		socket: &mut Self::UdpSocket,
 		buffer: &mut [u8],
 	) -> nb::Result<usize, Self::Error>;
 
+	/// Read several datagrams the remote host has sent to us in one call.
+	/// Returns `Ok(n)`, which means the first `n` entries of `datagrams` have
+	/// been filled in. An error (including `WouldBlock`) is only returned if
+	/// not even one datagram could be read.
+	///
+	/// The default implementation reads the datagrams one by one with
+	/// [`read`](#tymethod.read), until it would block. Stacks that can deliver
+	/// several datagrams per driver call should override it.
+	fn read_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, Self::Error> {
+		for (received, datagram) in datagrams.iter_mut().enumerate() {
+			match self.read(socket, datagram.buffer) {
+				Ok(len) => datagram.len = len,
+				Err(e) if received == 0 => return Err(e),
+				Err(_) => return Ok(received),
+			}
+		}
+		Ok(datagrams.len())
+	}
+
 	/// Close an existing UDP socket.
 	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error>;
 }
@@ -0,0 +1,1036 @@
+use crate::{
+	AddrType, BindOptions, Dns, DnsError, DnsErrorKind, Mode, NetworkErrorKind, TcpError, TcpStack,
+	UdpError, UdpServer, UdpStack,
+};
+use core::cell::{Cell, RefCell};
+use heapless::{consts, ArrayLength, String, Vec};
+use no_std_net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+/// Bytes each socket can hold before writes to it block, or datagrams sent
+/// to it are dropped.
+const BUFFER: usize = 1024;
+/// An address as stored in front of each queued datagram: family, address
+/// and port.
+const ADDR_LEN: usize = 1 + 16 + 2;
+const FIRST_EPHEMERAL_PORT: u16 = 49152;
+
+/// The error type of a [`LoopbackStack`].
+///
+/// [`LoopbackStack`]: struct.LoopbackStack.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoopbackError {
+	/// All sockets, listeners or host entries are in use
+	Exhausted,
+	/// The socket, or the address accepted on, is not known to the stack
+	InvalidSocket,
+	/// The socket was closed, although its slot may since have been reused by
+	/// another socket
+	StaleSocket,
+	/// Nothing listens on the address connected to
+	ConnectionRefused,
+	/// The socket is not connected
+	NotConnected,
+	/// The peer closed the connection
+	Closed,
+	/// Another socket or listener already uses the local address
+	AddrInUse,
+	/// The name is not in the host table
+	NxDomain,
+	/// The name is in the host table, but without an address of the
+	/// requested type
+	NoRecord,
+	/// The name is longer than 64 bytes
+	NameTooLong,
+}
+
+impl DnsError for LoopbackError {
+	fn kind(&self) -> DnsErrorKind {
+		match self {
+			LoopbackError::NxDomain => DnsErrorKind::NxDomain,
+			LoopbackError::NoRecord => DnsErrorKind::NoRecordOfType,
+			LoopbackError::NameTooLong => DnsErrorKind::NameTooLong,
+			_ => DnsErrorKind::Other,
+		}
+	}
+}
+
+impl TcpError for LoopbackError {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			LoopbackError::Exhausted => NetworkErrorKind::Exhausted,
+			LoopbackError::InvalidSocket | LoopbackError::StaleSocket => {
+				NetworkErrorKind::InvalidInput
+			}
+			LoopbackError::ConnectionRefused => NetworkErrorKind::ConnectionRefused,
+			LoopbackError::NotConnected => NetworkErrorKind::NotConnected,
+			LoopbackError::Closed => NetworkErrorKind::PipeClosed,
+			LoopbackError::AddrInUse => NetworkErrorKind::AddrInUse,
+			LoopbackError::NxDomain | LoopbackError::NoRecord | LoopbackError::NameTooLong => {
+				NetworkErrorKind::Dns(DnsError::kind(self))
+			}
+		}
+	}
+}
+
+impl UdpError for LoopbackError {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			LoopbackError::Exhausted => NetworkErrorKind::Exhausted,
+			LoopbackError::InvalidSocket | LoopbackError::StaleSocket => {
+				NetworkErrorKind::InvalidInput
+			}
+			LoopbackError::ConnectionRefused => NetworkErrorKind::ConnectionRefused,
+			LoopbackError::NotConnected => NetworkErrorKind::NotConnected,
+			LoopbackError::Closed => NetworkErrorKind::PipeClosed,
+			LoopbackError::AddrInUse => NetworkErrorKind::AddrInUse,
+			LoopbackError::NxDomain | LoopbackError::NoRecord | LoopbackError::NameTooLong => {
+				NetworkErrorKind::Dns(DnsError::kind(self))
+			}
+		}
+	}
+}
+
+impl core::fmt::Display for LoopbackError {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			LoopbackError::Exhausted => {
+				f.write_str("all sockets, listeners or host entries are in use")
+			}
+			LoopbackError::InvalidSocket => f.write_str("the socket is not known to the stack"),
+			LoopbackError::StaleSocket => f.write_str("the socket was closed"),
+			LoopbackError::ConnectionRefused => f.write_str("connection refused"),
+			LoopbackError::NotConnected => f.write_str("the socket is not connected"),
+			LoopbackError::Closed => f.write_str("the peer closed the connection"),
+			LoopbackError::AddrInUse => f.write_str("the address is in use"),
+			LoopbackError::NxDomain => f.write_str("the name is not in the host table"),
+			LoopbackError::NoRecord => f.write_str("the name has no address of the requested type"),
+			LoopbackError::NameTooLong => f.write_str("the name is too long"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for LoopbackError {}
+
+/// A socket of a [`LoopbackStack`]. It carries the generation of its slot in
+/// the stack, so that using it after it was closed fails with
+/// [`LoopbackError::StaleSocket`], even once the slot has been reused.
+///
+/// [`LoopbackStack`]: struct.LoopbackStack.html
+/// [`LoopbackError::StaleSocket`]: enum.LoopbackError.html#variant.StaleSocket
+#[derive(Debug, PartialEq)]
+pub struct LoopbackSocket {
+	index: usize,
+	generation: u32,
+}
+
+enum Kind {
+	Free,
+	/// A TCP socket, connected to the socket `peer` until either closes
+	Tcp {
+		peer: Option<usize>,
+		connected: bool,
+	},
+	Udp {
+		local: SocketAddr,
+		/// Where datagrams go and come from, for connected sockets
+		remote: Option<SocketAddr>,
+		shared: bool,
+	},
+}
+
+struct Socket {
+	kind: Kind,
+	/// How many times the slot has been freed
+	generation: u32,
+	/// Received stream data, or queued datagrams, each preceded by its length
+	/// as a big-endian `u16` and its source address.
+	rx: Vec<u8, consts::U1024>,
+}
+
+struct Listener {
+	addr: SocketAddr,
+	/// Sockets connected to the listener and not accepted yet
+	pending: Vec<usize, consts::U4>,
+}
+
+struct State {
+	sockets: Vec<Socket, consts::U8>,
+	listeners: Vec<Listener, consts::U4>,
+	hosts: Vec<(String<consts::U64>, IpAddr), consts::U8>,
+}
+
+/// A [`TcpStack`], [`UdpStack`], [`UdpServer`] and [`Dns`] implementation
+/// that works entirely in memory, so that crates built on this one can be
+/// unit tested on the host, including in `no_std` test environments.
+///
+/// The test plays the server side with the stack's own methods: it listens
+/// on an address with [`listen`](#method.listen) and takes the connections
+/// made to it with [`accept`](#method.accept), or binds a UDP socket with
+/// [`UdpServer::bind`]. Names resolve through a fixed table filled in with
+/// [`add_host`](#method.add_host). Any address can be used, whether or not it
+/// is a loopback address.
+///
+/// The stack holds up to 8 sockets and 4 listeners of up to 4 pending
+/// connections, and 8 host entries. Each socket buffers up to 1024 bytes of
+/// received data: TCP writes block when the peer's buffer is full, and
+/// datagrams that don't fit are dropped.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`UdpServer`]: trait.UdpServer.html
+/// [`UdpServer::bind`]: trait.UdpServer.html#tymethod.bind
+/// [`Dns`]: trait.Dns.html
+pub struct LoopbackStack {
+	state: RefCell<State>,
+	next_port: Cell<u16>,
+}
+
+impl Default for LoopbackStack {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+fn encode_addr(addr: SocketAddr) -> [u8; ADDR_LEN] {
+	let mut out = [0; ADDR_LEN];
+	match addr.ip() {
+		IpAddr::V4(ip) => {
+			out[0] = 4;
+			out[1..5].copy_from_slice(&ip.octets());
+		}
+		IpAddr::V6(ip) => {
+			out[0] = 6;
+			out[1..17].copy_from_slice(&ip.octets());
+		}
+	}
+	out[17..].copy_from_slice(&addr.port().to_be_bytes());
+	out
+}
+
+fn decode_addr(data: &[u8]) -> SocketAddr {
+	let port = u16::from(data[17]) << 8 | u16::from(data[18]);
+	let ip = if data[0] == 4 {
+		IpAddr::from([data[1], data[2], data[3], data[4]])
+	} else {
+		let mut octets = [0; 16];
+		octets.copy_from_slice(&data[1..17]);
+		IpAddr::from(octets)
+	};
+	SocketAddr::new(ip, port)
+}
+
+/// Remove the first `n` elements of `buffer`.
+fn consume<T, N: ArrayLength<T>>(buffer: &mut Vec<T, N>, n: usize) {
+	buffer.rotate_left(n);
+	// `Vec::truncate` indexes past the end in this version of heapless.
+	for _ in 0..n {
+		buffer.pop();
+	}
+}
+
+fn is_unspecified(ip: IpAddr) -> bool {
+	match ip {
+		IpAddr::V4(ip) => ip == Ipv4Addr::new(0, 0, 0, 0),
+		IpAddr::V6(ip) => ip == Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0),
+	}
+}
+
+impl LoopbackStack {
+	/// Create a stack with no socket, listener or host.
+	pub fn new() -> Self {
+		LoopbackStack {
+			state: RefCell::new(State {
+				sockets: Vec::new(),
+				listeners: Vec::new(),
+				hosts: Vec::new(),
+			}),
+			next_port: Cell::new(FIRST_EPHEMERAL_PORT),
+		}
+	}
+
+	/// Accept TCP connections to `addr`.
+	pub fn listen(&self, addr: SocketAddr) -> Result<(), LoopbackError> {
+		let mut state = self.state.borrow_mut();
+		if state.listeners.iter().any(|l| l.addr == addr) {
+			return Err(LoopbackError::AddrInUse);
+		}
+		state
+			.listeners
+			.push(Listener {
+				addr,
+				pending: Vec::new(),
+			})
+			.map_err(|_| LoopbackError::Exhausted)
+	}
+
+	/// Take the oldest connection made to `addr`, which must have been
+	/// listened on, or return `WouldBlock` if there is none.
+	pub fn accept(&self, addr: SocketAddr) -> nb::Result<LoopbackSocket, LoopbackError> {
+		let mut state = self.state.borrow_mut();
+		let listener = state
+			.listeners
+			.iter_mut()
+			.find(|l| l.addr == addr)
+			.ok_or(LoopbackError::InvalidSocket)?;
+		if listener.pending.is_empty() {
+			return Err(nb::Error::WouldBlock);
+		}
+		let index = listener.pending[0];
+		consume(&mut listener.pending, 1);
+		Ok(LoopbackSocket {
+			index,
+			generation: state.sockets[index].generation,
+		})
+	}
+
+	/// Let `hostname` resolve to `addr`. A name can be added several times to
+	/// give it several addresses.
+	pub fn add_host(&self, hostname: &str, addr: IpAddr) -> Result<(), LoopbackError> {
+		let mut name = String::new();
+		name.push_str(hostname)
+			.map_err(|_| LoopbackError::NameTooLong)?;
+		self.state
+			.borrow_mut()
+			.hosts
+			.push((name, addr))
+			.map_err(|_| LoopbackError::Exhausted)
+	}
+
+	fn allocate(state: &mut State, kind: Kind) -> Result<LoopbackSocket, LoopbackError> {
+		let free = state.sockets.iter().position(|s| match s.kind {
+			Kind::Free => true,
+			_ => false,
+		});
+		let index = match free {
+			Some(index) => {
+				let socket = &mut state.sockets[index];
+				socket.kind = kind;
+				// `Vec::clear` indexes past the end in this version of heapless.
+				while socket.rx.pop().is_some() {}
+				index
+			}
+			None => {
+				let socket = Socket {
+					kind,
+					generation: 0,
+					rx: Vec::new(),
+				};
+				state
+					.sockets
+					.push(socket)
+					.map_err(|_| LoopbackError::Exhausted)?;
+				state.sockets.len() - 1
+			}
+		};
+		Ok(LoopbackSocket {
+			index,
+			generation: state.sockets[index].generation,
+		})
+	}
+
+	/// The index of the slot of `socket`, if it is still open.
+	fn check(state: &State, socket: &LoopbackSocket) -> Result<usize, LoopbackError> {
+		match state.sockets.get(socket.index) {
+			Some(slot) if slot.generation == socket.generation => Ok(socket.index),
+			Some(_) => Err(LoopbackError::StaleSocket),
+			None => Err(LoopbackError::InvalidSocket),
+		}
+	}
+
+	fn ephemeral_port(&self) -> u16 {
+		let port = self.next_port.get();
+		self.next_port.set(if port == core::u16::MAX {
+			FIRST_EPHEMERAL_PORT
+		} else {
+			port + 1
+		});
+		port
+	}
+
+	/// Free the slot `index`, so that sockets still referring to it are
+	/// stale.
+	fn release(state: &mut State, index: usize) {
+		let socket = &mut state.sockets[index];
+		socket.kind = Kind::Free;
+		socket.generation = socket.generation.wrapping_add(1);
+	}
+
+	fn free(&self, socket: &LoopbackSocket) -> Result<(), LoopbackError> {
+		let mut state = self.state.borrow_mut();
+		let index = Self::check(&state, socket)?;
+		let peer = match state.sockets[index].kind {
+			Kind::Free => return Err(LoopbackError::StaleSocket),
+			Kind::Tcp { peer, .. } => peer,
+			Kind::Udp { .. } => None,
+		};
+		Self::release(&mut state, index);
+		if let Some(peer) = peer {
+			// A server end that was never accepted can't be closed by anyone
+			// else, so it goes along with its client.
+			let mut pending = false;
+			for listener in state.listeners.iter_mut() {
+				if let Some(position) = listener.pending.iter().position(|&p| p == peer) {
+					listener.pending[position..].rotate_left(1);
+					listener.pending.pop();
+					pending = true;
+				}
+			}
+			if pending {
+				Self::release(&mut state, peer);
+			} else if let Kind::Tcp { ref mut peer, .. } = state.sockets[peer].kind {
+				*peer = None;
+			}
+		}
+		Ok(())
+	}
+
+	/// Queue a datagram from `source` on the socket bound to `destination`,
+	/// dropping it if there is none or it has no room.
+	fn deliver(&self, source: SocketAddr, destination: SocketAddr, data: &[u8]) {
+		let mut state = self.state.borrow_mut();
+		let socket = state.sockets.iter_mut().find(|s| match s.kind {
+			Kind::Udp { local, .. } => {
+				local.port() == destination.port()
+					&& (local.ip() == destination.ip() || is_unspecified(local.ip()))
+			}
+			_ => false,
+		});
+		if let Some(socket) = socket {
+			if data.len() > BUFFER || socket.rx.len() + 2 + ADDR_LEN + data.len() > BUFFER {
+				return;
+			}
+			// Can't fail, there is room.
+			socket
+				.rx
+				.extend_from_slice(&(data.len() as u16).to_be_bytes())
+				.ok();
+			socket.rx.extend_from_slice(&encode_addr(source)).ok();
+			socket.rx.extend_from_slice(data).ok();
+		}
+	}
+
+	/// Take the oldest datagram queued on `socket`.
+	fn take_datagram(
+		&self,
+		socket: &LoopbackSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<(usize, SocketAddr), LoopbackError> {
+		let mut state = self.state.borrow_mut();
+		let index = Self::check(&state, socket)?;
+		let socket = &mut state.sockets[index];
+		if socket.rx.is_empty() {
+			return Err(nb::Error::WouldBlock);
+		}
+		let len = usize::from(u16::from(socket.rx[0]) << 8 | u16::from(socket.rx[1]));
+		let source = decode_addr(&socket.rx[2..2 + ADDR_LEN]);
+		let data = &socket.rx[2 + ADDR_LEN..2 + ADDR_LEN + len];
+		let copied = core::cmp::min(len, buffer.len());
+		buffer[..copied].copy_from_slice(&data[..copied]);
+		consume(&mut socket.rx, 2 + ADDR_LEN + len);
+		Ok((copied, source))
+	}
+
+	fn udp_addrs(
+		&self,
+		socket: &LoopbackSocket,
+	) -> Result<(SocketAddr, Option<SocketAddr>), LoopbackError> {
+		let state = self.state.borrow();
+		match state.sockets[Self::check(&state, socket)?].kind {
+			Kind::Udp { local, remote, .. } => Ok((local, remote)),
+			_ => Err(LoopbackError::InvalidSocket),
+		}
+	}
+}
+
+impl TcpStack for LoopbackStack {
+	type TcpSocket = LoopbackSocket;
+	type Error = LoopbackError;
+
+	fn open(&self, _mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		let mut state = self.state.borrow_mut();
+		let kind = Kind::Tcp {
+			peer: None,
+			connected: false,
+		};
+		Self::allocate(&mut state, kind)
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		let mut state = self.state.borrow_mut();
+		let index = Self::check(&state, &socket)?;
+		// The socket is consumed, so its slot is freed on every failure.
+		let listener = state
+			.listeners
+			.iter()
+			.position(|l| l.addr == remote && l.pending.len() < l.pending.capacity());
+		let listener = match listener {
+			Some(listener) => listener,
+			None => {
+				Self::release(&mut state, index);
+				return Err(LoopbackError::ConnectionRefused);
+			}
+		};
+		let kind = Kind::Tcp {
+			peer: Some(index),
+			connected: true,
+		};
+		let peer = match Self::allocate(&mut state, kind) {
+			Ok(peer) => peer.index,
+			Err(e) => {
+				Self::release(&mut state, index);
+				return Err(e);
+			}
+		};
+		state.sockets[index].kind = Kind::Tcp {
+			peer: Some(peer),
+			connected: true,
+		};
+		// Can't fail, there is room.
+		state.listeners[listener].pending.push(peer).ok();
+		Ok(socket)
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		let state = self.state.borrow();
+		match state.sockets[Self::check(&state, socket)?].kind {
+			Kind::Tcp { peer, .. } => Ok(peer.is_some()),
+			_ => Err(LoopbackError::InvalidSocket),
+		}
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		let mut state = self.state.borrow_mut();
+		let index = Self::check(&state, socket)?;
+		let peer = match state.sockets[index].kind {
+			Kind::Tcp {
+				peer: Some(peer), ..
+			} => peer,
+			Kind::Tcp {
+				connected: true, ..
+			} => return Err(nb::Error::Other(LoopbackError::Closed)),
+			Kind::Tcp { .. } => return Err(nb::Error::Other(LoopbackError::NotConnected)),
+			_ => return Err(nb::Error::Other(LoopbackError::InvalidSocket)),
+		};
+		let rx = &mut state.sockets[peer].rx;
+		let n = core::cmp::min(buffer.len(), BUFFER - rx.len());
+		if n == 0 && !buffer.is_empty() {
+			return Err(nb::Error::WouldBlock);
+		}
+		// Can't fail, there is room.
+		rx.extend_from_slice(&buffer[..n]).ok();
+		Ok(n)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let mut state = self.state.borrow_mut();
+		let index = Self::check(&state, socket)?;
+		let socket = &mut state.sockets[index];
+		let open = match socket.kind {
+			Kind::Tcp {
+				peer,
+				connected: true,
+			} => peer.is_some(),
+			Kind::Tcp { .. } => return Err(nb::Error::Other(LoopbackError::NotConnected)),
+			_ => return Err(nb::Error::Other(LoopbackError::InvalidSocket)),
+		};
+		if socket.rx.is_empty() {
+			// Once the peer closed, an empty read signals the end of the
+			// stream.
+			return if open {
+				Err(nb::Error::WouldBlock)
+			} else {
+				Ok(0)
+			};
+		}
+		let n = core::cmp::min(buffer.len(), socket.rx.len());
+		buffer[..n].copy_from_slice(&socket.rx[..n]);
+		consume(&mut socket.rx, n);
+		Ok(n)
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		self.free(&socket)
+	}
+}
+
+impl UdpStack for LoopbackStack {
+	type UdpSocket = LoopbackSocket;
+	type Error = LoopbackError;
+
+	fn open(&self, remote: SocketAddr, _mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		let ip = match remote {
+			SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+			SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 1)),
+		};
+		let kind = Kind::Udp {
+			local: SocketAddr::new(ip, self.ephemeral_port()),
+			remote: Some(remote),
+			shared: false,
+		};
+		Self::allocate(&mut self.state.borrow_mut(), kind)
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		match self.udp_addrs(socket)? {
+			(local, Some(remote)) => {
+				self.deliver(local, remote, buffer);
+				Ok(())
+			}
+			(_, None) => Err(nb::Error::Other(LoopbackError::NotConnected)),
+		}
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let remote = self.udp_addrs(socket)?.1;
+		loop {
+			let (len, source) = self.take_datagram(socket, buffer)?;
+			// Sockets bound to an unspecified address send from it, which
+			// stands for whichever address they were reached on.
+			let expected = remote.map_or(true, |remote| {
+				remote.port() == source.port()
+					&& (remote.ip() == source.ip() || is_unspecified(source.ip()))
+			});
+			if expected {
+				return Ok(len);
+			}
+		}
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		self.free(&socket)
+	}
+}
+
+impl UdpServer for LoopbackStack {
+	fn bind(
+		&self,
+		local: SocketAddr,
+		options: BindOptions,
+		_mode: Mode,
+	) -> Result<Self::UdpSocket, Self::Error> {
+		let local = if local.port() == 0 {
+			SocketAddr::new(local.ip(), self.ephemeral_port())
+		} else {
+			local
+		};
+		let shared = options.reuse_address || options.reuse_port;
+		let mut state = self.state.borrow_mut();
+		let in_use = state.sockets.iter().any(|s| match s.kind {
+			Kind::Udp {
+				local: other,
+				shared: other_shared,
+				..
+			} => other == local && !(shared && other_shared),
+			_ => false,
+		});
+		if in_use {
+			return Err(LoopbackError::AddrInUse);
+		}
+		let kind = Kind::Udp {
+			local,
+			remote: None,
+			shared,
+		};
+		Self::allocate(&mut state, kind)
+	}
+
+	fn write_to(
+		&self,
+		socket: &mut Self::UdpSocket,
+		remote: SocketAddr,
+		buffer: &[u8],
+	) -> nb::Result<(), Self::Error> {
+		let (local, _) = self.udp_addrs(socket)?;
+		self.deliver(local, remote, buffer);
+		Ok(())
+	}
+
+	fn read_from(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<(usize, SocketAddr), Self::Error> {
+		self.udp_addrs(socket)?;
+		self.take_datagram(socket, buffer)
+	}
+}
+
+impl Dns for LoopbackStack {
+	type Error = LoopbackError;
+
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error> {
+		let mut addr = [IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))];
+		self.get_hosts_by_name(hostname, addr_type, &mut addr)?;
+		Ok(addr[0])
+	}
+
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		let hostname = hostname.trim_end_matches('.');
+		let state = self.state.borrow();
+		let mut known = false;
+		let mut found = 0;
+		for (name, addr) in state.hosts.iter() {
+			if !name.eq_ignore_ascii_case(hostname) {
+				continue;
+			}
+			known = true;
+			let wanted = match (&addr_type, addr) {
+				(AddrType::IPv4, IpAddr::V4(_)) | (AddrType::IPv6, IpAddr::V6(_)) => true,
+				(AddrType::Either, _) => true,
+				_ => false,
+			};
+			if wanted && found < out.len() {
+				out[found] = *addr;
+				found += 1;
+			}
+		}
+		match (known, found) {
+			(false, _) => Err(nb::Error::Other(LoopbackError::NxDomain)),
+			(true, 0) if !out.is_empty() => Err(nb::Error::Other(LoopbackError::NoRecord)),
+			(true, found) => Ok(found),
+		}
+	}
+
+	fn get_host_by_address(&self, addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error> {
+		let state = self.state.borrow();
+		let (name, _) = state
+			.hosts
+			.iter()
+			.find(|(_, a)| *a == addr)
+			.ok_or(LoopbackError::NxDomain)?;
+		let mut hostname = String::new();
+		// Names are at most 64 bytes, so this can't fail.
+		hostname.push_str(name).ok();
+		Ok(hostname)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn addr(port: u16) -> SocketAddr {
+		SocketAddr::new(IpAddr::from([127, 0, 0, 1]), port)
+	}
+
+	/// Connect a client to a listener on `port`, returning the client and
+	/// server ends.
+	fn connected(stack: &LoopbackStack, port: u16) -> (LoopbackSocket, LoopbackSocket) {
+		stack.listen(addr(port)).unwrap();
+		let client = TcpStack::open(stack, Mode::Blocking).unwrap();
+		let client = stack.connect(client, addr(port)).unwrap();
+		let server = stack.accept(addr(port)).unwrap();
+		(client, server)
+	}
+
+	#[test]
+	fn tcp_exchange() {
+		let stack = LoopbackStack::new();
+		let (mut client, mut server) = connected(&stack, 80);
+		assert_eq!(stack.accept(addr(80)), Err(nb::Error::WouldBlock));
+		assert!(stack.is_connected(&client).unwrap());
+
+		let mut buffer = [0; 8];
+		assert_eq!(
+			TcpStack::read(&stack, &mut server, &mut buffer),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(TcpStack::write(&stack, &mut client, b"hello"), Ok(5));
+		assert_eq!(TcpStack::read(&stack, &mut server, &mut buffer[..3]), Ok(3));
+		assert_eq!(TcpStack::read(&stack, &mut server, &mut buffer[3..]), Ok(2));
+		assert_eq!(&buffer[..5], b"hello");
+		assert_eq!(TcpStack::write(&stack, &mut server, b"hi"), Ok(2));
+		assert_eq!(TcpStack::read(&stack, &mut client, &mut buffer), Ok(2));
+		assert_eq!(&buffer[..2], b"hi");
+	}
+
+	#[test]
+	fn tcp_writes_block_when_the_peer_buffer_is_full() {
+		let stack = LoopbackStack::new();
+		let (mut client, mut server) = connected(&stack, 80);
+		let data = [0x55; BUFFER + 10];
+		assert_eq!(TcpStack::write(&stack, &mut client, &data), Ok(BUFFER));
+		assert_eq!(
+			TcpStack::write(&stack, &mut client, &data),
+			Err(nb::Error::WouldBlock)
+		);
+		let mut buffer = [0; 100];
+		assert_eq!(TcpStack::read(&stack, &mut server, &mut buffer), Ok(100));
+		assert_eq!(TcpStack::write(&stack, &mut client, &data), Ok(100));
+	}
+
+	#[test]
+	fn tcp_close_ends_the_stream() {
+		let stack = LoopbackStack::new();
+		let (mut client, mut server) = connected(&stack, 80);
+		TcpStack::write(&stack, &mut client, b"bye").unwrap();
+		TcpStack::close(&stack, client).unwrap();
+
+		assert!(!stack.is_connected(&server).unwrap());
+		let mut buffer = [0; 8];
+		assert_eq!(TcpStack::read(&stack, &mut server, &mut buffer), Ok(3));
+		assert_eq!(TcpStack::read(&stack, &mut server, &mut buffer), Ok(0));
+		assert_eq!(
+			TcpStack::write(&stack, &mut server, b"x"),
+			Err(nb::Error::Other(LoopbackError::Closed))
+		);
+	}
+
+	#[test]
+	fn tcp_connect_errors() {
+		let stack = LoopbackStack::new();
+		let mut socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		assert_eq!(
+			TcpStack::write(&stack, &mut socket, b"x"),
+			Err(nb::Error::Other(LoopbackError::NotConnected))
+		);
+		assert_eq!(
+			stack.connect(socket, addr(80)).unwrap_err(),
+			LoopbackError::ConnectionRefused
+		);
+
+		stack.listen(addr(80)).unwrap();
+		assert_eq!(stack.listen(addr(80)), Err(LoopbackError::AddrInUse));
+		assert_eq!(
+			stack.accept(addr(81)),
+			Err(nb::Error::Other(LoopbackError::InvalidSocket))
+		);
+	}
+
+	#[test]
+	fn failed_connects_free_their_socket() {
+		let stack = LoopbackStack::new();
+		for _ in 0..9 {
+			let socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+			assert_eq!(
+				stack.connect(socket, addr(80)).unwrap_err(),
+				LoopbackError::ConnectionRefused
+			);
+		}
+		for _ in 0..8 {
+			TcpStack::open(&stack, Mode::Blocking).unwrap();
+		}
+	}
+
+	#[test]
+	fn connects_without_room_for_the_server_end_free_their_socket() {
+		let stack = LoopbackStack::new();
+		stack.listen(addr(80)).unwrap();
+		let mut sockets = Vec::<LoopbackSocket, consts::U8>::new();
+		for _ in 0..8 {
+			sockets
+				.push(TcpStack::open(&stack, Mode::Blocking).unwrap())
+				.unwrap();
+		}
+		let socket = sockets.pop().unwrap();
+		assert_eq!(
+			stack.connect(socket, addr(80)).unwrap_err(),
+			LoopbackError::Exhausted
+		);
+		assert!(TcpStack::open(&stack, Mode::Blocking).is_ok());
+	}
+
+	#[test]
+	fn closing_a_client_frees_its_unaccepted_server_end() {
+		let stack = LoopbackStack::new();
+		stack.listen(addr(80)).unwrap();
+		for _ in 0..8 {
+			let socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+			let socket = stack.connect(socket, addr(80)).unwrap();
+			TcpStack::close(&stack, socket).unwrap();
+		}
+		assert_eq!(stack.accept(addr(80)), Err(nb::Error::WouldBlock));
+
+		// Only the closed connection leaves the backlog.
+		let first = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		let mut first = stack.connect(first, addr(80)).unwrap();
+		let second = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		let second = stack.connect(second, addr(80)).unwrap();
+		let third = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		let mut third = stack.connect(third, addr(80)).unwrap();
+		TcpStack::close(&stack, second).unwrap();
+		TcpStack::write(&stack, &mut first, b"1").unwrap();
+		TcpStack::write(&stack, &mut third, b"3").unwrap();
+		let mut buffer = [0; 1];
+		let mut server = stack.accept(addr(80)).unwrap();
+		assert_eq!(TcpStack::read(&stack, &mut server, &mut buffer), Ok(1));
+		assert_eq!(&buffer, b"1");
+		let mut server = stack.accept(addr(80)).unwrap();
+		assert_eq!(TcpStack::read(&stack, &mut server, &mut buffer), Ok(1));
+		assert_eq!(&buffer, b"3");
+		assert_eq!(stack.accept(addr(80)), Err(nb::Error::WouldBlock));
+	}
+
+	#[test]
+	fn stale_sockets_are_rejected_after_reuse() {
+		let stack = LoopbackStack::new();
+		let (client, mut server) = connected(&stack, 80);
+		TcpStack::write(&stack, &mut server, b"unread").unwrap();
+		let stale = LoopbackSocket {
+			index: client.index,
+			generation: client.generation,
+		};
+		TcpStack::close(&stack, client).unwrap();
+
+		// The new socket takes the slot of the closed one, with an empty
+		// buffer.
+		stack.listen(addr(81)).unwrap();
+		let socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		assert_eq!(socket.index, stale.index);
+		let mut socket = stack.connect(socket, addr(81)).unwrap();
+		let mut buffer = [0; 8];
+		assert_eq!(
+			TcpStack::read(&stack, &mut socket, &mut buffer),
+			Err(nb::Error::WouldBlock)
+		);
+
+		assert_eq!(stack.is_connected(&stale), Err(LoopbackError::StaleSocket));
+		assert_eq!(
+			TcpStack::close(&stack, stale),
+			Err(LoopbackError::StaleSocket)
+		);
+		let unknown = LoopbackSocket {
+			index: 7,
+			generation: 0,
+		};
+		assert_eq!(
+			stack.is_connected(&unknown),
+			Err(LoopbackError::InvalidSocket)
+		);
+	}
+
+	#[test]
+	fn socket_table_exhaustion() {
+		let stack = LoopbackStack::new();
+		for _ in 0..8 {
+			TcpStack::open(&stack, Mode::Blocking).unwrap();
+		}
+		assert_eq!(
+			TcpStack::open(&stack, Mode::Blocking),
+			Err(LoopbackError::Exhausted)
+		);
+	}
+
+	#[test]
+	fn udp_exchange() {
+		let stack = LoopbackStack::new();
+		let mut server = stack
+			.bind(addr(53), BindOptions::default(), Mode::Blocking)
+			.unwrap();
+		let mut client = UdpStack::open(&stack, addr(53), Mode::Blocking).unwrap();
+		UdpStack::write(&stack, &mut client, b"query").unwrap();
+
+		let mut buffer = [0; 8];
+		let (len, source) = stack.read_from(&mut server, &mut buffer).unwrap();
+		assert_eq!(&buffer[..len], b"query");
+		assert_eq!(source, addr(FIRST_EPHEMERAL_PORT));
+		assert_eq!(
+			stack.read_from(&mut server, &mut buffer),
+			Err(nb::Error::WouldBlock)
+		);
+
+		// Datagrams that don't fit lose their tail.
+		stack
+			.write_to(&mut server, source, b"a long answer")
+			.unwrap();
+		assert_eq!(UdpStack::read(&stack, &mut client, &mut buffer), Ok(8));
+		assert_eq!(&buffer, b"a long a");
+	}
+
+	#[test]
+	fn connected_udp_sockets_filter_sources() {
+		let stack = LoopbackStack::new();
+		let mut server = stack
+			.bind(addr(53), BindOptions::default(), Mode::Blocking)
+			.unwrap();
+		let mut other = stack
+			.bind(addr(54), BindOptions::default(), Mode::Blocking)
+			.unwrap();
+		let mut client = UdpStack::open(&stack, addr(53), Mode::Blocking).unwrap();
+		let client_addr = addr(FIRST_EPHEMERAL_PORT);
+		stack.write_to(&mut other, client_addr, b"spoof").unwrap();
+		stack.write_to(&mut server, client_addr, b"real").unwrap();
+
+		let mut buffer = [0; 8];
+		assert_eq!(UdpStack::read(&stack, &mut client, &mut buffer), Ok(4));
+		assert_eq!(&buffer[..4], b"real");
+	}
+
+	#[test]
+	fn udp_bind_conflicts() {
+		let stack = LoopbackStack::new();
+		let shared = BindOptions {
+			reuse_address: true,
+			..BindOptions::default()
+		};
+		stack.bind(addr(5353), shared, Mode::Blocking).unwrap();
+		stack.bind(addr(5353), shared, Mode::Blocking).unwrap();
+		assert_eq!(
+			stack.bind(addr(5353), BindOptions::default(), Mode::Blocking),
+			Err(LoopbackError::AddrInUse)
+		);
+	}
+
+	#[test]
+	fn host_table() {
+		let stack = LoopbackStack::new();
+		let v4 = IpAddr::from([10, 0, 0, 1]);
+		let v6 = IpAddr::from([0xfd, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+		stack.add_host("Example.com", v4).unwrap();
+		stack.add_host("example.com", v6).unwrap();
+		stack.add_host("v4.example.com", v4).unwrap();
+
+		let mut out = [v4; 4];
+		assert_eq!(
+			stack.get_hosts_by_name("EXAMPLE.com.", AddrType::Either, &mut out),
+			Ok(2)
+		);
+		assert_eq!(&out[..2], &[v4, v6]);
+		assert_eq!(
+			stack.get_host_by_name("example.com", AddrType::IPv6),
+			Ok(v6)
+		);
+		let error = stack
+			.get_host_by_name("v4.example.com", AddrType::IPv6)
+			.unwrap_err();
+		assert_eq!(error, nb::Error::Other(LoopbackError::NoRecord));
+		assert_eq!(
+			DnsError::kind(&LoopbackError::NoRecord),
+			DnsErrorKind::NoRecordOfType
+		);
+		assert_eq!(
+			stack.get_host_by_name("unknown.com", AddrType::Either),
+			Err(nb::Error::Other(LoopbackError::NxDomain))
+		);
+		assert_eq!(
+			stack.get_host_by_address(v4).unwrap().as_str(),
+			"Example.com"
+		);
+		assert_eq!(
+			stack.add_host(core::str::from_utf8(&[b'a'; 65]).unwrap(), v4),
+			Err(LoopbackError::NameTooLong)
+		);
+	}
+}
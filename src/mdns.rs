@@ -0,0 +1,112 @@
+use crate::{AddrType, Protocol};
+use heapless::{consts, String};
+use no_std_net::IpAddr;
+
+/// This trait is implemented by stacks that can resolve link-local names
+/// (`.local`) with multicast DNS ([`rfc6762`]), so devices can find printers,
+/// gateways and peers on the local network without any unicast DNS
+/// infrastructure.
+///
+/// [`rfc6762`]: https://tools.ietf.org/html/rfc6762
+pub trait MdnsResolve {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Resolve the first ip address of a host on the local link, given its
+	/// hostname and a desired address record type to look for.
+	///
+	/// `hostname` must be in the `.local` domain, for example
+	/// `printer.local`.
+	fn get_local_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error>;
+}
+
+/// A service instance found by [`ServiceDiscovery::browse`].
+///
+/// [`ServiceDiscovery::browse`]: trait.ServiceDiscovery.html#tymethod.browse
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ServiceInstance {
+	/// The instance name, such as `Living Room Printer`
+	pub name: String<consts::U64>,
+}
+
+/// Where a service instance can be reached, as returned by
+/// [`ServiceDiscovery::resolve`].
+///
+/// [`ServiceDiscovery::resolve`]: trait.ServiceDiscovery.html#tymethod.resolve
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServiceInfo {
+	/// Hostname of the host providing the service
+	pub hostname: String<consts::U256>,
+	/// Address of the host, if it was included in the answer
+	pub addr: Option<IpAddr>,
+	/// Port of the service on the host
+	pub port: u16,
+	/// The number of bytes of `TXT` data written to the caller's buffer
+	pub txt_len: usize,
+}
+
+/// This trait is implemented by stacks that support DNS-based service
+/// discovery ([`rfc6763`]) on the local link, as used by the commissioning
+/// flows of many Wi-Fi products.
+///
+/// Services are named by their type and protocol, for example `ipp` and
+/// [`Protocol::Tcp`] for `_ipp._tcp.local`; the service type is given without
+/// its leading underscore.
+///
+/// [`rfc6763`]: https://tools.ietf.org/html/rfc6763
+/// [`Protocol::Tcp`]: enum.Protocol.html#variant.Tcp
+pub trait ServiceDiscovery {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Find the instances of a service on the local link. Returns `Ok(n)`,
+	/// which means `n` instances have been placed in `&out[0..n]`.
+	fn browse(
+		&self,
+		service: &str,
+		protocol: Protocol,
+		out: &mut [ServiceInstance],
+	) -> nb::Result<usize, Self::Error>;
+
+	/// Find where a service instance can be reached. The instance's `TXT`
+	/// record is written to `txt` in the format read by [`TxtRecords`].
+	///
+	/// [`TxtRecords`]: struct.TxtRecords.html
+	fn resolve(
+		&self,
+		instance: &str,
+		service: &str,
+		protocol: Protocol,
+		txt: &mut [u8],
+	) -> nb::Result<ServiceInfo, Self::Error>;
+}
+
+/// This trait is an extension trait for [`ServiceDiscovery`] for stacks that
+/// can also announce the device's own services.
+///
+/// [`ServiceDiscovery`]: trait.ServiceDiscovery.html
+pub trait ServiceAnnounce: ServiceDiscovery {
+	/// Start announcing a service instance provided by this device on `port`,
+	/// with the given `TXT` key/value strings, such as `"path=/api"`.
+	fn announce(
+		&self,
+		instance: &str,
+		service: &str,
+		protocol: Protocol,
+		port: u16,
+		txt: &[&str],
+	) -> Result<(), Self::Error>;
+
+	/// Stop announcing a service instance, sending a goodbye so that peers
+	/// forget it.
+	fn withdraw(
+		&self,
+		instance: &str,
+		service: &str,
+		protocol: Protocol,
+	) -> Result<(), Self::Error>;
+}
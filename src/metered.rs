@@ -0,0 +1,290 @@
+use crate::{DatagramBuf, Mode, RecvOutcome, TcpStack, UdpStack};
+use core::cell::Cell;
+use no_std_net::SocketAddr;
+
+/// The totals accumulated by a [`Metered`] stack.
+///
+/// [`Metered`]: struct.Metered.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Metrics {
+	/// TCP connects and UDP opens that succeeded
+	pub connects: u32,
+	/// TCP connects and UDP opens that failed
+	pub connect_failures: u32,
+	/// Bytes written, over TCP and UDP
+	pub bytes_sent: u64,
+	/// Bytes read, over TCP and UDP
+	pub bytes_received: u64,
+	/// Reads and writes that returned `WouldBlock`
+	pub would_blocks: u32,
+	/// Other calls that failed
+	pub errors: u32,
+}
+
+/// A wrapper around a [`TcpStack`] and [`UdpStack`] that accumulates totals
+/// of its activity, which [`metrics`](#method.metrics) returns as a
+/// snapshot. It feeds device health telemetry the same way whatever the
+/// stack. Counters wrap around on overflow.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+pub struct Metered<S> {
+	stack: S,
+	metrics: Cell<Metrics>,
+}
+
+impl<S> Metered<S> {
+	/// Wrap `stack`, with all counters at zero.
+	pub fn new(stack: S) -> Self {
+		Metered {
+			stack,
+			metrics: Cell::new(Metrics::default()),
+		}
+	}
+
+	/// The totals accumulated so far.
+	pub fn metrics(&self) -> Metrics {
+		self.metrics.get()
+	}
+
+	/// Set all counters back to zero.
+	pub fn reset(&self) {
+		self.metrics.set(Metrics::default());
+	}
+
+	/// Get a reference to the wrapped stack.
+	pub fn inner(&self) -> &S {
+		&self.stack
+	}
+
+	/// Destroy the wrapper, returning the wrapped stack.
+	pub fn into_inner(self) -> S {
+		self.stack
+	}
+
+	fn update(&self, f: impl FnOnce(&mut Metrics)) {
+		let mut metrics = self.metrics.get();
+		f(&mut metrics);
+		self.metrics.set(metrics);
+	}
+
+	fn count_connect<T, E>(&self, result: &Result<T, E>) {
+		self.update(|m| match result {
+			Ok(_) => m.connects = m.connects.wrapping_add(1),
+			Err(_) => m.connect_failures = m.connect_failures.wrapping_add(1),
+		});
+	}
+
+	/// Count a read or write, which transferred `len` bytes if it succeeded.
+	fn count_transfer<T, E>(&self, result: &nb::Result<T, E>, len: usize, sent: bool) {
+		self.update(|m| match result {
+			Ok(_) if sent => m.bytes_sent = m.bytes_sent.wrapping_add(len as u64),
+			Ok(_) => m.bytes_received = m.bytes_received.wrapping_add(len as u64),
+			Err(nb::Error::WouldBlock) => m.would_blocks = m.would_blocks.wrapping_add(1),
+			Err(nb::Error::Other(_)) => m.errors = m.errors.wrapping_add(1),
+		});
+	}
+
+	fn count_error<T, E>(&self, result: &Result<T, E>) {
+		if result.is_err() {
+			self.update(|m| m.errors = m.errors.wrapping_add(1));
+		}
+	}
+}
+
+impl<S: TcpStack> TcpStack for Metered<S> {
+	type TcpSocket = S::TcpSocket;
+	type Error = S::Error;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		let result = self.stack.open(mode);
+		self.count_error(&result);
+		result
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		let result = self.stack.connect(socket, remote);
+		self.count_connect(&result);
+		result
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.stack.is_connected(socket)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.write(socket, buffer);
+		self.count_transfer(&result, *result.as_ref().unwrap_or(&0), true);
+		result
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let result = TcpStack::read(&self.stack, socket, buffer);
+		self.count_transfer(&result, *result.as_ref().unwrap_or(&0), false);
+		result
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		let result = self.stack.receive(socket, buffer);
+		let len = match result {
+			Ok(RecvOutcome::Data(n)) => n,
+			_ => 0,
+		};
+		self.count_transfer(&result, len, false);
+		result
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		let result = TcpStack::close(&self.stack, socket);
+		self.count_error(&result);
+		result
+	}
+}
+
+impl<S: UdpStack> UdpStack for Metered<S> {
+	type UdpSocket = S::UdpSocket;
+	type Error = S::Error;
+
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		let result = self.stack.open(remote, mode);
+		self.count_connect(&result);
+		result
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		let result = self.stack.write(socket, buffer);
+		self.count_transfer(&result, buffer.len(), true);
+		result
+	}
+
+	fn write_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.write_batch(socket, datagrams);
+		let sent = *result.as_ref().unwrap_or(&0);
+		let len = datagrams[..sent].iter().map(|d| d.len()).sum();
+		self.count_transfer(&result, len, true);
+		result
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let result = UdpStack::read(&self.stack, socket, buffer);
+		self.count_transfer(&result, *result.as_ref().unwrap_or(&0), false);
+		result
+	}
+
+	fn read_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.read_batch(socket, datagrams);
+		let received = *result.as_ref().unwrap_or(&0);
+		let len = datagrams[..received].iter().map(|d| d.len).sum();
+		self.count_transfer(&result, len, false);
+		result
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		let result = UdpStack::close(&self.stack, socket);
+		self.count_error(&result);
+		result
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Call, Expectation, MockStack};
+
+	fn remote() -> SocketAddr {
+		SocketAddr::new([192, 0, 2, 1].into(), 80)
+	}
+
+	#[test]
+	fn tcp_traffic_is_counted() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(remote())).fail(),
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(remote())),
+			Expectation::new(Call::TcpWrite(b"ping")),
+			Expectation::new(Call::TcpRead(b"pong")).blocked(),
+			Expectation::new(Call::TcpRead(b"pong")),
+			Expectation::new(Call::TcpRead(b"")).fail(),
+			Expectation::new(Call::TcpClose),
+		];
+		let stack = Metered::new(MockStack::new(&script));
+		let socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		assert!(stack.connect(socket, remote()).is_err());
+		let socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		let mut socket = stack.connect(socket, remote()).unwrap();
+		assert_eq!(TcpStack::write(&stack, &mut socket, b"ping"), Ok(4));
+		let mut buffer = [0; 8];
+		assert!(TcpStack::read(&stack, &mut socket, &mut buffer).is_err());
+		assert_eq!(TcpStack::read(&stack, &mut socket, &mut buffer), Ok(4));
+		assert!(TcpStack::read(&stack, &mut socket, &mut buffer).is_err());
+		TcpStack::close(&stack, socket).unwrap();
+
+		assert_eq!(
+			stack.metrics(),
+			Metrics {
+				connects: 1,
+				connect_failures: 1,
+				bytes_sent: 4,
+				bytes_received: 4,
+				would_blocks: 1,
+				errors: 1,
+			}
+		);
+		stack.reset();
+		assert_eq!(stack.metrics(), Metrics::default());
+	}
+
+	#[test]
+	fn udp_traffic_is_counted() {
+		let script = [
+			Expectation::new(Call::UdpOpen(remote())),
+			Expectation::new(Call::UdpWrite(b"ping")),
+			Expectation::new(Call::UdpWrite(b"pong")).fail(),
+			Expectation::new(Call::UdpRead(b"hi")),
+			Expectation::new(Call::UdpClose).fail(),
+		];
+		let stack = Metered::new(MockStack::new(&script));
+		let mut socket = UdpStack::open(&stack, remote(), Mode::Blocking).unwrap();
+		UdpStack::write(&stack, &mut socket, b"ping").unwrap();
+		assert!(UdpStack::write(&stack, &mut socket, b"pong").is_err());
+		let mut buffer = [0; 8];
+		assert_eq!(UdpStack::read(&stack, &mut socket, &mut buffer), Ok(2));
+		assert!(UdpStack::close(&stack, socket).is_err());
+
+		assert_eq!(
+			stack.metrics(),
+			Metrics {
+				connects: 1,
+				bytes_sent: 4,
+				bytes_received: 2,
+				errors: 2,
+				..Metrics::default()
+			}
+		);
+	}
+}
@@ -0,0 +1,491 @@
+use crate::{Mode, NetworkErrorKind, TcpError, TcpStack, UdpError, UdpStack};
+use core::cell::Cell;
+use no_std_net::SocketAddr;
+
+/// A call a [`MockStack`] expects, with what it returns.
+///
+/// [`MockStack`]: struct.MockStack.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Call<'a> {
+	/// [`TcpStack::open`](trait.TcpStack.html#tymethod.open)
+	TcpOpen,
+	/// [`TcpStack::connect`](trait.TcpStack.html#tymethod.connect) to the
+	/// given address
+	TcpConnect(SocketAddr),
+	/// [`TcpStack::write`](trait.TcpStack.html#tymethod.write) of the given
+	/// bytes, which may be spread over several writes
+	TcpWrite(&'a [u8]),
+	/// [`TcpStack::read`](trait.TcpStack.html#tymethod.read), returning the
+	/// given bytes, which may be spread over several reads
+	TcpRead(&'a [u8]),
+	/// [`TcpStack::close`](trait.TcpStack.html#tymethod.close)
+	TcpClose,
+	/// [`UdpStack::open`](trait.UdpStack.html#tymethod.open) to the given
+	/// address
+	UdpOpen(SocketAddr),
+	/// [`UdpStack::write`](trait.UdpStack.html#tymethod.write) of the given
+	/// datagram
+	UdpWrite(&'a [u8]),
+	/// [`UdpStack::read`](trait.UdpStack.html#tymethod.read), returning the
+	/// given datagram
+	UdpRead(&'a [u8]),
+	/// [`UdpStack::close`](trait.UdpStack.html#tymethod.close)
+	UdpClose,
+}
+
+/// What an expected call returns, see [`Expectation`].
+///
+/// [`Expectation`]: struct.Expectation.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Outcome {
+	/// The call succeeds
+	Ok,
+	/// The call returns `WouldBlock`, which only reads and writes can
+	Blocked,
+	/// The call fails with [`MockError`]
+	///
+	/// [`MockError`]: struct.MockError.html
+	Fail,
+}
+
+/// One step of the script of a [`MockStack`]: a call it expects, and its
+/// outcome.
+///
+/// [`MockStack`]: struct.MockStack.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Expectation<'a> {
+	/// The call expected
+	pub call: Call<'a>,
+	/// What the call returns
+	pub outcome: Outcome,
+}
+
+impl<'a> Expectation<'a> {
+	/// Expect `call`, and let it succeed.
+	pub fn new(call: Call<'a>) -> Self {
+		Expectation {
+			call,
+			outcome: Outcome::Ok,
+		}
+	}
+
+	/// Let the call return `WouldBlock` instead.
+	pub fn blocked(self) -> Self {
+		Expectation {
+			outcome: Outcome::Blocked,
+			..self
+		}
+	}
+
+	/// Let the call fail with [`MockError`] instead.
+	///
+	/// [`MockError`]: struct.MockError.html
+	pub fn fail(self) -> Self {
+		Expectation {
+			outcome: Outcome::Fail,
+			..self
+		}
+	}
+}
+
+/// The error returned by calls a [`MockStack`] was scripted to fail.
+///
+/// [`MockStack`]: struct.MockStack.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MockError;
+
+impl TcpError for MockError {
+	fn kind(&self) -> NetworkErrorKind {
+		NetworkErrorKind::Other
+	}
+}
+
+impl UdpError for MockError {
+	fn kind(&self) -> NetworkErrorKind {
+		NetworkErrorKind::Other
+	}
+}
+
+impl core::fmt::Display for MockError {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		f.write_str("the call was scripted to fail")
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MockError {}
+
+/// A socket of a [`MockStack`].
+///
+/// [`MockStack`]: struct.MockStack.html
+#[derive(Debug)]
+pub struct MockSocket {
+	connected: bool,
+}
+
+/// A [`TcpStack`] and [`UdpStack`] that plays a script of expected calls and
+/// their outcomes, for precise tests of drivers and clients built on this
+/// crate: expect a connect to some address, then return these bytes on read,
+/// then an error.
+///
+/// Calls must come in the order of the script. A call that doesn't match the
+/// next expectation panics, as does dropping the stack before the script is
+/// over; [`remaining`](#method.remaining) tells what is left of it.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+pub struct MockStack<'a> {
+	script: &'a [Expectation<'a>],
+	next: Cell<usize>,
+	/// How much of the data of the next TCP read or write was already
+	/// transferred by previous calls
+	offset: Cell<usize>,
+}
+
+impl<'a> MockStack<'a> {
+	/// Create a stack expecting the calls of `script`, in order.
+	pub fn new(script: &'a [Expectation<'a>]) -> Self {
+		MockStack {
+			script,
+			next: Cell::new(0),
+			offset: Cell::new(0),
+		}
+	}
+
+	/// The expectations that haven't been met yet.
+	pub fn remaining(&self) -> &'a [Expectation<'a>] {
+		&self.script[self.next.get()..]
+	}
+
+	fn peek(&self, called: &str) -> &'a Expectation<'a> {
+		match self.script.get(self.next.get()) {
+			Some(expectation) => expectation,
+			None => panic!("MockStack: unexpected call of {}", called),
+		}
+	}
+
+	fn advance(&self) {
+		self.next.set(self.next.get() + 1);
+		self.offset.set(0);
+	}
+
+	/// Check that the next expectation is `expected` with any outcome, and
+	/// move past it.
+	fn expect(&self, expected: Call, called: &str) -> Outcome {
+		let expectation = self.peek(called);
+		if expectation.call != expected {
+			panic!(
+				"MockStack: expected {:?}, got {:?}",
+				expectation.call, expected
+			);
+		}
+		self.advance();
+		expectation.outcome
+	}
+
+	fn mismatch(&self, expectation: &Expectation, called: &str) -> ! {
+		panic!(
+			"MockStack: expected {:?}, got a call of {}",
+			expectation.call, called
+		)
+	}
+
+	/// Turn the outcome of a call that can't block into its result.
+	fn result(outcome: Outcome, called: &str) -> Result<(), MockError> {
+		match outcome {
+			Outcome::Ok => Ok(()),
+			Outcome::Fail => Err(MockError),
+			Outcome::Blocked => panic!("MockStack: {} can't return WouldBlock", called),
+		}
+	}
+
+	/// Handle the outcomes of reads and writes other than success, moving
+	/// past failed calls.
+	fn blocked_or_failed(&self, outcome: Outcome) -> nb::Result<(), MockError> {
+		match outcome {
+			Outcome::Ok => Ok(()),
+			Outcome::Blocked => {
+				self.advance();
+				Err(nb::Error::WouldBlock)
+			}
+			Outcome::Fail => {
+				self.advance();
+				Err(nb::Error::Other(MockError))
+			}
+		}
+	}
+
+	/// Transfer the part of `data` that wasn't transferred yet, up to `len`
+	/// bytes, returning its range.
+	fn transfer(&self, data: &[u8], len: usize) -> core::ops::Range<usize> {
+		let start = self.offset.get();
+		let end = core::cmp::min(data.len(), start + len);
+		if end == data.len() {
+			self.advance();
+		} else {
+			self.offset.set(end);
+		}
+		start..end
+	}
+}
+
+impl<'a> Drop for MockStack<'a> {
+	fn drop(&mut self) {
+		// Don't turn the failure of a test into an abort.
+		#[cfg(feature = "std")]
+		{
+			if std::thread::panicking() {
+				return;
+			}
+		}
+		if let Some(expectation) = self.remaining().first() {
+			panic!(
+				"MockStack: dropped while still expecting {:?}",
+				expectation.call
+			);
+		}
+	}
+}
+
+impl<'a> TcpStack for MockStack<'a> {
+	type TcpSocket = MockSocket;
+	type Error = MockError;
+
+	fn open(&self, _mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		let outcome = self.expect(Call::TcpOpen, "TcpStack::open");
+		Self::result(outcome, "TcpStack::open")?;
+		Ok(MockSocket { connected: false })
+	}
+
+	fn connect(
+		&self,
+		mut socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		let outcome = self.expect(Call::TcpConnect(remote), "TcpStack::connect");
+		Self::result(outcome, "TcpStack::connect")?;
+		socket.connected = true;
+		Ok(socket)
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		Ok(socket.connected)
+	}
+
+	fn write(
+		&self,
+		_socket: &mut Self::TcpSocket,
+		buffer: &[u8],
+	) -> nb::Result<usize, Self::Error> {
+		let expectation = self.peek("TcpStack::write");
+		let data = match expectation.call {
+			Call::TcpWrite(data) => data,
+			_ => self.mismatch(expectation, "TcpStack::write"),
+		};
+		self.blocked_or_failed(expectation.outcome)?;
+		let range = self.transfer(data, buffer.len());
+		let written = &buffer[..range.len()];
+		if written != &data[range] {
+			panic!(
+				"MockStack: expected a write of {:?}, got {:?}",
+				data, buffer
+			);
+		}
+		Ok(written.len())
+	}
+
+	fn read(
+		&self,
+		_socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let expectation = self.peek("TcpStack::read");
+		let data = match expectation.call {
+			Call::TcpRead(data) => data,
+			_ => self.mismatch(expectation, "TcpStack::read"),
+		};
+		self.blocked_or_failed(expectation.outcome)?;
+		let range = self.transfer(data, buffer.len());
+		let len = range.len();
+		buffer[..len].copy_from_slice(&data[range]);
+		Ok(len)
+	}
+
+	fn close(&self, _socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		let outcome = self.expect(Call::TcpClose, "TcpStack::close");
+		Self::result(outcome, "TcpStack::close")
+	}
+}
+
+impl<'a> UdpStack for MockStack<'a> {
+	type UdpSocket = MockSocket;
+	type Error = MockError;
+
+	fn open(&self, remote: SocketAddr, _mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		let outcome = self.expect(Call::UdpOpen(remote), "UdpStack::open");
+		Self::result(outcome, "UdpStack::open")?;
+		Ok(MockSocket { connected: true })
+	}
+
+	fn write(&self, _socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		let expectation = self.peek("UdpStack::write");
+		match expectation.call {
+			Call::UdpWrite(data) if data == buffer => {}
+			_ => panic!(
+				"MockStack: expected {:?}, got UdpWrite({:?})",
+				expectation.call, buffer
+			),
+		}
+		self.blocked_or_failed(expectation.outcome)?;
+		self.advance();
+		Ok(())
+	}
+
+	fn read(
+		&self,
+		_socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let expectation = self.peek("UdpStack::read");
+		let data = match expectation.call {
+			Call::UdpRead(data) => data,
+			_ => self.mismatch(expectation, "UdpStack::read"),
+		};
+		self.blocked_or_failed(expectation.outcome)?;
+		self.advance();
+		// Like real datagrams, those that don't fit lose their tail.
+		let len = core::cmp::min(data.len(), buffer.len());
+		buffer[..len].copy_from_slice(&data[..len]);
+		Ok(len)
+	}
+
+	fn close(&self, _socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		let outcome = self.expect(Call::UdpClose, "UdpStack::close");
+		Self::result(outcome, "UdpStack::close")
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use no_std_net::IpAddr;
+
+	fn remote() -> SocketAddr {
+		SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 80)
+	}
+
+	#[test]
+	fn tcp_script() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(remote())),
+			Expectation::new(Call::TcpWrite(b"ping")),
+			Expectation::new(Call::TcpRead(b"")).blocked(),
+			Expectation::new(Call::TcpRead(b"pong")),
+			Expectation::new(Call::TcpRead(b"")).fail(),
+			Expectation::new(Call::TcpClose),
+		];
+		let stack = MockStack::new(&script);
+		let socket = TcpStack::open(&stack, Mode::Blocking).unwrap();
+		assert!(!stack.is_connected(&socket).unwrap());
+		let mut socket = stack.connect(socket, remote()).unwrap();
+		assert!(stack.is_connected(&socket).unwrap());
+
+		// Data may be spread over several calls.
+		assert_eq!(TcpStack::write(&stack, &mut socket, b"pi"), Ok(2));
+		assert_eq!(TcpStack::write(&stack, &mut socket, b"ng"), Ok(2));
+		let mut buffer = [0; 3];
+		assert_eq!(
+			TcpStack::read(&stack, &mut socket, &mut buffer),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(TcpStack::read(&stack, &mut socket, &mut buffer), Ok(3));
+		assert_eq!(&buffer, b"pon");
+		assert_eq!(TcpStack::read(&stack, &mut socket, &mut buffer), Ok(1));
+		assert_eq!(buffer[0], b'g');
+		assert_eq!(
+			TcpStack::read(&stack, &mut socket, &mut buffer),
+			Err(nb::Error::Other(MockError))
+		);
+		assert_eq!(stack.remaining(), &script[6..]);
+		TcpStack::close(&stack, socket).unwrap();
+		assert!(stack.remaining().is_empty());
+	}
+
+	#[test]
+	fn failing_calls() {
+		let script = [
+			Expectation::new(Call::TcpOpen).fail(),
+			Expectation::new(Call::UdpOpen(remote())),
+			Expectation::new(Call::UdpWrite(b"x")).blocked(),
+			Expectation::new(Call::UdpClose).fail(),
+		];
+		let stack = MockStack::new(&script);
+		assert_eq!(
+			TcpStack::open(&stack, Mode::Blocking).unwrap_err(),
+			MockError
+		);
+		let mut socket = UdpStack::open(&stack, remote(), Mode::Blocking).unwrap();
+		assert_eq!(
+			UdpStack::write(&stack, &mut socket, b"x"),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(UdpStack::close(&stack, socket), Err(MockError));
+	}
+
+	#[test]
+	fn udp_script() {
+		let script = [
+			Expectation::new(Call::UdpOpen(remote())),
+			Expectation::new(Call::UdpWrite(b"query")),
+			Expectation::new(Call::UdpRead(b"a long answer")),
+			Expectation::new(Call::UdpClose),
+		];
+		let stack = MockStack::new(&script);
+		let mut socket = UdpStack::open(&stack, remote(), Mode::Blocking).unwrap();
+		UdpStack::write(&stack, &mut socket, b"query").unwrap();
+		// Datagrams that don't fit lose their tail.
+		let mut buffer = [0; 6];
+		assert_eq!(UdpStack::read(&stack, &mut socket, &mut buffer), Ok(6));
+		assert_eq!(&buffer, b"a long");
+		UdpStack::close(&stack, socket).unwrap();
+	}
+
+	#[test]
+	#[should_panic(expected = "dropped while still expecting TcpClose")]
+	fn dropping_before_the_end_of_the_script() {
+		let script = [Expectation::new(Call::TcpClose)];
+		MockStack::new(&script);
+	}
+
+	// Without `std`, the stack can't tell that a test is already failing,
+	// and panics again when dropped.
+	#[cfg(feature = "std")]
+	#[test]
+	#[should_panic(expected = "expected TcpOpen, got a call of TcpStack::write")]
+	fn unexpected_call() {
+		let script = [Expectation::new(Call::TcpOpen)];
+		let stack = MockStack::new(&script);
+		TcpStack::write(&stack, &mut MockSocket { connected: true }, b"x").ok();
+	}
+
+	#[cfg(feature = "std")]
+	#[test]
+	#[should_panic(expected = "expected a write of")]
+	fn unexpected_data() {
+		let script = [Expectation::new(Call::TcpWrite(b"ping"))];
+		let stack = MockStack::new(&script);
+		TcpStack::write(&stack, &mut MockSocket { connected: true }, b"pong").ok();
+	}
+
+	#[cfg(feature = "std")]
+	#[test]
+	#[should_panic(expected = "TcpStack::connect can't return WouldBlock")]
+	fn blocked_connect() {
+		let script = [Expectation::new(Call::TcpConnect(remote())).blocked()];
+		let stack = MockStack::new(&script);
+		stack
+			.connect(MockSocket { connected: false }, remote())
+			.ok();
+	}
+}
@@ -0,0 +1,113 @@
+use crate::{Interface, UdpStack};
+use no_std_net::IpAddr;
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that can send
+/// and receive multicast datagrams. It provides the group membership
+/// operations needed by discovery protocols such as mDNS, SSDP and multicast
+/// CoAP.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpMulticast: UdpStack {
+	/// Join the multicast group `group` on the given interface, so that
+	/// datagrams sent to the group are delivered to `socket`.
+	///
+	/// `group` must be an IPv4 or IPv6 multicast address.
+	fn join_multicast_group(
+		&self,
+		socket: &mut Self::UdpSocket,
+		group: IpAddr,
+		interface: Interface,
+	) -> Result<(), Self::Error>;
+
+	/// Leave a multicast group previously joined with
+	/// [`join_multicast_group`](#tymethod.join_multicast_group).
+	fn leave_multicast_group(
+		&self,
+		socket: &mut Self::UdpSocket,
+		group: IpAddr,
+		interface: Interface,
+	) -> Result<(), Self::Error>;
+
+	/// Set the time-to-live (IPv4) or hop limit (IPv6) of multicast datagrams
+	/// sent from this socket (`IP_MULTICAST_TTL` / `IPV6_MULTICAST_HOPS`).
+	/// This is separate from the unicast TTL, and usually defaults to 1 so
+	/// that multicast traffic stays on the local link.
+	fn set_multicast_ttl(&self, socket: &mut Self::UdpSocket, ttl: u8) -> Result<(), Self::Error>;
+
+	/// Choose whether multicast datagrams sent from this socket are looped
+	/// back to sockets on the local host that have joined the group
+	/// (`IP_MULTICAST_LOOP` / `IPV6_MULTICAST_LOOP`).
+	fn set_multicast_loop(
+		&self,
+		socket: &mut Self::UdpSocket,
+		enable: bool,
+	) -> Result<(), Self::Error>;
+
+	/// Select the interface multicast datagrams sent from this socket leave
+	/// through (`IP_MULTICAST_IF` / `IPV6_MULTICAST_IF`).
+	fn set_multicast_interface(
+		&self,
+		socket: &mut Self::UdpSocket,
+		interface: Interface,
+	) -> Result<(), Self::Error>;
+}
+
+/// This trait is an extension trait for [`UdpMulticast`] for stacks that
+/// support source-specific multicast (IGMPv3 / MLDv2), where a socket only
+/// receives datagrams sent to a group by a given source.
+///
+/// [`UdpMulticast`]: trait.UdpMulticast.html
+pub trait UdpSourceMulticast: UdpMulticast {
+	/// Join the multicast group `group` on the given interface, only
+	/// accepting datagrams sent by `source`. This can be called several times
+	/// with different sources to accept datagrams from each of them.
+	fn join_multicast_source(
+		&self,
+		socket: &mut Self::UdpSocket,
+		group: IpAddr,
+		source: IpAddr,
+		interface: Interface,
+	) -> Result<(), Self::Error>;
+
+	/// Stop accepting datagrams sent to `group` by `source`, as previously
+	/// configured with [`join_multicast_source`](#tymethod.join_multicast_source).
+	fn leave_multicast_source(
+		&self,
+		socket: &mut Self::UdpSocket,
+		group: IpAddr,
+		source: IpAddr,
+		interface: Interface,
+	) -> Result<(), Self::Error>;
+}
+
+/// This trait is implemented by stacks that manage multicast group
+/// membership per interface rather than per socket, as IGMP and MLD do.
+/// Memberships made through it outlive sockets, which suits groups that are
+/// used for the whole life of the device.
+///
+/// Memberships made with [`UdpMulticast`] may or may not show up here,
+/// depending on the stack.
+///
+/// [`UdpMulticast`]: trait.UdpMulticast.html
+pub trait MulticastGroups {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Join the multicast group `group` on the given interface.
+	fn join_group(&self, group: IpAddr, interface: Interface) -> Result<(), Self::Error>;
+
+	/// Leave the multicast group `group` on the given interface.
+	fn leave_group(&self, group: IpAddr, interface: Interface) -> Result<(), Self::Error>;
+
+	/// List the groups joined on the given interface. Returns `Ok(n)`, which
+	/// means `n` groups have been placed in `&out[0..n]`. If more groups are
+	/// joined than fit in `out`, only the first `out.len()` are returned.
+	fn joined_groups(&self, interface: Interface, out: &mut [IpAddr])
+		-> Result<usize, Self::Error>;
+
+	/// Send an unsolicited membership report for `group`, or for every joined
+	/// group with `None`, rather than waiting for the router's next query.
+	/// This is useful after the link came back up, so that multicast traffic
+	/// resumes straight away.
+	fn send_report(&self, group: Option<IpAddr>, interface: Interface) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,81 @@
+use crate::{Interface, MacAddr};
+use no_std_net::IpAddr;
+
+/// The state of an entry in a [`NeighborTable`], following the neighbor
+/// unreachability detection states of [`rfc4861`].
+///
+/// [`NeighborTable`]: trait.NeighborTable.html
+/// [`rfc4861`]: https://tools.ietf.org/html/rfc4861
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NeighborState {
+	/// Resolution is in progress and no link-layer address is known yet
+	Incomplete,
+	/// The neighbor was recently confirmed to be reachable
+	Reachable,
+	/// The neighbor has not been confirmed for a while, but is assumed to
+	/// still be there
+	Stale,
+	/// The entry was pinned, and never expires
+	Static,
+}
+
+/// An entry of a [`NeighborTable`].
+///
+/// [`NeighborTable`]: trait.NeighborTable.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Neighbor {
+	/// The IP address of the neighbor
+	pub ip: IpAddr,
+	/// The link-layer address of the neighbor, meaningless while the entry is
+	/// [`NeighborState::Incomplete`]
+	///
+	/// [`NeighborState::Incomplete`]: enum.NeighborState.html#variant.Incomplete
+	pub mac: MacAddr,
+	/// The state of the entry
+	pub state: NeighborState,
+}
+
+/// This trait is implemented by stacks on Ethernet-like links that give
+/// access to their ARP and NDP neighbor tables. Wake-on-LAN style flows need
+/// the link-layer address of peers, and inspecting the table helps diagnose
+/// an unreachable gateway.
+pub trait NeighborTable {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Get the link-layer address of `ip` on the given interface, sending ARP
+	/// requests or neighbor solicitations if it isn't known yet.
+	///
+	/// Returns `WouldBlock` until the neighbor answered; no answer is
+	/// eventually reported as an error.
+	fn lookup_neighbor(&self, ip: IpAddr, interface: Interface)
+		-> nb::Result<MacAddr, Self::Error>;
+
+	/// List the entries of the table of the given interface. Returns
+	/// `Ok(n)`, which means `n` entries have been placed in `&out[0..n]`. If
+	/// the table holds more entries than fit in `out`, only the first
+	/// `out.len()` are returned.
+	fn neighbors(&self, interface: Interface, out: &mut [Neighbor]) -> Result<usize, Self::Error>;
+}
+
+/// This trait is an extension trait for [`NeighborTable`] for stacks that can
+/// pin entries, so that a peer stays reachable without ARP or NDP, such as a
+/// gateway on a link where neighbor discovery is unreliable.
+///
+/// [`NeighborTable`]: trait.NeighborTable.html
+pub trait StaticNeighbors: NeighborTable {
+	/// Add or replace the entry for `ip` on the given interface with a
+	/// [`NeighborState::Static`] entry for `mac`.
+	///
+	/// [`NeighborState::Static`]: enum.NeighborState.html#variant.Static
+	fn pin_neighbor(
+		&self,
+		ip: IpAddr,
+		mac: MacAddr,
+		interface: Interface,
+	) -> Result<(), Self::Error>;
+
+	/// Remove the pinned entry for `ip` on the given interface, letting the
+	/// stack resolve it again.
+	fn unpin_neighbor(&self, ip: IpAddr, interface: Interface) -> Result<(), Self::Error>;
+}
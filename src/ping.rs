@@ -0,0 +1,145 @@
+use core::time::Duration;
+use no_std_net::IpAddr;
+
+/// The outcome of a successful [`Ping::ping`].
+///
+/// [`Ping::ping`]: trait.Ping.html#tymethod.ping
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RttInfo {
+	/// The round-trip time
+	pub rtt: Duration,
+	/// The time to live, or hop limit, of the reply, if the stack reports it
+	pub ttl: Option<u8>,
+	/// The number of payload bytes in the reply
+	pub len: usize,
+}
+
+/// This trait is implemented by stacks that can send ICMP or ICMPv6 echo
+/// requests, so that connectivity checks and link-quality probes can be
+/// written once for every stack. Virtually every offload modem and host stack
+/// exposes ping.
+pub trait Ping {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Send an echo request carrying `payload` to `remote`, and wait up to
+	/// `timeout` for the reply.
+	///
+	/// Returns `WouldBlock` until the reply arrives or the timeout expires;
+	/// calling it again with the same arguments keeps waiting for the same
+	/// request. No reply within the timeout is reported as an error.
+	fn ping(
+		&self,
+		remote: IpAddr,
+		payload: &[u8],
+		timeout: Duration,
+	) -> nb::Result<RttInfo, Self::Error>;
+}
+
+/// The outcome of a [`PingTtl::ping_with_ttl`].
+///
+/// [`PingTtl::ping_with_ttl`]: trait.PingTtl.html#tymethod.ping_with_ttl
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PingReply {
+	/// The destination replied
+	Echo(RttInfo),
+	/// A router on the way reported that the time to live ran out
+	TimeExceeded {
+		/// The address of the router
+		from: IpAddr,
+		/// The round-trip time
+		rtt: Duration,
+	},
+	/// Nothing replied in time
+	Timeout,
+}
+
+/// This trait is an extension trait for [`Ping`] for stacks that can set the
+/// time to live of echo requests and report where they expired, which is
+/// what [`Traceroute`] is built on.
+///
+/// [`Ping`]: trait.Ping.html
+/// [`Traceroute`]: struct.Traceroute.html
+pub trait PingTtl: Ping {
+	/// Send an echo request like [`ping`](trait.Ping.html#tymethod.ping),
+	/// with a time to live, or hop limit, of `ttl`. Unlike `ping`, no reply
+	/// within the timeout is not an error.
+	fn ping_with_ttl(
+		&self,
+		remote: IpAddr,
+		payload: &[u8],
+		timeout: Duration,
+		ttl: u8,
+	) -> nb::Result<PingReply, Self::Error>;
+}
+
+/// One hop of a route, as found by [`Traceroute`].
+///
+/// [`Traceroute`]: struct.Traceroute.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Hop {
+	/// The address of the router or destination, if it replied
+	pub addr: Option<IpAddr>,
+	/// The round-trip time to it, if it replied
+	pub rtt: Option<Duration>,
+}
+
+/// Finds the route to a destination, by sending echo requests with growing
+/// time to live and noting which router reports each of them as expired.
+/// It is invaluable for debugging routing problems in the field, such as on
+/// cellular networks.
+///
+/// Hops are written to a caller-provided buffer, hop `n` going to index
+/// `n - 1`, so the buffer length bounds the number of hops probed.
+pub struct Traceroute {
+	remote: IpAddr,
+	timeout: Duration,
+	/// The time to live of the next probe, which ends up one past the last hop
+	ttl: u16,
+	done: bool,
+}
+
+impl Traceroute {
+	/// Prepare to trace the route to `remote`, waiting up to `timeout` for
+	/// each hop to reply.
+	pub fn new(remote: IpAddr, timeout: Duration) -> Self {
+		Traceroute {
+			remote,
+			timeout,
+			ttl: 1,
+			done: false,
+		}
+	}
+
+	/// Probe the hops towards the destination, returning `Ok(n)` once the
+	/// destination replied or `out` is full, which means `n` hops have been
+	/// placed in `&out[0..n]`.
+	///
+	/// Returns `WouldBlock` while a probe is in flight; it must be called
+	/// again with the same `out` until the route is complete.
+	pub fn poll<P: PingTtl>(&mut self, pinger: &P, out: &mut [Hop]) -> nb::Result<usize, P::Error> {
+		let max_hops = core::cmp::min(out.len(), usize::from(core::u8::MAX));
+		while !self.done && usize::from(self.ttl) <= max_hops {
+			let reply =
+				pinger.ping_with_ttl(self.remote, &[0; 32], self.timeout, self.ttl as u8)?;
+			out[usize::from(self.ttl) - 1] = match reply {
+				PingReply::Echo(info) => {
+					self.done = true;
+					Hop {
+						addr: Some(self.remote),
+						rtt: Some(info.rtt),
+					}
+				}
+				PingReply::TimeExceeded { from, rtt } => Hop {
+					addr: Some(from),
+					rtt: Some(rtt),
+				},
+				PingReply::Timeout => Hop::default(),
+			};
+			if !self.done {
+				self.ttl += 1;
+			}
+		}
+		Ok(core::cmp::min(usize::from(self.ttl), max_hops))
+	}
+}
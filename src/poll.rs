@@ -0,0 +1,79 @@
+use crate::{TcpStack, UdpStack};
+use core::time::Duration;
+
+/// Which ways a socket can be used without blocking, or is of interest, see
+/// [`PollEntry`].
+///
+/// [`PollEntry`]: struct.PollEntry.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Readiness {
+	/// Data can be read, or the connection was closed by the peer
+	pub readable: bool,
+	/// Data can be written
+	pub writable: bool,
+}
+
+/// A socket of either kind of a [`Poller`].
+///
+/// [`Poller`]: trait.Poller.html
+#[derive(Debug)]
+pub enum PollSocket<'a, T, U> {
+	/// A TCP socket
+	Tcp(&'a T),
+	/// A UDP socket
+	Udp(&'a U),
+}
+
+/// A socket to wait on with [`Poller::poll`], together with what to wait for
+/// and, once polled, what it is ready for.
+///
+/// [`Poller::poll`]: trait.Poller.html#tymethod.poll
+#[derive(Debug)]
+pub struct PollEntry<'a, T, U> {
+	/// The socket to wait on
+	pub socket: PollSocket<'a, T, U>,
+	/// What to wait for
+	pub interest: Readiness,
+	/// What the socket is ready for, among what was asked for in `interest`,
+	/// set by [`Poller::poll`]
+	///
+	/// [`Poller::poll`]: trait.Poller.html#tymethod.poll
+	pub ready: Readiness,
+}
+
+impl<'a, T, U> PollEntry<'a, T, U> {
+	/// Wait on `socket` for `interest`.
+	pub fn new(socket: PollSocket<'a, T, U>, interest: Readiness) -> Self {
+		PollEntry {
+			socket,
+			interest,
+			ready: Readiness::default(),
+		}
+	}
+}
+
+/// The type of the entries a [`Poller`] waits on.
+///
+/// [`Poller`]: trait.Poller.html
+pub type PollEntryOf<'a, S> = PollEntry<'a, <S as TcpStack>::TcpSocket, <S as UdpStack>::UdpSocket>;
+
+/// This trait is an extension trait for stacks that implement both
+/// [`TcpStack`] and [`UdpStack`], and can wait for any of a set of sockets to
+/// become ready, like `poll(2)`. Applications juggling several protocols, such
+/// as MQTT, DNS and SNTP, can then sleep until there is something to do
+/// rather than trying every socket in turn until one stops returning
+/// `WouldBlock`.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait Poller: TcpStack + UdpStack {
+	/// Wait until at least one of `entries` is ready for what it is
+	/// interested in, or for `timeout` if given, and set the
+	/// `ready` field of every entry. Returns how many entries are ready,
+	/// which is 0 if the timeout expired.
+	fn poll(
+		&self,
+		entries: &mut [PollEntryOf<Self>],
+		timeout: Option<Duration>,
+	) -> Result<usize, <Self as TcpStack>::Error>;
+}
@@ -0,0 +1,208 @@
+use crate::{TcpStack, UdpStack};
+use core::cell::RefCell;
+use heapless::{ArrayLength, Vec};
+
+/// This trait is implemented by pools of fixed-size buffers, which stacks and
+/// protocol layers pass ownership of instead of copying payloads at every
+/// layer boundary, see [`UdpPooled`] and [`TcpPooled`].
+///
+/// [`UdpPooled`]: trait.UdpPooled.html
+/// [`TcpPooled`]: trait.TcpPooled.html
+pub trait BufferPool {
+	/// A handle to one buffer of the pool, which owns it until it is
+	/// released
+	type Buffer: AsRef<[u8]> + AsMut<[u8]>;
+
+	/// Take a buffer out of the pool, or return `None` if all are in use.
+	fn acquire(&self) -> Option<Self::Buffer>;
+
+	/// Give a buffer back to the pool, or hand it back if the pool has no
+	/// room for it, as when it didn't come from the pool.
+	fn release(&self, buffer: Self::Buffer) -> Result<(), Self::Buffer>;
+}
+
+/// A buffer of a [`BufferPool`] holding a payload in its first `len` bytes.
+///
+/// [`BufferPool`]: trait.BufferPool.html
+#[derive(Debug)]
+pub struct Pooled<B> {
+	/// The buffer
+	pub buffer: B,
+	/// The length of the payload
+	pub len: usize,
+}
+
+impl<B: AsRef<[u8]>> Pooled<B> {
+	/// Wrap `buffer`, whose first `len` bytes are the payload.
+	pub fn new(buffer: B, len: usize) -> Self {
+		Pooled { buffer, len }
+	}
+
+	/// The payload.
+	pub fn data(&self) -> &[u8] {
+		&self.buffer.as_ref()[..self.len]
+	}
+}
+
+/// The error of a pooled write that was not accepted, which hands the buffer
+/// back to the caller.
+#[derive(Debug)]
+pub struct Rejected<B, E> {
+	/// The buffer that was not written
+	pub packet: Pooled<B>,
+	/// Why it was not written, which may be `WouldBlock`
+	pub error: nb::Error<E>,
+}
+
+/// The buffer type of the pool of a [`UdpPooled`] or [`TcpPooled`] stack.
+///
+/// [`UdpPooled`]: trait.UdpPooled.html
+/// [`TcpPooled`]: trait.TcpPooled.html
+pub type PoolBuffer<P> = <P as BufferPool>::Buffer;
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that exchange
+/// datagrams in buffers of a [`BufferPool`], without copying them.
+///
+/// Buffers to write are acquired from the stack's [`pool`](#tymethod.pool);
+/// a written buffer belongs to the stack, which releases it once the
+/// datagram is sent. Buffers returned by reads belong to the caller, who
+/// releases them to the pool once done.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`BufferPool`]: trait.BufferPool.html
+pub trait UdpPooled: UdpStack {
+	/// The pool buffers come from
+	type Pool: BufferPool;
+
+	/// The pool buffers come from.
+	fn pool(&self) -> &Self::Pool;
+
+	/// Send the payload of `packet` as one datagram to the remote host,
+	/// handing the buffer over to the stack. If the datagram is not accepted,
+	/// the buffer is handed back.
+	fn write_pooled(
+		&self,
+		socket: &mut Self::UdpSocket,
+		packet: Pooled<PoolBuffer<Self::Pool>>,
+	) -> Result<(), Rejected<PoolBuffer<Self::Pool>, Self::Error>>;
+
+	/// Read a datagram the remote host has sent to us, in the buffer it was
+	/// received into.
+	fn read_pooled(
+		&self,
+		socket: &mut Self::UdpSocket,
+	) -> nb::Result<Pooled<PoolBuffer<Self::Pool>>, Self::Error>;
+}
+
+/// This trait is an extension trait for [`TcpStack`] for stacks that exchange
+/// stream data in buffers of a [`BufferPool`], without copying it. Buffers
+/// are owned like for [`UdpPooled`].
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`BufferPool`]: trait.BufferPool.html
+/// [`UdpPooled`]: trait.UdpPooled.html
+pub trait TcpPooled: TcpStack {
+	/// The pool buffers come from
+	type Pool: BufferPool;
+
+	/// The pool buffers come from.
+	fn pool(&self) -> &Self::Pool;
+
+	/// Queue the whole payload of `packet` on the stream, handing the buffer
+	/// over to the stack. If it can't all be queued, none of it is and the
+	/// buffer is handed back.
+	fn write_pooled(
+		&self,
+		socket: &mut Self::TcpSocket,
+		packet: Pooled<PoolBuffer<Self::Pool>>,
+	) -> Result<(), Rejected<PoolBuffer<Self::Pool>, Self::Error>>;
+
+	/// Read the next segment of the stream, in the buffer it was received
+	/// into.
+	fn read_pooled(
+		&self,
+		socket: &mut Self::TcpSocket,
+	) -> nb::Result<Pooled<PoolBuffer<Self::Pool>>, Self::Error>;
+}
+
+/// A [`BufferPool`] of caller-provided slices, such as parts of a static
+/// array, holding up to `N` of them. The slices should all be the same size.
+///
+/// [`BufferPool`]: trait.BufferPool.html
+pub struct SlicePool<'a, N: ArrayLength<&'a mut [u8]>> {
+	free: RefCell<Vec<&'a mut [u8], N>>,
+}
+
+impl<'a, N: ArrayLength<&'a mut [u8]>> SlicePool<'a, N> {
+	/// Create an empty pool.
+	pub fn new() -> Self {
+		SlicePool {
+			free: RefCell::new(Vec::new()),
+		}
+	}
+
+	/// Add `buffer` to the pool, or hand it back if the pool is full.
+	pub fn add(&self, buffer: &'a mut [u8]) -> Result<(), &'a mut [u8]> {
+		self.free.borrow_mut().push(buffer)
+	}
+
+	/// How many buffers are free.
+	pub fn available(&self) -> usize {
+		self.free.borrow().len()
+	}
+}
+
+impl<'a, N: ArrayLength<&'a mut [u8]>> Default for SlicePool<'a, N> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<'a, N: ArrayLength<&'a mut [u8]>> BufferPool for SlicePool<'a, N> {
+	type Buffer = &'a mut [u8];
+
+	fn acquire(&self) -> Option<Self::Buffer> {
+		self.free.borrow_mut().pop()
+	}
+
+	fn release(&self, buffer: Self::Buffer) -> Result<(), Self::Buffer> {
+		self.free.borrow_mut().push(buffer)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use heapless::consts;
+
+	#[test]
+	fn buffers_are_acquired_and_released() {
+		let (mut a, mut b) = ([0; 4], [0; 4]);
+		let pool = SlicePool::<consts::U2>::new();
+		pool.add(&mut a).unwrap();
+		pool.add(&mut b).unwrap();
+		assert_eq!(pool.available(), 2);
+
+		let first = pool.acquire().unwrap();
+		let second = pool.acquire().unwrap();
+		assert!(pool.acquire().is_none());
+		first.copy_from_slice(b"ping");
+		let packet = Pooled::new(first, 2);
+		assert_eq!(packet.data(), b"pi");
+
+		pool.release(packet.buffer).unwrap();
+		pool.release(second).unwrap();
+		assert_eq!(pool.available(), 2);
+	}
+
+	#[test]
+	fn full_pools_hand_buffers_back() {
+		let (mut a, mut b, mut c) = ([0; 4], [1; 4], [2; 4]);
+		let pool = SlicePool::<consts::U1>::new();
+		pool.add(&mut a).unwrap();
+		assert_eq!(pool.add(&mut b), Err(&mut [1; 4][..]));
+		assert_eq!(pool.release(&mut c), Err(&mut [2; 4][..]));
+		assert_eq!(pool.available(), 1);
+		assert_eq!(pool.acquire(), Some(&mut [0; 4][..]));
+	}
+}
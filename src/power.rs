@@ -0,0 +1,80 @@
+use core::time::Duration;
+
+/// Cellular power saving mode (PSM) timers, as requested from the network.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PsmConfig {
+	/// How often the modem wakes up to tell the network it's still there
+	/// (periodic TAU, T3412)
+	pub periodic_update: Duration,
+	/// How long the modem stays reachable after each wake-up before sleeping
+	/// again (active time, T3324)
+	pub active_time: Duration,
+}
+
+/// Cellular extended discontinuous reception (eDRX) parameters, as requested
+/// from the network.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EdrxConfig {
+	/// How often the modem listens for paging
+	pub cycle: Duration,
+	/// How long the modem listens each cycle
+	pub paging_window: Duration,
+}
+
+/// Wi-Fi station power save parameters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WifiPowerSaveConfig {
+	/// How often, in beacon intervals, the station wakes up to receive the
+	/// access point's beacon
+	pub listen_interval: u16,
+	/// Whether to also wake up for DTIM beacons, so that broadcast and
+	/// multicast traffic is received
+	pub receive_dtim: bool,
+}
+
+/// The power saving parameters of a stack, see [`PowerSave`]. Every field
+/// left at `None` disables that mechanism, so the default configuration
+/// disables power saving.
+///
+/// [`PowerSave`]: trait.PowerSave.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PowerSaveConfig {
+	/// Cellular power saving mode
+	pub psm: Option<PsmConfig>,
+	/// Cellular extended discontinuous reception
+	pub edrx: Option<EdrxConfig>,
+	/// Wi-Fi station power save
+	pub wifi: Option<WifiPowerSaveConfig>,
+}
+
+/// What becomes of open sockets while the MCU sleeps, as returned by
+/// [`PowerSave::prepare_sleep`].
+///
+/// [`PowerSave::prepare_sleep`]: trait.PowerSave.html#tymethod.prepare_sleep
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SocketRetention {
+	/// Sockets survive, and can be used again after waking up
+	Retained,
+	/// Sockets are lost, and must be closed and opened again after waking up
+	Lost,
+}
+
+/// This trait is implemented by stacks whose radio can save power, and that
+/// need to know when the MCU goes to sleep. It makes explicit what happens to
+/// open sockets across sleep.
+pub trait PowerSave {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Apply `config`. Setting parameters for a mechanism that the stack's
+	/// radio doesn't have, such as PSM on Wi-Fi, is an error. The network may
+	/// grant other timers than the ones requested.
+	fn set_power_save(&self, config: &PowerSaveConfig) -> Result<(), Self::Error>;
+
+	/// Tell the stack that the MCU is about to sleep, and wait for it to be
+	/// ready. Reports whether open sockets will survive.
+	fn prepare_sleep(&self) -> nb::Result<SocketRetention, Self::Error>;
+
+	/// Tell the stack that the MCU woke up.
+	fn wake(&self) -> nb::Result<(), Self::Error>;
+}
@@ -0,0 +1,46 @@
+use crate::TcpStack;
+
+mod http;
+mod socks5;
+pub(crate) use http::read_status;
+pub use http::{HttpConnectProxy, HttpProxyError};
+pub use socks5::{Socks5Connector, Socks5Error};
+
+/// Why talking to a proxy over a TCP socket failed.
+pub(crate) enum IoError<E> {
+	Tcp(E),
+	/// The proxy closed the connection
+	Closed,
+}
+
+/// Write all of `buffer` to `socket`, waiting for the stack as long as needed.
+pub(crate) fn write_all<S: TcpStack>(
+	stack: &S,
+	socket: &mut S::TcpSocket,
+	mut buffer: &[u8],
+) -> Result<(), IoError<S::Error>> {
+	while !buffer.is_empty() {
+		let n = nb::block!(stack.write(socket, buffer)).map_err(IoError::Tcp)?;
+		if n == 0 {
+			return Err(IoError::Closed);
+		}
+		buffer = &buffer[n..];
+	}
+	Ok(())
+}
+
+/// Fill `buffer` from `socket`, waiting for the stack as long as needed.
+pub(crate) fn read_exact<S: TcpStack>(
+	stack: &S,
+	socket: &mut S::TcpSocket,
+	mut buffer: &mut [u8],
+) -> Result<(), IoError<S::Error>> {
+	while !buffer.is_empty() {
+		let n = nb::block!(stack.read(socket, buffer)).map_err(IoError::Tcp)?;
+		if n == 0 {
+			return Err(IoError::Closed);
+		}
+		buffer = &mut buffer[n..];
+	}
+	Ok(())
+}
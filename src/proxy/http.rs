@@ -0,0 +1,464 @@
+use super::{read_exact, write_all, IoError};
+use crate::{validate_hostname, Mode, NetworkErrorKind, RecvOutcome, TcpError, TcpStack};
+use core::fmt::Write;
+use heapless::{consts, String};
+use no_std_net::SocketAddr;
+
+/// The longest status line that is looked at; the rest of it is skipped.
+const MAX_STATUS_LINE: usize = 64;
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The error type of an [`HttpConnectProxy`].
+///
+/// [`HttpConnectProxy`]: struct.HttpConnectProxy.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum HttpProxyError<E> {
+	/// The underlying TCP stack failed
+	Tcp(E),
+	/// The proxy closed the connection before the tunnel was established
+	Closed,
+	/// The proxy sent something that is not a valid HTTP response
+	Protocol,
+	/// The proxy refused to open the tunnel, with the given HTTP status, such
+	/// as 407 when authentication is required
+	Status(u16),
+	/// The username and password together are longer than 255 bytes
+	TooLong,
+	/// The hostname to connect to is not a valid domain name, see
+	/// [`validate_hostname`](fn.validate_hostname.html)
+	InvalidHostname,
+}
+
+impl<E: TcpError> TcpError for HttpProxyError<E> {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			HttpProxyError::Tcp(e) => e.kind(),
+			HttpProxyError::Closed => NetworkErrorKind::PipeClosed,
+			HttpProxyError::Status(504) => NetworkErrorKind::Timeout,
+			HttpProxyError::Status(_) => NetworkErrorKind::ConnectionRefused,
+			HttpProxyError::TooLong | HttpProxyError::InvalidHostname => {
+				NetworkErrorKind::InvalidInput
+			}
+			HttpProxyError::Protocol => NetworkErrorKind::Other,
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for HttpProxyError<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			HttpProxyError::Tcp(e) => write!(f, "TCP error: {}", e),
+			HttpProxyError::Closed => f.write_str("the proxy closed the connection"),
+			HttpProxyError::Protocol => f.write_str("the proxy sent an invalid response"),
+			HttpProxyError::Status(status) => {
+				write!(f, "the proxy refused the tunnel, with status {}", status)
+			}
+			HttpProxyError::TooLong => f.write_str("the username and password are too long"),
+			HttpProxyError::InvalidHostname => {
+				f.write_str("the hostname is not a valid domain name")
+			}
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for HttpProxyError<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			HttpProxyError::Tcp(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl<E> From<IoError<E>> for HttpProxyError<E> {
+	fn from(e: IoError<E>) -> Self {
+		match e {
+			IoError::Tcp(e) => HttpProxyError::Tcp(e),
+			IoError::Closed => HttpProxyError::Closed,
+		}
+	}
+}
+
+/// Write the base64 encoding of `input` into `out`, returning its length.
+fn base64_encode(input: &[u8], out: &mut [u8]) -> usize {
+	let mut len = 0;
+	for chunk in input.chunks(3) {
+		let b = [
+			chunk[0],
+			chunk.get(1).cloned().unwrap_or(0),
+			chunk.get(2).cloned().unwrap_or(0),
+		];
+		let n = u32::from(b[0]) << 16 | u32::from(b[1]) << 8 | u32::from(b[2]);
+		for i in 0..4 {
+			out[len + i] = if i <= chunk.len() {
+				BASE64[(n >> (18 - 6 * i) & 0x3F) as usize]
+			} else {
+				b'='
+			};
+		}
+		len += 4;
+	}
+	len
+}
+
+/// A [`TcpStack`] wrapper that connects through an HTTP proxy, by asking it
+/// to open a tunnel with the `CONNECT` method ([`rfc7231`] section 4.3.6).
+/// Enterprise and OT networks often only let traffic out this way.
+///
+/// Sockets are opened on the wrapped stack, but connecting one connects it to
+/// the proxy and has the proxy open a tunnel to the requested address. Once
+/// connected, the socket carries the application's data, typically a TLS
+/// session, as if it were connected directly. The handshake waits for the
+/// wrapped stack as long as needed, whatever the [`Mode`] of the socket.
+///
+/// Basic authentication ([`rfc7617`]) is used when credentials are given.
+/// The credentials are sent in the clear, so only use them with proxies on a
+/// trusted network.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`Mode`]: enum.Mode.html
+/// [`rfc7231`]: https://tools.ietf.org/html/rfc7231
+/// [`rfc7617`]: https://tools.ietf.org/html/rfc7617
+pub struct HttpConnectProxy<'a, S> {
+	stack: S,
+	proxy: SocketAddr,
+	credentials: Option<(&'a str, &'a str)>,
+}
+
+impl<'a, S> HttpConnectProxy<'a, S>
+where
+	S: TcpStack,
+{
+	/// Connect through the proxy at `proxy`, without authentication.
+	pub fn new(stack: S, proxy: SocketAddr) -> Self {
+		HttpConnectProxy {
+			stack,
+			proxy,
+			credentials: None,
+		}
+	}
+
+	/// Connect through the proxy at `proxy`, authenticating with `username`
+	/// and `password`.
+	pub fn with_auth(stack: S, proxy: SocketAddr, username: &'a str, password: &'a str) -> Self {
+		HttpConnectProxy {
+			stack,
+			proxy,
+			credentials: Some((username, password)),
+		}
+	}
+
+	/// Get a reference to the wrapped stack.
+	pub fn inner(&self) -> &S {
+		&self.stack
+	}
+
+	/// Destroy the connector, returning the wrapped stack.
+	pub fn into_inner(self) -> S {
+		self.stack
+	}
+
+	/// Connect `socket` to port `port` of `hostname`, leaving name resolution
+	/// to the proxy. This keeps working where the device itself can't
+	/// resolve names.
+	///
+	/// `hostname` goes into the request as is, so it is checked with
+	/// [`validate_hostname`](fn.validate_hostname.html) first, and nothing
+	/// is sent if it isn't valid.
+	pub fn connect_host(
+		&self,
+		socket: S::TcpSocket,
+		hostname: &str,
+		port: u16,
+	) -> Result<S::TcpSocket, HttpProxyError<S::Error>> {
+		validate_hostname(hostname).map_err(|_| HttpProxyError::InvalidHostname)?;
+		let mut authority: String<consts::U256> = String::new();
+		write!(authority, "{}:{}", hostname, port).map_err(|_| HttpProxyError::TooLong)?;
+		self.connect_to(socket, &authority)
+	}
+
+	fn connect_to(
+		&self,
+		socket: S::TcpSocket,
+		authority: &str,
+	) -> Result<S::TcpSocket, HttpProxyError<S::Error>> {
+		let mut socket = self
+			.stack
+			.connect(socket, self.proxy)
+			.map_err(HttpProxyError::Tcp)?;
+		match self.handshake(&mut socket, authority) {
+			Ok(()) => Ok(socket),
+			Err(e) => {
+				self.stack.close(socket).ok();
+				Err(e)
+			}
+		}
+	}
+
+	fn handshake(
+		&self,
+		socket: &mut S::TcpSocket,
+		authority: &str,
+	) -> Result<(), HttpProxyError<S::Error>> {
+		let stack = &self.stack;
+		for part in &[
+			"CONNECT ",
+			authority,
+			" HTTP/1.1\r\nHost: ",
+			authority,
+			"\r\n",
+		] {
+			write_all(stack, socket, part.as_bytes())?;
+		}
+		if let Some((username, password)) = self.credentials {
+			let len = username.len() + 1 + password.len();
+			if len > 255 {
+				return Err(HttpProxyError::TooLong);
+			}
+			let mut userpass = [0; 255];
+			userpass[..username.len()].copy_from_slice(username.as_bytes());
+			userpass[username.len()] = b':';
+			userpass[username.len() + 1..len].copy_from_slice(password.as_bytes());
+			let mut encoded = [0; 340];
+			let encoded_len = base64_encode(&userpass[..len], &mut encoded);
+			write_all(stack, socket, b"Proxy-Authorization: Basic ")?;
+			write_all(stack, socket, &encoded[..encoded_len])?;
+			write_all(stack, socket, b"\r\n")?;
+		}
+		write_all(stack, socket, b"\r\n")?;
+
+		match read_status(stack, socket)? {
+			Some(200..=299) => Ok(()),
+			Some(status) => Err(HttpProxyError::Status(status)),
+			None => Err(HttpProxyError::Protocol),
+		}
+	}
+}
+
+/// Read the head of an HTTP response from `socket`, returning its status
+/// code, or `None` if it isn't a valid HTTP/1.x response.
+///
+/// The response is read a byte at a time, so that nothing past its head,
+/// such as tunnelled data, is consumed.
+pub(crate) fn read_status<S: TcpStack>(
+	stack: &S,
+	socket: &mut S::TcpSocket,
+) -> Result<Option<u16>, IoError<S::Error>> {
+	let mut status_line = [0; MAX_STATUS_LINE];
+	let mut status_len = 0;
+	let mut in_status_line = true;
+	let mut tail = [0; 4];
+	while &tail != b"\r\n\r\n" {
+		let mut byte = [0];
+		read_exact(stack, socket, &mut byte)?;
+		tail = [tail[1], tail[2], tail[3], byte[0]];
+		if in_status_line {
+			if byte[0] == b'\n' {
+				in_status_line = false;
+			} else if status_len < MAX_STATUS_LINE {
+				status_line[status_len] = byte[0];
+				status_len += 1;
+			}
+		}
+	}
+
+	// "HTTP/1.1 200 Connection established", where the reason phrase may be
+	// empty.
+	let status_line = &status_line[..status_len];
+	if !status_line.starts_with(b"HTTP/1.") || status_line.len() < 12 || status_line[8] != b' ' {
+		return Ok(None);
+	}
+	match status_line.get(12) {
+		None | Some(b' ') | Some(b'\r') => {}
+		Some(_) => return Ok(None),
+	}
+	let mut status = 0;
+	for &digit in &status_line[9..12] {
+		if !digit.is_ascii_digit() {
+			return Ok(None);
+		}
+		status = status * 10 + u16::from(digit - b'0');
+	}
+	Ok(Some(status))
+}
+
+impl<'a, S> TcpStack for HttpConnectProxy<'a, S>
+where
+	S: TcpStack,
+{
+	type TcpSocket = S::TcpSocket;
+	type Error = HttpProxyError<S::Error>;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		self.stack.open(mode).map_err(HttpProxyError::Tcp)
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		let mut authority: String<consts::U64> = String::new();
+		// The longest address, `[ipv6%scope]:port`, fits.
+		write!(authority, "{}", remote).ok();
+		self.connect_to(socket, &authority)
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.stack.is_connected(socket).map_err(HttpProxyError::Tcp)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		self.stack
+			.write(socket, buffer)
+			.map_err(|e| e.map(HttpProxyError::Tcp))
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		self.stack
+			.read(socket, buffer)
+			.map_err(|e| e.map(HttpProxyError::Tcp))
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		self.stack
+			.receive(socket, buffer)
+			.map_err(|e| e.map(HttpProxyError::Tcp))
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		self.stack.close(socket).map_err(HttpProxyError::Tcp)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Call, Expectation, MockStack};
+	use no_std_net::IpAddr;
+
+	fn proxy() -> SocketAddr {
+		SocketAddr::new(IpAddr::from([192, 168, 1, 1]), 3128)
+	}
+
+	fn tunnel(request: &'static [u8], response: &'static [u8]) -> [Expectation<'static>; 4] {
+		[
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(proxy())),
+			Expectation::new(Call::TcpWrite(request)),
+			Expectation::new(Call::TcpRead(response)),
+		]
+	}
+
+	#[test]
+	fn base64() {
+		let mut out = [0; 8];
+		for &(input, encoded) in &[
+			(&b""[..], &b""[..]),
+			(b"f", b"Zg=="),
+			(b"fo", b"Zm8="),
+			(b"foo", b"Zm9v"),
+			(b"me:pwd", b"bWU6cHdk"),
+		] {
+			let len = base64_encode(input, &mut out);
+			assert_eq!(&out[..len], encoded);
+		}
+	}
+
+	#[test]
+	fn connect_leaves_tunnelled_data_unread() {
+		let mut script = [Expectation::new(Call::TcpClose); 5];
+		script[..4].copy_from_slice(&tunnel(
+			b"CONNECT 10.0.0.1:443 HTTP/1.1\r\nHost: 10.0.0.1:443\r\n\r\n",
+			b"HTTP/1.1 200 Connection established\r\nVia: proxy\r\n\r\n",
+		));
+		script[4] = Expectation::new(Call::TcpRead(b"data"));
+		let proxy = HttpConnectProxy::new(MockStack::new(&script), proxy());
+		let socket = proxy.open(Mode::Blocking).unwrap();
+		let mut socket = proxy
+			.connect(socket, SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 443))
+			.unwrap();
+		let mut buffer = [0; 8];
+		assert_eq!(proxy.read(&mut socket, &mut buffer), Ok(4));
+		assert_eq!(&buffer[..4], b"data");
+	}
+
+	#[test]
+	fn connect_host_with_auth() {
+		let script = tunnel(
+			b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\
+			  Proxy-Authorization: Basic bWU6cHdk\r\n\r\n",
+			b"HTTP/1.0 200\r\n\r\n",
+		);
+		let proxy = HttpConnectProxy::with_auth(MockStack::new(&script), proxy(), "me", "pwd");
+		let socket = proxy.open(Mode::Blocking).unwrap();
+		proxy.connect_host(socket, "example.com", 443).unwrap();
+	}
+
+	#[test]
+	fn connect_host_rejects_header_injection() {
+		let script = [Expectation::new(Call::TcpOpen)];
+		let proxy = HttpConnectProxy::new(MockStack::new(&script), proxy());
+		let socket = proxy.open(Mode::Blocking).unwrap();
+		let error = proxy
+			.connect_host(socket, "example.com\r\nX-Evil: 1", 443)
+			.unwrap_err();
+		assert_eq!(error, HttpProxyError::InvalidHostname);
+		assert_eq!(error.kind(), NetworkErrorKind::InvalidInput);
+	}
+
+	#[test]
+	fn refused_and_invalid_responses() {
+		let request = b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n";
+		let cases: [(&[u8], HttpProxyError<_>); 6] = [
+			(
+				b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n",
+				HttpProxyError::Status(407),
+			),
+			(
+				b"HTTP/1.1 504 Gateway Timeout\r\n\r\n",
+				HttpProxyError::Status(504),
+			),
+			(b"HTTP/1.1X200 OK\r\n\r\n", HttpProxyError::Protocol),
+			(b"HTTP/1.1 2000 OK\r\n\r\n", HttpProxyError::Protocol),
+			(b"HTTP/1.1 2x0 OK\r\n\r\n", HttpProxyError::Protocol),
+			(b"SSH-2.0-OpenSSH\r\n\r\n", HttpProxyError::Protocol),
+		];
+		for (response, expected) in cases.iter() {
+			let mut script = [Expectation::new(Call::TcpClose); 5];
+			script[..3].copy_from_slice(&tunnel(request, b"")[..3]);
+			script[3] = Expectation::new(Call::TcpRead(response));
+			let proxy = HttpConnectProxy::new(MockStack::new(&script), proxy());
+			let socket = proxy.open(Mode::Blocking).unwrap();
+			assert_eq!(
+				proxy.connect_host(socket, "example.com", 443).unwrap_err(),
+				*expected
+			);
+		}
+	}
+
+	#[test]
+	fn proxy_closing_before_the_end_of_the_head() {
+		let mut script = [Expectation::new(Call::TcpClose); 6];
+		script[..4].copy_from_slice(&tunnel(
+			b"CONNECT example.com:443 HTTP/1.1\r\nHost: example.com:443\r\n\r\n",
+			b"HTTP/1.1 200 OK\r\n",
+		));
+		script[4] = Expectation::new(Call::TcpRead(b""));
+		let proxy = HttpConnectProxy::new(MockStack::new(&script), proxy());
+		let socket = proxy.open(Mode::Blocking).unwrap();
+		assert_eq!(
+			proxy.connect_host(socket, "example.com", 443).unwrap_err(),
+			HttpProxyError::Closed
+		);
+	}
+}
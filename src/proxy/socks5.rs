@@ -0,0 +1,514 @@
+use super::{read_exact, write_all, IoError};
+use crate::{Mode, NetworkErrorKind, RecvOutcome, TcpError, TcpStack};
+use no_std_net::{IpAddr, SocketAddr};
+
+const VERSION: u8 = 5;
+const METHOD_NONE: u8 = 0x00;
+const METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const METHOD_NO_ACCEPTABLE: u8 = 0xFF;
+const COMMAND_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+
+/// The error type of a [`Socks5Connector`].
+///
+/// [`Socks5Connector`]: struct.Socks5Connector.html
+#[derive(Clone, Debug, PartialEq)]
+pub enum Socks5Error<E> {
+	/// The underlying TCP stack failed
+	Tcp(E),
+	/// The proxy closed the connection during the handshake
+	Closed,
+	/// The proxy sent something that is not a valid SOCKS5 reply
+	Protocol,
+	/// The proxy accepts none of the authentication methods offered
+	NoAcceptableAuth,
+	/// The proxy rejected the username and password
+	AuthRejected,
+	/// The proxy could not connect to the destination, with the given reply
+	/// code, such as 5 for connection refused
+	ConnectFailed(u8),
+	/// The username, password or hostname is longer than 255 bytes
+	TooLong,
+}
+
+impl<E: TcpError> TcpError for Socks5Error<E> {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			Socks5Error::Tcp(e) => e.kind(),
+			Socks5Error::Closed => NetworkErrorKind::PipeClosed,
+			Socks5Error::ConnectFailed(3) | Socks5Error::ConnectFailed(4) => {
+				NetworkErrorKind::Unreachable
+			}
+			Socks5Error::ConnectFailed(5) => NetworkErrorKind::ConnectionRefused,
+			Socks5Error::ConnectFailed(6) => NetworkErrorKind::Timeout,
+			Socks5Error::TooLong => NetworkErrorKind::InvalidInput,
+			_ => NetworkErrorKind::Other,
+		}
+	}
+}
+
+impl<E: core::fmt::Display> core::fmt::Display for Socks5Error<E> {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			Socks5Error::Tcp(e) => write!(f, "TCP error: {}", e),
+			Socks5Error::Closed => f.write_str("the proxy closed the connection"),
+			Socks5Error::Protocol => f.write_str("the proxy sent an invalid reply"),
+			Socks5Error::NoAcceptableAuth => {
+				f.write_str("the proxy accepts none of the authentication methods offered")
+			}
+			Socks5Error::AuthRejected => {
+				f.write_str("the proxy rejected the username and password")
+			}
+			Socks5Error::ConnectFailed(code) => {
+				write!(f, "the proxy could not connect, with reply code {}", code)
+			}
+			Socks5Error::TooLong => f.write_str("the username, password or hostname is too long"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl<E: std::error::Error + 'static> std::error::Error for Socks5Error<E> {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Socks5Error::Tcp(e) => Some(e),
+			_ => None,
+		}
+	}
+}
+
+impl<E> From<IoError<E>> for Socks5Error<E> {
+	fn from(e: IoError<E>) -> Self {
+		match e {
+			IoError::Tcp(e) => Socks5Error::Tcp(e),
+			IoError::Closed => Socks5Error::Closed,
+		}
+	}
+}
+
+/// The destination of a SOCKS5 connect request.
+enum Destination<'a> {
+	Addr(IpAddr),
+	Name(&'a str),
+}
+
+/// A [`TcpStack`] wrapper that connects through a SOCKS5 proxy ([`rfc1928`]),
+/// for devices deployed behind mandatory proxies.
+///
+/// Sockets are opened on the wrapped stack, but connecting one connects it to
+/// the proxy and asks the proxy to connect it on to the requested address.
+/// Once connected, the socket carries the application's data as if it were
+/// connected directly. The handshake waits for the wrapped stack as long as
+/// needed, whatever the [`Mode`] of the socket.
+///
+/// Username and password authentication ([`rfc1929`]) is used when
+/// credentials are given.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`Mode`]: enum.Mode.html
+/// [`rfc1928`]: https://tools.ietf.org/html/rfc1928
+/// [`rfc1929`]: https://tools.ietf.org/html/rfc1929
+pub struct Socks5Connector<'a, S> {
+	stack: S,
+	proxy: SocketAddr,
+	credentials: Option<(&'a str, &'a str)>,
+}
+
+impl<'a, S> Socks5Connector<'a, S>
+where
+	S: TcpStack,
+{
+	/// Connect through the proxy at `proxy`, without authentication.
+	pub fn new(stack: S, proxy: SocketAddr) -> Self {
+		Socks5Connector {
+			stack,
+			proxy,
+			credentials: None,
+		}
+	}
+
+	/// Connect through the proxy at `proxy`, authenticating with `username`
+	/// and `password`.
+	pub fn with_auth(stack: S, proxy: SocketAddr, username: &'a str, password: &'a str) -> Self {
+		Socks5Connector {
+			stack,
+			proxy,
+			credentials: Some((username, password)),
+		}
+	}
+
+	/// Get a reference to the wrapped stack.
+	pub fn inner(&self) -> &S {
+		&self.stack
+	}
+
+	/// Destroy the connector, returning the wrapped stack.
+	pub fn into_inner(self) -> S {
+		self.stack
+	}
+
+	/// Connect `socket` to port `port` of `hostname`, leaving name resolution
+	/// to the proxy. This keeps working where the device itself can't
+	/// resolve names.
+	pub fn connect_host(
+		&self,
+		socket: S::TcpSocket,
+		hostname: &str,
+		port: u16,
+	) -> Result<S::TcpSocket, Socks5Error<S::Error>> {
+		self.connect_to(socket, Destination::Name(hostname), port)
+	}
+
+	fn connect_to(
+		&self,
+		socket: S::TcpSocket,
+		destination: Destination,
+		port: u16,
+	) -> Result<S::TcpSocket, Socks5Error<S::Error>> {
+		let mut socket = self
+			.stack
+			.connect(socket, self.proxy)
+			.map_err(Socks5Error::Tcp)?;
+		match self.handshake(&mut socket, destination, port) {
+			Ok(()) => Ok(socket),
+			Err(e) => {
+				self.stack.close(socket).ok();
+				Err(e)
+			}
+		}
+	}
+
+	fn handshake(
+		&self,
+		socket: &mut S::TcpSocket,
+		destination: Destination,
+		port: u16,
+	) -> Result<(), Socks5Error<S::Error>> {
+		let stack = &self.stack;
+
+		// Method selection
+		let offer: &[u8] = match self.credentials {
+			Some(_) => &[VERSION, 2, METHOD_NONE, METHOD_USERNAME_PASSWORD],
+			None => &[VERSION, 1, METHOD_NONE],
+		};
+		write_all(stack, socket, offer)?;
+		let mut reply = [0; 2];
+		read_exact(stack, socket, &mut reply)?;
+		match (reply[0], reply[1], self.credentials) {
+			(VERSION, METHOD_NONE, _) => {}
+			(VERSION, METHOD_USERNAME_PASSWORD, Some((username, password))) => {
+				self.authenticate(socket, username, password)?
+			}
+			(VERSION, METHOD_NO_ACCEPTABLE, _) => return Err(Socks5Error::NoAcceptableAuth),
+			_ => return Err(Socks5Error::Protocol),
+		}
+
+		// Connect request: version, command, reserved, address, port
+		let mut request = [0; 4 + 1 + 255 + 2];
+		request[..3].copy_from_slice(&[VERSION, COMMAND_CONNECT, 0]);
+		let mut len = 3;
+		match destination {
+			Destination::Addr(IpAddr::V4(addr)) => {
+				request[len] = ATYP_IPV4;
+				request[len + 1..len + 5].copy_from_slice(&addr.octets());
+				len += 5;
+			}
+			Destination::Addr(IpAddr::V6(addr)) => {
+				request[len] = ATYP_IPV6;
+				request[len + 1..len + 17].copy_from_slice(&addr.octets());
+				len += 17;
+			}
+			Destination::Name(name) => {
+				if name.len() > 255 {
+					return Err(Socks5Error::TooLong);
+				}
+				request[len] = ATYP_DOMAIN;
+				request[len + 1] = name.len() as u8;
+				request[len + 2..len + 2 + name.len()].copy_from_slice(name.as_bytes());
+				len += 2 + name.len();
+			}
+		}
+		request[len..len + 2].copy_from_slice(&port.to_be_bytes());
+		len += 2;
+		write_all(stack, socket, &request[..len])?;
+
+		// Reply: version, reply code, reserved, then the bound address, which
+		// is read and discarded.
+		let mut reply = [0; 4];
+		read_exact(stack, socket, &mut reply)?;
+		if reply[0] != VERSION {
+			return Err(Socks5Error::Protocol);
+		}
+		if reply[1] != 0 {
+			return Err(Socks5Error::ConnectFailed(reply[1]));
+		}
+		let mut bound = [0; 255 + 2];
+		let bound_len = match reply[3] {
+			ATYP_IPV4 => 4 + 2,
+			ATYP_IPV6 => 16 + 2,
+			ATYP_DOMAIN => {
+				let mut name_len = [0];
+				read_exact(stack, socket, &mut name_len)?;
+				usize::from(name_len[0]) + 2
+			}
+			_ => return Err(Socks5Error::Protocol),
+		};
+		read_exact(stack, socket, &mut bound[..bound_len])?;
+		Ok(())
+	}
+
+	fn authenticate(
+		&self,
+		socket: &mut S::TcpSocket,
+		username: &str,
+		password: &str,
+	) -> Result<(), Socks5Error<S::Error>> {
+		if username.len() > 255 || password.len() > 255 {
+			return Err(Socks5Error::TooLong);
+		}
+		let mut request = [0; 3 + 255 + 255];
+		request[0] = 1;
+		request[1] = username.len() as u8;
+		let mut len = 2;
+		request[len..len + username.len()].copy_from_slice(username.as_bytes());
+		len += username.len();
+		request[len] = password.len() as u8;
+		len += 1;
+		request[len..len + password.len()].copy_from_slice(password.as_bytes());
+		len += password.len();
+		write_all(&self.stack, socket, &request[..len])?;
+
+		let mut reply = [0; 2];
+		read_exact(&self.stack, socket, &mut reply)?;
+		match reply {
+			[1, 0] => Ok(()),
+			[1, _] => Err(Socks5Error::AuthRejected),
+			_ => Err(Socks5Error::Protocol),
+		}
+	}
+}
+
+impl<'a, S> TcpStack for Socks5Connector<'a, S>
+where
+	S: TcpStack,
+{
+	type TcpSocket = S::TcpSocket;
+	type Error = Socks5Error<S::Error>;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		self.stack.open(mode).map_err(Socks5Error::Tcp)
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		self.connect_to(socket, Destination::Addr(remote.ip()), remote.port())
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.stack.is_connected(socket).map_err(Socks5Error::Tcp)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		self.stack
+			.write(socket, buffer)
+			.map_err(|e| e.map(Socks5Error::Tcp))
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		self.stack
+			.read(socket, buffer)
+			.map_err(|e| e.map(Socks5Error::Tcp))
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		self.stack
+			.receive(socket, buffer)
+			.map_err(|e| e.map(Socks5Error::Tcp))
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		self.stack.close(socket).map_err(Socks5Error::Tcp)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Call, Expectation, MockStack};
+
+	fn proxy() -> SocketAddr {
+		SocketAddr::new(IpAddr::from([192, 168, 1, 1]), 1080)
+	}
+
+	fn remote() -> SocketAddr {
+		SocketAddr::new(IpAddr::from([10, 0, 0, 1]), 80)
+	}
+
+	#[test]
+	fn connect_without_auth() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(proxy())),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0])),
+			Expectation::new(Call::TcpRead(&[5, 0])),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0, 1, 10, 0, 0, 1, 0, 80])),
+			Expectation::new(Call::TcpRead(&[5, 0, 0, 1, 10, 0, 0, 2, 0x9C, 0x40])),
+		];
+		let connector = Socks5Connector::new(MockStack::new(&script), proxy());
+		let socket = connector.open(Mode::Blocking).unwrap();
+		let socket = connector.connect(socket, remote()).unwrap();
+		assert!(connector.is_connected(&socket).unwrap());
+	}
+
+	#[test]
+	fn connect_host_with_auth() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(proxy())),
+			Expectation::new(Call::TcpWrite(&[5, 2, 0, 2])),
+			Expectation::new(Call::TcpRead(&[5, 2])),
+			Expectation::new(Call::TcpWrite(&[1, 2, b'm', b'e', 3, b'p', b'w', b'd'])),
+			Expectation::new(Call::TcpRead(&[1, 0])),
+			Expectation::new(Call::TcpWrite(&[
+				5, 1, 0, 3, 11, b'e', b'x', b'a', b'm', b'p', b'l', b'e', b'.', b'c', b'o', b'm',
+				0x01, 0xBB,
+			])),
+			// The bound address is a name, delivered in small pieces.
+			Expectation::new(Call::TcpRead(&[5, 0, 0])),
+			Expectation::new(Call::TcpRead(&[3, 3])),
+			Expectation::new(Call::TcpRead(&[b'p', b'x', b'y', 0, 0])),
+		];
+		let connector = Socks5Connector::with_auth(MockStack::new(&script), proxy(), "me", "pwd");
+		let socket = connector.open(Mode::Blocking).unwrap();
+		connector.connect_host(socket, "example.com", 443).unwrap();
+	}
+
+	#[test]
+	fn connect_failure_closes_the_socket() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(proxy())),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0])),
+			Expectation::new(Call::TcpRead(&[5, 0])),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0, 1, 10, 0, 0, 1, 0, 80])),
+			Expectation::new(Call::TcpRead(&[5, 5, 0, 1])),
+			Expectation::new(Call::TcpClose),
+		];
+		let connector = Socks5Connector::new(MockStack::new(&script), proxy());
+		let socket = connector.open(Mode::Blocking).unwrap();
+		let error = connector.connect(socket, remote()).unwrap_err();
+		assert_eq!(error, Socks5Error::ConnectFailed(5));
+		assert_eq!(error.kind(), NetworkErrorKind::ConnectionRefused);
+	}
+
+	#[test]
+	fn method_selection_errors() {
+		let cases: [(&[u8], Socks5Error<_>); 3] = [
+			(&[5, 0xFF], Socks5Error::NoAcceptableAuth),
+			// Username and password authentication wasn't offered.
+			(&[5, 2], Socks5Error::Protocol),
+			(&[4, 0], Socks5Error::Protocol),
+		];
+		for (reply, expected) in cases.iter() {
+			let script = [
+				Expectation::new(Call::TcpOpen),
+				Expectation::new(Call::TcpConnect(proxy())),
+				Expectation::new(Call::TcpWrite(&[5, 1, 0])),
+				Expectation::new(Call::TcpRead(reply)),
+				Expectation::new(Call::TcpClose),
+			];
+			let connector = Socks5Connector::new(MockStack::new(&script), proxy());
+			let socket = connector.open(Mode::Blocking).unwrap();
+			assert_eq!(connector.connect(socket, remote()).unwrap_err(), *expected);
+		}
+	}
+
+	#[test]
+	fn auth_rejected() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(proxy())),
+			Expectation::new(Call::TcpWrite(&[5, 2, 0, 2])),
+			Expectation::new(Call::TcpRead(&[5, 2])),
+			Expectation::new(Call::TcpWrite(&[1, 2, b'm', b'e', 1, b'x'])),
+			Expectation::new(Call::TcpRead(&[1, 1])),
+			Expectation::new(Call::TcpClose),
+		];
+		let connector = Socks5Connector::with_auth(MockStack::new(&script), proxy(), "me", "x");
+		let socket = connector.open(Mode::Blocking).unwrap();
+		assert_eq!(
+			connector.connect(socket, remote()).unwrap_err(),
+			Socks5Error::AuthRejected
+		);
+	}
+
+	#[test]
+	fn proxy_closing_mid_reply() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(proxy())),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0])),
+			Expectation::new(Call::TcpRead(&[5, 0])),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0, 1, 10, 0, 0, 1, 0, 80])),
+			Expectation::new(Call::TcpRead(&[5, 0, 0, 1, 10, 0])),
+			Expectation::new(Call::TcpRead(&[])),
+			Expectation::new(Call::TcpClose),
+		];
+		let connector = Socks5Connector::new(MockStack::new(&script), proxy());
+		let socket = connector.open(Mode::Blocking).unwrap();
+		assert_eq!(
+			connector.connect(socket, remote()).unwrap_err(),
+			Socks5Error::Closed
+		);
+	}
+
+	#[test]
+	fn bad_bound_address_type() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(proxy())),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0])),
+			Expectation::new(Call::TcpRead(&[5, 0])),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0, 1, 10, 0, 0, 1, 0, 80])),
+			Expectation::new(Call::TcpRead(&[5, 0, 0, 7])),
+			Expectation::new(Call::TcpClose),
+		];
+		let connector = Socks5Connector::new(MockStack::new(&script), proxy());
+		let socket = connector.open(Mode::Blocking).unwrap();
+		assert_eq!(
+			connector.connect(socket, remote()).unwrap_err(),
+			Socks5Error::Protocol
+		);
+	}
+
+	#[test]
+	fn hostname_too_long() {
+		let name = [b'a'; 256];
+		let name = core::str::from_utf8(&name).unwrap();
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(proxy())),
+			Expectation::new(Call::TcpWrite(&[5, 1, 0])),
+			Expectation::new(Call::TcpRead(&[5, 0])),
+			Expectation::new(Call::TcpClose),
+		];
+		let connector = Socks5Connector::new(MockStack::new(&script), proxy());
+		let socket = connector.open(Mode::Blocking).unwrap();
+		let error = connector.connect_host(socket, name, 80).unwrap_err();
+		assert_eq!(error, Socks5Error::TooLong);
+		assert_eq!(error.kind(), NetworkErrorKind::InvalidInput);
+	}
+}
@@ -0,0 +1,799 @@
+//! Recording socket operations of a stack, and replaying them.
+//!
+//! The log is a sequence of entries, each made of an operation code, a socket
+//! id, a big-endian `u16` payload length and the payload, which is the data
+//! written or read, or the encoded remote address for `connect` and UDP
+//! `open`. Failed calls are recorded with the `FAILED` bit set and the kind
+//! of their error as payload: one byte, followed by one more for DNS kinds.
+//! Calls that returned `WouldBlock` are not recorded.
+
+use crate::{
+	DatagramBuf, DnsErrorKind, Mode, NetworkErrorKind, RecvOutcome, TcpError, TcpStack, UdpError,
+	UdpStack,
+};
+use core::cell::{Cell, RefCell};
+use no_std_net::{IpAddr, SocketAddr};
+
+const TCP_OPEN: u8 = 1;
+const TCP_CONNECT: u8 = 2;
+const TCP_WRITE: u8 = 3;
+const TCP_READ: u8 = 4;
+const TCP_CLOSE: u8 = 5;
+const UDP_OPEN: u8 = 6;
+const UDP_WRITE: u8 = 7;
+const UDP_READ: u8 = 8;
+const UDP_CLOSE: u8 = 9;
+/// Set in the operation code of calls that failed.
+const FAILED: u8 = 0x80;
+const ENTRY_HEADER: usize = 4;
+/// An address as encoded in the log: family, address and port.
+const MAX_ADDR: usize = 1 + 16 + 2;
+
+/// The error kinds as they are recorded, in order of their code, starting
+/// at 1. `Dns` kinds are recorded with the code of the DNS kind after it.
+const KINDS: [NetworkErrorKind; 12] = [
+	NetworkErrorKind::WouldBlock,
+	NetworkErrorKind::Timeout,
+	NetworkErrorKind::ConnectionRefused,
+	NetworkErrorKind::ConnectionReset,
+	NetworkErrorKind::PipeClosed,
+	NetworkErrorKind::NotConnected,
+	NetworkErrorKind::AddrInUse,
+	NetworkErrorKind::Unreachable,
+	NetworkErrorKind::Exhausted,
+	NetworkErrorKind::InvalidInput,
+	NetworkErrorKind::Dns(DnsErrorKind::Other),
+	NetworkErrorKind::Other,
+];
+const DNS_KINDS: [DnsErrorKind; 7] = [
+	DnsErrorKind::NxDomain,
+	DnsErrorKind::Timeout,
+	DnsErrorKind::ServerFailure,
+	DnsErrorKind::NameTooLong,
+	DnsErrorKind::InvalidName,
+	DnsErrorKind::NoRecordOfType,
+	DnsErrorKind::Other,
+];
+
+fn code<T: PartialEq>(table: &[T], value: &T) -> u8 {
+	// Every value is in its table.
+	table.iter().position(|v| v == value).unwrap_or(0) as u8 + 1
+}
+
+fn encode_kind(kind: NetworkErrorKind, out: &mut [u8; 2]) -> usize {
+	match kind {
+		NetworkErrorKind::Dns(dns) => {
+			out[0] = code(&KINDS, &NetworkErrorKind::Dns(DnsErrorKind::Other));
+			out[1] = code(&DNS_KINDS, &dns);
+			2
+		}
+		kind => {
+			out[0] = code(&KINDS, &kind);
+			1
+		}
+	}
+}
+
+fn decode_kind(payload: &[u8]) -> Option<NetworkErrorKind> {
+	let kind = *KINDS.get(usize::from(*payload.first()?).wrapping_sub(1))?;
+	match (kind, payload.len()) {
+		(NetworkErrorKind::Dns(_), 2) => DNS_KINDS
+			.get(usize::from(payload[1]).wrapping_sub(1))
+			.map(|dns| NetworkErrorKind::Dns(*dns)),
+		(NetworkErrorKind::Dns(_), _) | (_, 2) => None,
+		(kind, _) => Some(kind),
+	}
+}
+
+fn encode_addr(addr: SocketAddr, out: &mut [u8; MAX_ADDR]) -> usize {
+	let len = match addr.ip() {
+		IpAddr::V4(ip) => {
+			out[0] = 4;
+			out[1..5].copy_from_slice(&ip.octets());
+			5
+		}
+		IpAddr::V6(ip) => {
+			out[0] = 6;
+			out[1..17].copy_from_slice(&ip.octets());
+			17
+		}
+	};
+	out[len..len + 2].copy_from_slice(&addr.port().to_be_bytes());
+	len + 2
+}
+
+/// A socket of a [`Recorder`], which is the wrapped stack's socket tagged
+/// with the id it has in the log.
+///
+/// [`Recorder`]: struct.Recorder.html
+#[derive(Debug)]
+pub struct RecordedSocket<T> {
+	id: u8,
+	socket: T,
+}
+
+struct Log<'a> {
+	buffer: &'a mut [u8],
+	len: usize,
+	truncated: bool,
+}
+
+/// A wrapper around a [`TcpStack`] and [`UdpStack`] that records every socket
+/// operation and its payload into a caller-provided buffer. The log can then
+/// be served back by a [`Replay`], to run regression tests of protocol
+/// clients without network access.
+///
+/// Recording stops when the buffer is full, which [`is_truncated`] reports.
+/// Sockets are numbered in the order they are opened, wrapping around after
+/// 256.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`Replay`]: struct.Replay.html
+/// [`is_truncated`]: #method.is_truncated
+pub struct Recorder<'a, S> {
+	stack: S,
+	log: RefCell<Log<'a>>,
+	next_id: Cell<u8>,
+}
+
+impl<'a, S> Recorder<'a, S> {
+	/// Wrap `stack`, recording into `buffer`.
+	pub fn new(stack: S, buffer: &'a mut [u8]) -> Self {
+		Recorder {
+			stack,
+			log: RefCell::new(Log {
+				buffer,
+				len: 0,
+				truncated: false,
+			}),
+			next_id: Cell::new(0),
+		}
+	}
+
+	/// How many bytes of the buffer have been recorded.
+	pub fn len(&self) -> usize {
+		self.log.borrow().len
+	}
+
+	/// Whether nothing has been recorded yet.
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	/// Whether some operations could not be recorded because the buffer was
+	/// full.
+	pub fn is_truncated(&self) -> bool {
+		self.log.borrow().truncated
+	}
+
+	/// Destroy the recorder, returning the wrapped stack and the recorded
+	/// part of the buffer.
+	pub fn finish(self) -> (S, &'a mut [u8]) {
+		let log = self.log.into_inner();
+		(self.stack, &mut log.buffer[..log.len])
+	}
+
+	fn record(&self, op: u8, id: u8, payload: &[u8]) {
+		let mut log = self.log.borrow_mut();
+		let start = log.len;
+		let end = start + ENTRY_HEADER + payload.len();
+		if log.truncated || end > log.buffer.len() || payload.len() > usize::from(core::u16::MAX) {
+			log.truncated = true;
+			return;
+		}
+		log.buffer[start] = op;
+		log.buffer[start + 1] = id;
+		log.buffer[start + 2..start + 4].copy_from_slice(&(payload.len() as u16).to_be_bytes());
+		log.buffer[start + ENTRY_HEADER..end].copy_from_slice(payload);
+		log.len = end;
+	}
+
+	/// Record a call that failed with an error of the given kind.
+	fn record_failure(&self, op: u8, id: u8, kind: NetworkErrorKind) {
+		let mut payload = [0; 2];
+		let len = encode_kind(kind, &mut payload);
+		self.record(op | FAILED, id, &payload[..len]);
+	}
+
+	/// Record the outcome of a call; `payload` is only recorded on success.
+	fn record_result<T, E>(
+		&self,
+		op: u8,
+		id: u8,
+		result: &nb::Result<T, E>,
+		kind: fn(&E) -> NetworkErrorKind,
+		payload: &[u8],
+	) {
+		match result {
+			Ok(_) => self.record(op, id, payload),
+			Err(nb::Error::Other(e)) => self.record_failure(op, id, kind(e)),
+			Err(nb::Error::WouldBlock) => {}
+		}
+	}
+
+	fn new_id(&self) -> u8 {
+		let id = self.next_id.get();
+		self.next_id.set(id.wrapping_add(1));
+		id
+	}
+}
+
+impl<'a, S: TcpStack> TcpStack for Recorder<'a, S> {
+	type TcpSocket = RecordedSocket<S::TcpSocket>;
+	type Error = S::Error;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		let id = self.new_id();
+		match self.stack.open(mode) {
+			Ok(socket) => {
+				self.record(TCP_OPEN, id, &[]);
+				Ok(RecordedSocket { id, socket })
+			}
+			Err(e) => {
+				self.record_failure(TCP_OPEN, id, e.kind());
+				Err(e)
+			}
+		}
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		let mut addr = [0; MAX_ADDR];
+		let len = encode_addr(remote, &mut addr);
+		let id = socket.id;
+		match self.stack.connect(socket.socket, remote) {
+			Ok(socket) => {
+				self.record(TCP_CONNECT, id, &addr[..len]);
+				Ok(RecordedSocket { id, socket })
+			}
+			Err(e) => {
+				self.record_failure(TCP_CONNECT, id, e.kind());
+				Err(e)
+			}
+		}
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.stack.is_connected(&socket.socket)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.write(&mut socket.socket, buffer);
+		let written = *result.as_ref().unwrap_or(&0);
+		let payload = &buffer[..written];
+		self.record_result(TCP_WRITE, socket.id, &result, TcpError::kind, payload);
+		result
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let result = TcpStack::read(&self.stack, &mut socket.socket, buffer);
+		let read = *result.as_ref().unwrap_or(&0);
+		let payload = &buffer[..read];
+		self.record_result(TCP_READ, socket.id, &result, TcpError::kind, payload);
+		result
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		let result = self.stack.receive(&mut socket.socket, buffer);
+		// Recorded as the reads they stand for, so that a replayed `receive`
+		// finds the same outcome.
+		match result {
+			Ok(RecvOutcome::Data(n)) => self.record(TCP_READ, socket.id, &buffer[..n]),
+			Ok(RecvOutcome::Closed) => self.record(TCP_READ, socket.id, &[]),
+			Ok(RecvOutcome::Reset) => {
+				self.record_failure(TCP_READ, socket.id, NetworkErrorKind::ConnectionReset)
+			}
+			Err(nb::Error::Other(ref e)) => self.record_failure(TCP_READ, socket.id, e.kind()),
+			Err(nb::Error::WouldBlock) => {}
+		}
+		result
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		let result = TcpStack::close(&self.stack, socket.socket);
+		match result {
+			Ok(()) => self.record(TCP_CLOSE, socket.id, &[]),
+			Err(ref e) => self.record_failure(TCP_CLOSE, socket.id, e.kind()),
+		}
+		result
+	}
+}
+
+impl<'a, S: UdpStack> UdpStack for Recorder<'a, S> {
+	type UdpSocket = RecordedSocket<S::UdpSocket>;
+	type Error = S::Error;
+
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		let id = self.new_id();
+		let mut addr = [0; MAX_ADDR];
+		let len = encode_addr(remote, &mut addr);
+		match self.stack.open(remote, mode) {
+			Ok(socket) => {
+				self.record(UDP_OPEN, id, &addr[..len]);
+				Ok(RecordedSocket { id, socket })
+			}
+			Err(e) => {
+				self.record_failure(UDP_OPEN, id, e.kind());
+				Err(e)
+			}
+		}
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		let result = self.stack.write(&mut socket.socket, buffer);
+		self.record_result(UDP_WRITE, socket.id, &result, UdpError::kind, buffer);
+		result
+	}
+
+	fn write_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.write_batch(&mut socket.socket, datagrams);
+		match result {
+			Ok(sent) => {
+				for datagram in &datagrams[..sent] {
+					self.record(UDP_WRITE, socket.id, datagram);
+				}
+			}
+			Err(nb::Error::Other(ref e)) => self.record_failure(UDP_WRITE, socket.id, e.kind()),
+			Err(nb::Error::WouldBlock) => {}
+		}
+		result
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		let result = UdpStack::read(&self.stack, &mut socket.socket, buffer);
+		let read = *result.as_ref().unwrap_or(&0);
+		let payload = &buffer[..read];
+		self.record_result(UDP_READ, socket.id, &result, UdpError::kind, payload);
+		result
+	}
+
+	fn read_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, Self::Error> {
+		let result = self.stack.read_batch(&mut socket.socket, datagrams);
+		match result {
+			Ok(received) => {
+				for datagram in &datagrams[..received] {
+					self.record(UDP_READ, socket.id, datagram.data());
+				}
+			}
+			Err(nb::Error::Other(ref e)) => self.record_failure(UDP_READ, socket.id, e.kind()),
+			Err(nb::Error::WouldBlock) => {}
+		}
+		result
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		let result = UdpStack::close(&self.stack, socket.socket);
+		match result {
+			Ok(()) => self.record(UDP_CLOSE, socket.id, &[]),
+			Err(ref e) => self.record_failure(UDP_CLOSE, socket.id, e.kind()),
+		}
+		result
+	}
+}
+
+/// The error type of a [`Replay`].
+///
+/// [`Replay`]: struct.Replay.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayError {
+	/// The call failed when it was recorded, with an error of the given kind
+	Recorded(NetworkErrorKind),
+	/// The call is not the one that was recorded next, or has other
+	/// arguments, such as different data written
+	Mismatch,
+	/// Every recorded operation has been replayed
+	EndOfLog,
+	/// The log is not a valid recording
+	Malformed,
+}
+
+impl TcpError for ReplayError {
+	fn kind(&self) -> NetworkErrorKind {
+		match self {
+			ReplayError::Recorded(kind) => *kind,
+			_ => NetworkErrorKind::Other,
+		}
+	}
+}
+
+impl UdpError for ReplayError {
+	fn kind(&self) -> NetworkErrorKind {
+		TcpError::kind(self)
+	}
+}
+
+impl core::fmt::Display for ReplayError {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match self {
+			ReplayError::Recorded(kind) => {
+				write!(f, "the call failed when it was recorded: {}", kind)
+			}
+			ReplayError::Mismatch => f.write_str("the call does not match the recording"),
+			ReplayError::EndOfLog => f.write_str("the whole log has been replayed"),
+			ReplayError::Malformed => f.write_str("the log is not a valid recording"),
+		}
+	}
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ReplayError {}
+
+/// A socket of a [`Replay`], identified by its id in the log.
+///
+/// [`Replay`]: struct.Replay.html
+#[derive(Debug, PartialEq)]
+pub struct ReplaySocket(u8);
+
+/// A [`TcpStack`] and [`UdpStack`] that serves back a log recorded by a
+/// [`Recorder`], without any network access.
+///
+/// Every call must be the one that was recorded next, with the same
+/// arguments: the same remote addresses, and the same data written. Reads
+/// return the recorded data. Any other call fails with
+/// [`ReplayError::Mismatch`], which makes a replay a regression test of the
+/// protocol client driving it. Calls never return `WouldBlock`.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`Recorder`]: struct.Recorder.html
+/// [`ReplayError::Mismatch`]: enum.ReplayError.html#variant.Mismatch
+pub struct Replay<'a> {
+	log: &'a [u8],
+	pos: Cell<usize>,
+	next_id: Cell<u8>,
+}
+
+impl<'a> Replay<'a> {
+	/// Serve back `log`, as returned by [`Recorder::finish`].
+	///
+	/// [`Recorder::finish`]: struct.Recorder.html#method.finish
+	pub fn new(log: &'a [u8]) -> Self {
+		Replay {
+			log,
+			pos: Cell::new(0),
+			next_id: Cell::new(0),
+		}
+	}
+
+	/// Whether every recorded operation has been replayed.
+	pub fn is_finished(&self) -> bool {
+		self.pos.get() >= self.log.len()
+	}
+
+	/// Take the next entry, which must be operation `op` on socket `id`,
+	/// returning its payload.
+	fn next(&self, op: u8, id: u8) -> Result<&'a [u8], ReplayError> {
+		let pos = self.pos.get();
+		let header = self
+			.log
+			.get(pos..pos + ENTRY_HEADER)
+			.ok_or(ReplayError::EndOfLog)?;
+		let len = usize::from(u16::from(header[2]) << 8 | u16::from(header[3]));
+		let payload = self
+			.log
+			.get(pos + ENTRY_HEADER..pos + ENTRY_HEADER + len)
+			.ok_or(ReplayError::Malformed)?;
+		if header[0] & !FAILED != op || header[1] != id {
+			return Err(ReplayError::Mismatch);
+		}
+		self.pos.set(pos + ENTRY_HEADER + len);
+		if header[0] & FAILED != 0 {
+			let kind = decode_kind(payload).ok_or(ReplayError::Malformed)?;
+			return Err(ReplayError::Recorded(kind));
+		}
+		Ok(payload)
+	}
+
+	fn new_id(&self) -> u8 {
+		let id = self.next_id.get();
+		self.next_id.set(id.wrapping_add(1));
+		id
+	}
+
+	fn expect_addr(&self, op: u8, id: u8, remote: SocketAddr) -> Result<(), ReplayError> {
+		let mut addr = [0; MAX_ADDR];
+		let len = encode_addr(remote, &mut addr);
+		if self.next(op, id)? != &addr[..len] {
+			return Err(ReplayError::Mismatch);
+		}
+		Ok(())
+	}
+
+	fn read_into(&self, op: u8, id: u8, buffer: &mut [u8]) -> nb::Result<usize, ReplayError> {
+		let data = self.next(op, id)?;
+		let out = buffer.get_mut(..data.len()).ok_or(ReplayError::Mismatch)?;
+		out.copy_from_slice(data);
+		Ok(data.len())
+	}
+}
+
+impl<'a> TcpStack for Replay<'a> {
+	type TcpSocket = ReplaySocket;
+	type Error = ReplayError;
+
+	fn open(&self, _mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		let id = self.new_id();
+		self.next(TCP_OPEN, id)?;
+		Ok(ReplaySocket(id))
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		self.expect_addr(TCP_CONNECT, socket.0, remote)?;
+		Ok(socket)
+	}
+
+	fn is_connected(&self, _socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		Ok(true)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		let data = self.next(TCP_WRITE, socket.0)?;
+		if !buffer.starts_with(data) {
+			return Err(nb::Error::Other(ReplayError::Mismatch));
+		}
+		Ok(data.len())
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		self.read_into(TCP_READ, socket.0, buffer)
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		self.next(TCP_CLOSE, socket.0).map(|_| ())
+	}
+}
+
+impl<'a> UdpStack for Replay<'a> {
+	type UdpSocket = ReplaySocket;
+	type Error = ReplayError;
+
+	fn open(&self, remote: SocketAddr, _mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		let id = self.new_id();
+		self.expect_addr(UDP_OPEN, id, remote)?;
+		Ok(ReplaySocket(id))
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		if self.next(UDP_WRITE, socket.0)? != buffer {
+			return Err(nb::Error::Other(ReplayError::Mismatch));
+		}
+		Ok(())
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		self.read_into(UDP_READ, socket.0, buffer)
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		self.next(UDP_CLOSE, socket.0).map(|_| ())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{Call, Expectation, MockStack};
+
+	fn remote() -> SocketAddr {
+		SocketAddr::new(IpAddr::from([192, 0, 2, 1]), 80)
+	}
+
+	/// A stack whose connects fail with an unknown name, and whose reads with
+	/// a reset connection.
+	struct Failing;
+
+	impl TcpStack for Failing {
+		type TcpSocket = ();
+		type Error = NetworkErrorKind;
+
+		fn open(&self, _mode: Mode) -> Result<(), Self::Error> {
+			Ok(())
+		}
+
+		fn connect(&self, _socket: (), _remote: SocketAddr) -> Result<(), Self::Error> {
+			Err(NetworkErrorKind::Dns(DnsErrorKind::NxDomain))
+		}
+
+		fn is_connected(&self, _socket: &()) -> Result<bool, Self::Error> {
+			Ok(true)
+		}
+
+		fn write(&self, _socket: &mut (), buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+			Ok(buffer.len())
+		}
+
+		fn read(&self, _socket: &mut (), _buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+			Err(nb::Error::Other(NetworkErrorKind::ConnectionReset))
+		}
+
+		fn close(&self, _socket: ()) -> Result<(), Self::Error> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn data_round_trip() {
+		let udp_remote = SocketAddr::new(IpAddr::from([192, 0, 2, 2]), 53);
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(remote())),
+			Expectation::new(Call::TcpWrite(b"GET")),
+			Expectation::new(Call::TcpRead(b"")).blocked(),
+			Expectation::new(Call::TcpRead(b"200")),
+			Expectation::new(Call::TcpClose),
+			Expectation::new(Call::UdpOpen(udp_remote)),
+			Expectation::new(Call::UdpWrite(b"query")),
+			Expectation::new(Call::UdpRead(b"answer")),
+			Expectation::new(Call::UdpClose),
+		];
+		let mut log = [0; 128];
+		let recorder = Recorder::new(MockStack::new(&script), &mut log);
+		let mut buffer = [0; 8];
+		let socket = TcpStack::open(&recorder, Mode::Blocking).unwrap();
+		let mut socket = recorder.connect(socket, remote()).unwrap();
+		assert_eq!(TcpStack::write(&recorder, &mut socket, b"GET"), Ok(3));
+		assert_eq!(
+			TcpStack::read(&recorder, &mut socket, &mut buffer),
+			Err(nb::Error::WouldBlock)
+		);
+		assert_eq!(TcpStack::read(&recorder, &mut socket, &mut buffer), Ok(3));
+		TcpStack::close(&recorder, socket).unwrap();
+		let mut socket = UdpStack::open(&recorder, udp_remote, Mode::Blocking).unwrap();
+		UdpStack::write(&recorder, &mut socket, b"query").unwrap();
+		assert_eq!(UdpStack::read(&recorder, &mut socket, &mut buffer), Ok(6));
+		UdpStack::close(&recorder, socket).unwrap();
+		assert!(!recorder.is_truncated());
+		let (_, log) = recorder.finish();
+
+		// The read that would have blocked isn't replayed.
+		let replay = Replay::new(log);
+		let mut buffer = [0; 8];
+		let socket = TcpStack::open(&replay, Mode::Blocking).unwrap();
+		let mut socket = replay.connect(socket, remote()).unwrap();
+		assert_eq!(TcpStack::write(&replay, &mut socket, b"GET"), Ok(3));
+		assert_eq!(TcpStack::read(&replay, &mut socket, &mut buffer), Ok(3));
+		assert_eq!(&buffer[..3], b"200");
+		TcpStack::close(&replay, socket).unwrap();
+		let mut socket = UdpStack::open(&replay, udp_remote, Mode::Blocking).unwrap();
+		UdpStack::write(&replay, &mut socket, b"query").unwrap();
+		assert_eq!(UdpStack::read(&replay, &mut socket, &mut buffer), Ok(6));
+		assert_eq!(&buffer[..6], b"answer");
+		UdpStack::close(&replay, socket).unwrap();
+		assert!(replay.is_finished());
+	}
+
+	#[test]
+	fn failures_replay_with_their_kind() {
+		let mut log = [0; 64];
+		let recorder = Recorder::new(Failing, &mut log);
+		let socket = TcpStack::open(&recorder, Mode::Blocking).unwrap();
+		assert!(recorder.connect(socket, remote()).is_err());
+		let mut socket = TcpStack::open(&recorder, Mode::Blocking).unwrap();
+		let mut buffer = [0; 8];
+		assert_eq!(
+			recorder.receive(&mut socket, &mut buffer),
+			Ok(RecvOutcome::Reset)
+		);
+		assert!(TcpStack::read(&recorder, &mut socket, &mut buffer).is_err());
+		let (_, log) = recorder.finish();
+
+		let replay = Replay::new(log);
+		let socket = TcpStack::open(&replay, Mode::Blocking).unwrap();
+		let error = replay.connect(socket, remote()).unwrap_err();
+		assert_eq!(
+			TcpError::kind(&error),
+			NetworkErrorKind::Dns(DnsErrorKind::NxDomain)
+		);
+		let mut socket = TcpStack::open(&replay, Mode::Blocking).unwrap();
+		assert_eq!(
+			replay.receive(&mut socket, &mut buffer),
+			Ok(RecvOutcome::Reset)
+		);
+		assert_eq!(
+			TcpStack::read(&replay, &mut socket, &mut buffer),
+			Err(nb::Error::Other(ReplayError::Recorded(
+				NetworkErrorKind::ConnectionReset
+			)))
+		);
+		assert!(replay.is_finished());
+	}
+
+	#[test]
+	fn every_kind_is_encoded() {
+		let mut kinds = [NetworkErrorKind::Other; KINDS.len() + DNS_KINDS.len()];
+		kinds[..KINDS.len()].copy_from_slice(&KINDS);
+		for (kind, dns) in kinds[KINDS.len()..].iter_mut().zip(DNS_KINDS.iter()) {
+			*kind = NetworkErrorKind::Dns(*dns);
+		}
+		for kind in kinds.iter() {
+			let mut payload = [0; 2];
+			let len = encode_kind(*kind, &mut payload);
+			assert_eq!(decode_kind(&payload[..len]), Some(*kind));
+		}
+		assert_eq!(decode_kind(&[]), None);
+		assert_eq!(decode_kind(&[0]), None);
+		assert_eq!(decode_kind(&[KINDS.len() as u8 + 1]), None);
+	}
+
+	#[test]
+	fn mismatches_are_reported() {
+		let script = [
+			Expectation::new(Call::TcpOpen),
+			Expectation::new(Call::TcpConnect(remote())),
+			Expectation::new(Call::TcpWrite(b"GET")),
+		];
+		let mut log = [0; 64];
+		let recorder = Recorder::new(MockStack::new(&script), &mut log);
+		let socket = TcpStack::open(&recorder, Mode::Blocking).unwrap();
+		let mut socket = recorder.connect(socket, remote()).unwrap();
+		TcpStack::write(&recorder, &mut socket, b"GET").unwrap();
+		let (_, log) = recorder.finish();
+
+		let replay = Replay::new(log);
+		let socket = TcpStack::open(&replay, Mode::Blocking).unwrap();
+		let other = SocketAddr::new(IpAddr::from([192, 0, 2, 9]), 80);
+		assert_eq!(replay.connect(socket, other), Err(ReplayError::Mismatch));
+
+		let replay = Replay::new(log);
+		let socket = TcpStack::open(&replay, Mode::Blocking).unwrap();
+		let mut socket = replay.connect(socket, remote()).unwrap();
+		let mut buffer = [0; 8];
+		assert_eq!(
+			TcpStack::read(&replay, &mut socket, &mut buffer),
+			Err(nb::Error::Other(ReplayError::Mismatch))
+		);
+		assert_eq!(
+			TcpStack::write(&replay, &mut socket, b"PUT"),
+			Err(nb::Error::Other(ReplayError::Mismatch))
+		);
+
+		let replay = Replay::new(log);
+		let socket = TcpStack::open(&replay, Mode::Blocking).unwrap();
+		let mut socket = replay.connect(socket, remote()).unwrap();
+		assert_eq!(TcpStack::write(&replay, &mut socket, b"GET"), Ok(3));
+		assert_eq!(TcpStack::close(&replay, socket), Err(ReplayError::EndOfLog));
+		assert_eq!(
+			TcpStack::close(
+				&Replay::new(&[TCP_CLOSE | FAILED, 0, 0, 1, 99]),
+				ReplaySocket(0)
+			),
+			Err(ReplayError::Malformed)
+		);
+	}
+}
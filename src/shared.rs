@@ -0,0 +1,223 @@
+use crate::{AddrType, DatagramBuf, Dns, Mode, RecvOutcome, TcpStack, UdpStack};
+use heapless::{consts, String};
+use no_std_net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// A handle to a [`TcpStack`], [`UdpStack`] and [`Dns`] implementation shared
+/// between threads, such as by the tasks of a host-side tool or the threads
+/// of a test. Clones of the handle share the same stack, behind a mutex
+/// that each call holds for its duration.
+///
+/// A thread that panicked while holding the mutex doesn't make the stack
+/// unusable for the others.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`Dns`]: trait.Dns.html
+pub struct SharedStack<S> {
+	stack: Arc<Mutex<S>>,
+}
+
+impl<S> SharedStack<S> {
+	/// Share `stack`.
+	pub fn new(stack: S) -> Self {
+		SharedStack {
+			stack: Arc::new(Mutex::new(stack)),
+		}
+	}
+
+	/// Lock the stack, such as to call the methods of other traits it
+	/// implements.
+	pub fn lock(&self) -> MutexGuard<'_, S> {
+		self.stack.lock().unwrap_or_else(|e| e.into_inner())
+	}
+}
+
+impl<S> Clone for SharedStack<S> {
+	fn clone(&self) -> Self {
+		SharedStack {
+			stack: Arc::clone(&self.stack),
+		}
+	}
+}
+
+impl<S: TcpStack> TcpStack for SharedStack<S> {
+	type TcpSocket = S::TcpSocket;
+	type Error = S::Error;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		TcpStack::open(&*self.lock(), mode)
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		self.lock().connect(socket, remote)
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.lock().is_connected(socket)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		TcpStack::write(&*self.lock(), socket, buffer)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		TcpStack::read(&*self.lock(), socket, buffer)
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		self.lock().receive(socket, buffer)
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		TcpStack::close(&*self.lock(), socket)
+	}
+}
+
+impl<S: UdpStack> UdpStack for SharedStack<S> {
+	type UdpSocket = S::UdpSocket;
+	type Error = S::Error;
+
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		UdpStack::open(&*self.lock(), remote, mode)
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		UdpStack::write(&*self.lock(), socket, buffer)
+	}
+
+	fn write_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, Self::Error> {
+		self.lock().write_batch(socket, datagrams)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		UdpStack::read(&*self.lock(), socket, buffer)
+	}
+
+	fn read_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, Self::Error> {
+		self.lock().read_batch(socket, datagrams)
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		UdpStack::close(&*self.lock(), socket)
+	}
+}
+
+impl<S: Dns> Dns for SharedStack<S> {
+	type Error = S::Error;
+
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error> {
+		self.lock().get_host_by_name(hostname, addr_type)
+	}
+
+	fn get_host_by_name_with_ttl(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<(IpAddr, Option<u32>), Self::Error> {
+		self.lock().get_host_by_name_with_ttl(hostname, addr_type)
+	}
+
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		self.lock().get_hosts_by_name(hostname, addr_type, out)
+	}
+
+	fn get_host_by_address(&self, addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error> {
+		self.lock().get_host_by_address(addr)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{LoopbackStack, UdpServer};
+
+	fn addr(port: u16) -> SocketAddr {
+		SocketAddr::new(IpAddr::from([127, 0, 0, 1]), port)
+	}
+
+	#[test]
+	fn tcp_calls_are_forwarded_across_threads() {
+		let stack = SharedStack::new(LoopbackStack::new());
+		stack.lock().listen(addr(80)).unwrap();
+
+		let client = stack.clone();
+		let thread = std::thread::spawn(move || {
+			let socket = TcpStack::open(&client, Mode::Blocking).unwrap();
+			let mut socket = client.connect(socket, addr(80)).unwrap();
+			assert!(client.is_connected(&socket).unwrap());
+			assert_eq!(TcpStack::write(&client, &mut socket, b"hello"), Ok(5));
+			socket
+		});
+		let client = thread.join().unwrap();
+
+		let mut server = stack.lock().accept(addr(80)).unwrap();
+		let mut buffer = [0; 8];
+		assert_eq!(TcpStack::read(&stack, &mut server, &mut buffer), Ok(5));
+		assert_eq!(&buffer[..5], b"hello");
+		TcpStack::close(&stack, client).unwrap();
+		assert_eq!(
+			stack.receive(&mut server, &mut buffer),
+			Ok(RecvOutcome::Closed)
+		);
+		TcpStack::close(&stack, server).unwrap();
+	}
+
+	#[test]
+	fn udp_and_dns_calls_are_forwarded() {
+		let stack = SharedStack::new(LoopbackStack::new());
+		let other = stack.clone();
+		other
+			.lock()
+			.add_host("example.com", IpAddr::from([127, 0, 0, 1]))
+			.unwrap();
+		assert_eq!(
+			stack.get_host_by_name("example.com", AddrType::Either),
+			Ok(IpAddr::from([127, 0, 0, 1]))
+		);
+
+		let mut server = stack
+			.lock()
+			.bind(addr(53), Default::default(), Mode::Blocking)
+			.unwrap();
+		let mut client = UdpStack::open(&other, addr(53), Mode::Blocking).unwrap();
+		assert_eq!(other.write_batch(&mut client, &[b"one", b"two"]), Ok(2));
+		let mut buffer = [0; 8];
+		let (len, _) = stack.lock().read_from(&mut server, &mut buffer).unwrap();
+		assert_eq!(&buffer[..len], b"one");
+		UdpStack::close(&other, client).unwrap();
+	}
+}
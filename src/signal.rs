@@ -0,0 +1,30 @@
+/// Radio signal measurements, as returned by
+/// [`SignalQuality::signal_quality`]. Which fields are set depends on the
+/// radio technology: cellular modems usually report all of them but
+/// `snr_db`, while Wi-Fi usually only reports `rssi_dbm` and `snr_db`.
+///
+/// [`SignalQuality::signal_quality`]: trait.SignalQuality.html#tymethod.signal_quality
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SignalInfo {
+	/// Received signal strength indicator, in dBm
+	pub rssi_dbm: Option<i16>,
+	/// Reference signal received power (LTE), in dBm
+	pub rsrp_dbm: Option<i16>,
+	/// Reference signal received quality (LTE), in dB
+	pub rsrq_db: Option<i16>,
+	/// Signal to interference plus noise ratio (LTE), in dB
+	pub sinr_db: Option<i16>,
+	/// Signal to noise ratio (Wi-Fi), in dB
+	pub snr_db: Option<i16>,
+}
+
+/// This trait is implemented by stacks with a radio link that can report the
+/// quality of its signal, for fleet telemetry and for retry policies that
+/// adapt to the link.
+pub trait SignalQuality {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Measure the current signal quality.
+	fn signal_quality(&self) -> nb::Result<SignalInfo, Self::Error>;
+}
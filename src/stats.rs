@@ -0,0 +1,42 @@
+use crate::Interface;
+
+/// Traffic counters of a network interface, as returned by
+/// [`NetStats::interface_stats`]. Counters wrap around when they overflow.
+///
+/// [`NetStats::interface_stats`]: trait.NetStats.html#tymethod.interface_stats
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InterfaceStats {
+	/// Bytes sent, including protocol headers
+	pub tx_bytes: u64,
+	/// Bytes received, including protocol headers
+	pub rx_bytes: u64,
+	/// Packets sent
+	pub tx_packets: u32,
+	/// Packets received
+	pub rx_packets: u32,
+	/// Packets that could not be sent because of an error
+	pub tx_errors: u32,
+	/// Packets received with an error, such as a bad checksum
+	pub rx_errors: u32,
+	/// Packets dropped before being sent, such as when queues were full
+	pub tx_dropped: u32,
+	/// Packets received but dropped, such as when buffers were full
+	pub rx_dropped: u32,
+}
+
+/// This trait is implemented by stacks that count the traffic of their
+/// interfaces, so that devices on metered cellular plans can report how much
+/// data they use.
+pub trait NetStats {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Get the counters of the given interface. With [`Interface::Any`], the
+	/// counters of all interfaces are summed.
+	///
+	/// [`Interface::Any`]: enum.Interface.html#variant.Any
+	fn interface_stats(&self, interface: Interface) -> Result<InterfaceStats, Self::Error>;
+
+	/// Reset the counters of the given interface to zero.
+	fn reset_stats(&self, interface: Interface) -> Result<(), Self::Error>;
+}
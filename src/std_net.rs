@@ -0,0 +1,508 @@
+use crate::{
+	AddrType, BindOptions, Dns, DnsError, DnsErrorKind, Mode, NetworkErrorKind, TcpError, TcpStack,
+	UdpError, UdpOptions, UdpServer, UdpStack,
+};
+use heapless::{consts, String};
+use no_std_net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{self, TcpStream, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+fn to_std(addr: SocketAddr) -> net::SocketAddr {
+	let ip = match addr.ip() {
+		IpAddr::V4(ip) => net::IpAddr::from(ip.octets()),
+		IpAddr::V6(ip) => net::IpAddr::from(ip.octets()),
+	};
+	net::SocketAddr::new(ip, addr.port())
+}
+
+fn from_std_ip(ip: net::IpAddr) -> IpAddr {
+	match ip {
+		net::IpAddr::V4(ip) => IpAddr::from(ip.octets()),
+		net::IpAddr::V6(ip) => IpAddr::from(ip.octets()),
+	}
+}
+
+fn from_std(addr: net::SocketAddr) -> SocketAddr {
+	SocketAddr::new(from_std_ip(addr.ip()), addr.port())
+}
+
+/// Turn an I/O error into an `nb` error, so that sockets in
+/// [`Mode::NonBlocking`] and [`Mode::Timeout`] report `WouldBlock`.
+///
+/// [`Mode::NonBlocking`]: enum.Mode.html#variant.NonBlocking
+/// [`Mode::Timeout`]: enum.Mode.html#variant.Timeout
+fn nb_error(e: io::Error) -> nb::Error<io::Error> {
+	match e.kind() {
+		ErrorKind::WouldBlock | ErrorKind::TimedOut => nb::Error::WouldBlock,
+		_ => nb::Error::Other(e),
+	}
+}
+
+/// The timeout of sockets in `mode`. A timeout of 0 stands for not waiting
+/// at all, which `std::net` doesn't accept as a timeout, so it is left to
+/// [`nonblocking`].
+fn timeout(mode: Mode) -> Option<Duration> {
+	match mode {
+		Mode::Timeout(0) => None,
+		Mode::Timeout(ms) => Some(Duration::from_millis(u64::from(ms))),
+		_ => None,
+	}
+}
+
+/// Whether sockets in `mode` must not wait at all.
+fn nonblocking(mode: Mode) -> bool {
+	match mode {
+		Mode::NonBlocking | Mode::Timeout(0) => true,
+		_ => false,
+	}
+}
+
+/// The `ErrorKind` of an I/O error doesn't tell a name that doesn't exist
+/// apart from other failures: `NotFound` is also what a missing file or
+/// interface reports, and a failed lookup of the host resolver comes back
+/// as an uncategorized error. Neither maps to `NxDomain`.
+impl From<ErrorKind> for DnsErrorKind {
+	fn from(kind: ErrorKind) -> Self {
+		match kind {
+			ErrorKind::TimedOut => DnsErrorKind::Timeout,
+			ErrorKind::InvalidInput => DnsErrorKind::InvalidName,
+			_ => DnsErrorKind::Other,
+		}
+	}
+}
+
+impl From<DnsErrorKind> for ErrorKind {
+	fn from(kind: DnsErrorKind) -> Self {
+		match kind {
+			DnsErrorKind::NxDomain | DnsErrorKind::NoRecordOfType => ErrorKind::NotFound,
+			DnsErrorKind::Timeout => ErrorKind::TimedOut,
+			DnsErrorKind::NameTooLong | DnsErrorKind::InvalidName => ErrorKind::InvalidInput,
+			DnsErrorKind::ServerFailure | DnsErrorKind::Other => ErrorKind::Other,
+		}
+	}
+}
+
+impl From<ErrorKind> for NetworkErrorKind {
+	fn from(kind: ErrorKind) -> Self {
+		match kind {
+			ErrorKind::WouldBlock => NetworkErrorKind::WouldBlock,
+			ErrorKind::TimedOut => NetworkErrorKind::Timeout,
+			ErrorKind::ConnectionRefused => NetworkErrorKind::ConnectionRefused,
+			ErrorKind::ConnectionReset => NetworkErrorKind::ConnectionReset,
+			ErrorKind::BrokenPipe | ErrorKind::ConnectionAborted | ErrorKind::UnexpectedEof => {
+				NetworkErrorKind::PipeClosed
+			}
+			ErrorKind::NotConnected => NetworkErrorKind::NotConnected,
+			ErrorKind::AddrInUse => NetworkErrorKind::AddrInUse,
+			ErrorKind::AddrNotAvailable => NetworkErrorKind::Unreachable,
+			ErrorKind::InvalidInput => NetworkErrorKind::InvalidInput,
+			_ => NetworkErrorKind::Other,
+		}
+	}
+}
+
+impl From<NetworkErrorKind> for ErrorKind {
+	fn from(kind: NetworkErrorKind) -> Self {
+		match kind {
+			NetworkErrorKind::WouldBlock => ErrorKind::WouldBlock,
+			NetworkErrorKind::Timeout => ErrorKind::TimedOut,
+			NetworkErrorKind::ConnectionRefused => ErrorKind::ConnectionRefused,
+			NetworkErrorKind::ConnectionReset => ErrorKind::ConnectionReset,
+			NetworkErrorKind::PipeClosed => ErrorKind::BrokenPipe,
+			NetworkErrorKind::NotConnected => ErrorKind::NotConnected,
+			NetworkErrorKind::AddrInUse => ErrorKind::AddrInUse,
+			NetworkErrorKind::Unreachable => ErrorKind::AddrNotAvailable,
+			NetworkErrorKind::InvalidInput => ErrorKind::InvalidInput,
+			NetworkErrorKind::Dns(kind) => kind.into(),
+			NetworkErrorKind::Exhausted | NetworkErrorKind::Other => ErrorKind::Other,
+		}
+	}
+}
+
+impl TcpError for io::Error {
+	fn kind(&self) -> NetworkErrorKind {
+		io::Error::kind(self).into()
+	}
+}
+
+impl UdpError for io::Error {
+	fn kind(&self) -> NetworkErrorKind {
+		io::Error::kind(self).into()
+	}
+}
+
+/// Errors of [`StdDns`] carry this payload, with the kind of the failure as
+/// told by the resolver.
+///
+/// [`StdDns`]: struct.StdDns.html
+#[derive(Debug)]
+struct LookupError {
+	kind: DnsErrorKind,
+	source: Option<io::Error>,
+}
+
+impl core::fmt::Display for LookupError {
+	fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+		match &self.source {
+			Some(e) => e.fmt(f),
+			None => self.kind.fmt(f),
+		}
+	}
+}
+
+impl std::error::Error for LookupError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		self.source
+			.as_ref()
+			.map(|e| e as &(dyn std::error::Error + 'static))
+	}
+}
+
+/// An error of the given kind, for failures found by [`StdDns`] itself.
+///
+/// [`StdDns`]: struct.StdDns.html
+fn dns_error(kind: DnsErrorKind) -> io::Error {
+	io::Error::new(ErrorKind::from(kind), LookupError { kind, source: None })
+}
+
+/// Wrap a failure of the host resolver, classified with
+/// [`resolver_error_kind`].
+fn lookup_error(e: io::Error) -> io::Error {
+	io::Error::new(
+		e.kind(),
+		LookupError {
+			kind: resolver_error_kind(&e),
+			source: Some(e),
+		},
+	)
+}
+
+/// The kind of a failure of the host resolver. Only the Windows resolver
+/// reports why it failed through the error itself; elsewhere, `getaddrinfo`
+/// errors only have a message, so all that is known is their `ErrorKind`.
+fn resolver_error_kind(e: &io::Error) -> DnsErrorKind {
+	#[cfg(windows)]
+	{
+		// WSAHOST_NOT_FOUND and WSANO_DATA
+		match e.raw_os_error() {
+			Some(11001) => return DnsErrorKind::NxDomain,
+			Some(11004) => return DnsErrorKind::NoRecordOfType,
+			_ => {}
+		}
+	}
+	e.kind().into()
+}
+
+impl DnsError for io::Error {
+	fn kind(&self) -> DnsErrorKind {
+		match self.get_ref().and_then(|e| e.downcast_ref::<LookupError>()) {
+			Some(e) => e.kind,
+			None => io::Error::kind(self).into(),
+		}
+	}
+}
+
+/// A [`TcpStack`] over `std::net`, so that applications can be developed and
+/// integration-tested on a host before moving to their target.
+///
+/// The socket [`Mode`] is honoured once connected: reads and writes of
+/// [`Mode::NonBlocking`] sockets return `WouldBlock` instead of waiting, and
+/// those of [`Mode::Timeout`] sockets after the timeout. A timeout of 0
+/// behaves like [`Mode::NonBlocking`]. Connecting always waits, up to the
+/// timeout for [`Mode::Timeout`] sockets with a non-zero timeout.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`Mode`]: enum.Mode.html
+/// [`Mode::NonBlocking`]: enum.Mode.html#variant.NonBlocking
+/// [`Mode::Timeout`]: enum.Mode.html#variant.Timeout
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdTcpStack;
+
+/// A socket of a [`StdTcpStack`].
+///
+/// [`StdTcpStack`]: struct.StdTcpStack.html
+#[derive(Debug)]
+pub struct StdTcpSocket {
+	mode: Mode,
+	stream: Option<TcpStream>,
+}
+
+impl StdTcpSocket {
+	fn stream(&mut self) -> Result<&mut TcpStream, io::Error> {
+		self.stream
+			.as_mut()
+			.ok_or_else(|| io::Error::from(ErrorKind::NotConnected))
+	}
+}
+
+impl TcpStack for StdTcpStack {
+	type TcpSocket = StdTcpSocket;
+	type Error = io::Error;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		Ok(StdTcpSocket { mode, stream: None })
+	}
+
+	fn connect(
+		&self,
+		mut socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		let stream = match timeout(socket.mode) {
+			Some(timeout) => TcpStream::connect_timeout(&to_std(remote), timeout)?,
+			None => TcpStream::connect(to_std(remote))?,
+		};
+		stream.set_nonblocking(nonblocking(socket.mode))?;
+		stream.set_read_timeout(timeout(socket.mode))?;
+		stream.set_write_timeout(timeout(socket.mode))?;
+		socket.stream = Some(stream);
+		Ok(socket)
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		Ok(socket
+			.stream
+			.as_ref()
+			.map_or(false, |stream| stream.peer_addr().is_ok()))
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		socket.stream()?.write(buffer).map_err(nb_error)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		socket.stream()?.read(buffer).map_err(nb_error)
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		match socket.stream {
+			Some(stream) => match stream.shutdown(net::Shutdown::Both) {
+				// The peer may already have closed the connection.
+				Err(ref e) if e.kind() == ErrorKind::NotConnected => Ok(()),
+				result => result,
+			},
+			None => Ok(()),
+		}
+	}
+}
+
+/// A [`UdpStack`], [`UdpServer`] and [`UdpOptions`] implementation over
+/// `std::net`. The socket [`Mode`] is honoured like by [`StdTcpStack`].
+///
+/// `std::net` can't set `SO_REUSEADDR` or `SO_REUSEPORT`, so the
+/// [`BindOptions`] of [`UdpServer::bind`] are ignored, nor the hop limit of
+/// IPv6 unicast datagrams, so [`UdpOptions::set_hop_limit`] fails.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`UdpServer`]: trait.UdpServer.html
+/// [`UdpOptions`]: trait.UdpOptions.html
+/// [`Mode`]: enum.Mode.html
+/// [`StdTcpStack`]: struct.StdTcpStack.html
+/// [`BindOptions`]: struct.BindOptions.html
+/// [`UdpServer::bind`]: trait.UdpServer.html#tymethod.bind
+/// [`UdpOptions::set_hop_limit`]: trait.UdpOptions.html#tymethod.set_hop_limit
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdUdpStack;
+
+fn bind(local: SocketAddr, mode: Mode) -> Result<UdpSocket, io::Error> {
+	let socket = UdpSocket::bind(to_std(local))?;
+	socket.set_nonblocking(nonblocking(mode))?;
+	socket.set_read_timeout(timeout(mode))?;
+	socket.set_write_timeout(timeout(mode))?;
+	Ok(socket)
+}
+
+impl UdpStack for StdUdpStack {
+	type UdpSocket = UdpSocket;
+	type Error = io::Error;
+
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		let ip = match remote {
+			SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
+			SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 0)),
+		};
+		let socket = bind(SocketAddr::new(ip, 0), mode)?;
+		socket.connect(to_std(remote))?;
+		Ok(socket)
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		socket.send(buffer).map(|_| ()).map_err(nb_error)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		socket.recv(buffer).map_err(nb_error)
+	}
+
+	fn close(&self, _socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		Ok(())
+	}
+}
+
+impl UdpServer for StdUdpStack {
+	fn bind(
+		&self,
+		local: SocketAddr,
+		_options: BindOptions,
+		mode: Mode,
+	) -> Result<Self::UdpSocket, Self::Error> {
+		bind(local, mode)
+	}
+
+	fn write_to(
+		&self,
+		socket: &mut Self::UdpSocket,
+		remote: SocketAddr,
+		buffer: &[u8],
+	) -> nb::Result<(), Self::Error> {
+		socket
+			.send_to(buffer, to_std(remote))
+			.map(|_| ())
+			.map_err(nb_error)
+	}
+
+	fn read_from(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<(usize, SocketAddr), Self::Error> {
+		socket
+			.recv_from(buffer)
+			.map(|(n, remote)| (n, from_std(remote)))
+			.map_err(nb_error)
+	}
+}
+
+impl UdpOptions for StdUdpStack {
+	fn set_broadcast(&self, socket: &mut Self::UdpSocket, enable: bool) -> Result<(), Self::Error> {
+		socket.set_broadcast(enable)
+	}
+
+	fn set_ttl(&self, socket: &mut Self::UdpSocket, ttl: u8) -> Result<(), Self::Error> {
+		socket.set_ttl(u32::from(ttl))
+	}
+
+	fn set_hop_limit(&self, _socket: &mut Self::UdpSocket, _hops: u8) -> Result<(), Self::Error> {
+		Err(io::Error::new(
+			ErrorKind::Other,
+			"std::net can't set the IPv6 unicast hop limit",
+		))
+	}
+
+	fn max_datagram_size(&self, socket: &Self::UdpSocket) -> Result<usize, Self::Error> {
+		// The largest payload that fits the 16 bit length of the IP header.
+		Ok(match socket.local_addr()? {
+			net::SocketAddr::V4(_) => 65507,
+			net::SocketAddr::V6(_) => 65527,
+		})
+	}
+
+	fn set_read_timeout(
+		&self,
+		socket: &mut Self::UdpSocket,
+		timeout: Option<Duration>,
+	) -> Result<(), Self::Error> {
+		socket.set_read_timeout(timeout)
+	}
+}
+
+/// A [`Dns`] implementation using the resolver of the host, through
+/// `std::net::ToSocketAddrs`. Lookups always wait for the answer.
+///
+/// The standard library has no reverse lookup, so
+/// [`get_host_by_address`](#method.get_host_by_address) always fails.
+///
+/// [`Dns`]: trait.Dns.html
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdDns;
+
+impl Dns for StdDns {
+	type Error = io::Error;
+
+	fn get_host_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+	) -> nb::Result<IpAddr, Self::Error> {
+		let mut addr = [IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0))];
+		self.get_hosts_by_name(hostname, addr_type, &mut addr)?;
+		Ok(addr[0])
+	}
+
+	fn get_hosts_by_name(
+		&self,
+		hostname: &str,
+		addr_type: AddrType,
+		out: &mut [IpAddr],
+	) -> nb::Result<usize, Self::Error> {
+		let addrs = (hostname, 0)
+			.to_socket_addrs()
+			.map_err(|e| nb::Error::Other(lookup_error(e)))?
+			.map(|addr| addr.ip())
+			.filter(|ip| match addr_type {
+				AddrType::IPv4 => ip.is_ipv4(),
+				AddrType::IPv6 => ip.is_ipv6(),
+				AddrType::Either => true,
+			});
+		let mut found = 0;
+		for (slot, ip) in out.iter_mut().zip(addrs) {
+			*slot = from_std_ip(ip);
+			found += 1;
+		}
+		if found == 0 && !out.is_empty() {
+			return Err(nb::Error::Other(dns_error(DnsErrorKind::NoRecordOfType)));
+		}
+		Ok(found)
+	}
+
+	fn get_host_by_address(&self, _addr: IpAddr) -> nb::Result<String<consts::U256>, Self::Error> {
+		Err(nb::Error::Other(io::Error::new(
+			ErrorKind::Other,
+			"std::net has no reverse lookup",
+		)))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn dns_error_kinds() {
+		let not_found = io::Error::new(ErrorKind::NotFound, "no such file");
+		assert_eq!(DnsError::kind(&not_found), DnsErrorKind::Other);
+		let timed_out = io::Error::from(ErrorKind::TimedOut);
+		assert_eq!(DnsError::kind(&timed_out), DnsErrorKind::Timeout);
+
+		let failed = lookup_error(io::Error::new(ErrorKind::Other, "lookup failed"));
+		assert_eq!(failed.kind(), ErrorKind::Other);
+		assert_eq!(DnsError::kind(&failed), DnsErrorKind::Other);
+		assert_eq!(failed.to_string(), "lookup failed");
+
+		let no_record = dns_error(DnsErrorKind::NoRecordOfType);
+		assert_eq!(no_record.kind(), ErrorKind::NotFound);
+		assert_eq!(DnsError::kind(&no_record), DnsErrorKind::NoRecordOfType);
+	}
+
+	#[test]
+	fn zero_timeout_does_not_wait() {
+		let local = SocketAddr::new(IpAddr::from([127, 0, 0, 1]), 0);
+		let mut socket = StdUdpStack
+			.bind(local, BindOptions::default(), Mode::Timeout(0))
+			.unwrap();
+		let mut buffer = [0; 8];
+		match StdUdpStack.read_from(&mut socket, &mut buffer) {
+			Err(nb::Error::WouldBlock) => {}
+			result => panic!("expected WouldBlock, got {:?}", result),
+		}
+	}
+}
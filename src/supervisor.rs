@@ -0,0 +1,393 @@
+use crate::clock::{millis, Clock};
+use core::time::Duration;
+
+/// How a [`ConnectionSupervisor`] spaces out its connection attempts.
+///
+/// After the `n`th failed attempt in a row, the supervisor waits
+/// `initial_delay * 2^(n - 1)`, capped at `max_delay`, and
+/// shortened by a random amount of up to `jitter_percent` percent so that
+/// devices that lost their connection together don't all retry together.
+///
+/// The default waits from 1 second up to 5 minutes, with 25% jitter, and
+/// never gives up.
+///
+/// [`ConnectionSupervisor`]: struct.ConnectionSupervisor.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BackoffPolicy {
+	/// How long to wait after the first failed attempt
+	pub initial_delay: Duration,
+	/// The longest to wait between attempts
+	pub max_delay: Duration,
+	/// How much of each delay may be cut at random, in percent
+	pub jitter_percent: u8,
+	/// How many attempts in a row may fail before giving up, or `None` to
+	/// never give up
+	pub max_attempts: Option<u16>,
+}
+
+impl Default for BackoffPolicy {
+	fn default() -> Self {
+		BackoffPolicy {
+			initial_delay: Duration::from_secs(1),
+			max_delay: Duration::from_secs(300),
+			jitter_percent: 25,
+			max_attempts: None,
+		}
+	}
+}
+
+/// The state of a [`ConnectionSupervisor`], as returned by
+/// [`ConnectionSupervisor::poll`].
+///
+/// [`ConnectionSupervisor`]: struct.ConnectionSupervisor.html
+/// [`ConnectionSupervisor::poll`]: struct.ConnectionSupervisor.html#method.poll
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SupervisorState {
+	/// An attempt is in progress
+	Connecting,
+	/// The connection is up
+	Connected,
+	/// The last attempt failed, and the next one is due after the given time
+	Waiting {
+		/// The time until the next attempt
+		retry_in: Duration,
+	},
+	/// The policy's maximum number of attempts failed
+	GaveUp,
+}
+
+enum Phase<T> {
+	Connecting,
+	Connected(T),
+	Waiting { until: u64 },
+	GaveUp,
+}
+
+/// Keeps a connection up: it calls a connect closure, and when that fails,
+/// calls it again after a delay that grows according to a
+/// [`BackoffPolicy`]. This is the reconnect logic every application
+/// otherwise writes for itself.
+///
+/// The supervisor is a state machine driven by [`poll`](#method.poll), which
+/// never blocks. The closure is called from `poll` until it returns the
+/// connection or an error; `WouldBlock` means the attempt is still in
+/// progress. The application reports a broken connection with
+/// [`disconnected`](#method.disconnected), which hands the connection back
+/// to be closed and makes the next `poll` reconnect straight away.
+///
+/// [`BackoffPolicy`]: struct.BackoffPolicy.html
+pub struct ConnectionSupervisor<F, C, T, E> {
+	connect: F,
+	clock: C,
+	policy: BackoffPolicy,
+	phase: Phase<T>,
+	/// Failed attempts in a row
+	failures: u16,
+	last_error: Option<E>,
+	rng: u32,
+}
+
+impl<F, C, T, E> ConnectionSupervisor<F, C, T, E>
+where
+	F: FnMut() -> nb::Result<T, E>,
+	C: Clock,
+{
+	/// Supervise the connections made by `connect`, timing the attempts with
+	/// `clock` according to `policy`. The jitter is drawn from the sequence
+	/// determined by `seed`, which should differ between devices, such as a
+	/// serial number. The first attempt is made by the first `poll`.
+	pub fn new(connect: F, clock: C, policy: BackoffPolicy, seed: u32) -> Self {
+		ConnectionSupervisor {
+			connect,
+			clock,
+			policy,
+			phase: Phase::Connecting,
+			failures: 0,
+			last_error: None,
+			// The generator gets stuck at zero.
+			rng: if seed == 0 { 0x9E37_79B9 } else { seed },
+		}
+	}
+
+	/// Move the state machine forward, making or continuing a connection
+	/// attempt if one is due, and return the resulting state.
+	pub fn poll(&mut self) -> SupervisorState {
+		let now = self.clock.now_ms();
+		if let Phase::Waiting { until } = self.phase {
+			if now < until {
+				return SupervisorState::Waiting {
+					retry_in: Duration::from_millis(until - now),
+				};
+			}
+			self.phase = Phase::Connecting;
+		}
+		if let Phase::Connecting = self.phase {
+			match (self.connect)() {
+				Ok(connection) => {
+					self.phase = Phase::Connected(connection);
+					self.failures = 0;
+				}
+				Err(nb::Error::WouldBlock) => {}
+				Err(nb::Error::Other(e)) => {
+					self.last_error = Some(e);
+					self.failures = self.failures.saturating_add(1);
+					self.phase = match self.policy.max_attempts {
+						Some(max) if self.failures >= max => Phase::GaveUp,
+						_ => Phase::Waiting {
+							until: now.saturating_add(self.delay_ms()),
+						},
+					};
+				}
+			}
+		}
+		self.state()
+	}
+
+	/// The current state, as last moved forward by [`poll`](#method.poll).
+	pub fn state(&self) -> SupervisorState {
+		match self.phase {
+			Phase::Connecting => SupervisorState::Connecting,
+			Phase::Connected(_) => SupervisorState::Connected,
+			Phase::Waiting { until } => SupervisorState::Waiting {
+				retry_in: Duration::from_millis(until.saturating_sub(self.clock.now_ms())),
+			},
+			Phase::GaveUp => SupervisorState::GaveUp,
+		}
+	}
+
+	/// The connection, while it is up.
+	pub fn connection(&mut self) -> Option<&mut T> {
+		match &mut self.phase {
+			Phase::Connected(connection) => Some(connection),
+			_ => None,
+		}
+	}
+
+	/// Report that the connection broke. Returns the connection, to be
+	/// closed, if it was up; the next [`poll`](#method.poll) reconnects.
+	pub fn disconnected(&mut self) -> Option<T> {
+		match core::mem::replace(&mut self.phase, Phase::Connecting) {
+			Phase::Connected(connection) => Some(connection),
+			phase => {
+				self.phase = phase;
+				None
+			}
+		}
+	}
+
+	/// Start over after giving up, or skip the wait for the next attempt.
+	pub fn reset(&mut self) {
+		if let Phase::GaveUp | Phase::Waiting { .. } = self.phase {
+			self.phase = Phase::Connecting;
+		}
+		self.failures = 0;
+	}
+
+	/// The error of the last failed attempt.
+	pub fn last_error(&self) -> Option<&E> {
+		self.last_error.as_ref()
+	}
+
+	/// How many attempts in a row have failed.
+	pub fn failures(&self) -> u16 {
+		self.failures
+	}
+
+	/// The delay after the current number of failures, with jitter.
+	fn delay_ms(&mut self) -> u64 {
+		let doublings = core::cmp::min(u32::from(self.failures) - 1, 32);
+		let initial = millis(self.policy.initial_delay);
+		let doubled = if initial > core::u64::MAX >> doublings {
+			core::u64::MAX
+		} else {
+			initial << doublings
+		};
+		let delay = core::cmp::min(doubled, millis(self.policy.max_delay));
+		// xorshift32
+		self.rng ^= self.rng << 13;
+		self.rng ^= self.rng >> 17;
+		self.rng ^= self.rng << 5;
+		let percent = u128::from(core::cmp::min(self.policy.jitter_percent, 100));
+		let jitter = u128::from(delay) * percent / 100;
+		delay - (jitter * u128::from(self.rng) / u128::from(core::u32::MAX)) as u64
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::cell::Cell;
+
+	struct Manual(Cell<u64>);
+
+	impl Manual {
+		fn advance(&self, ms: u64) {
+			self.0.set(self.0.get() + ms);
+		}
+	}
+
+	impl Clock for Manual {
+		fn now_ms(&self) -> u64 {
+			self.0.get()
+		}
+	}
+
+	#[derive(Clone, Copy)]
+	enum Next {
+		Fail,
+		Pending,
+		Connect,
+	}
+
+	/// A connect closure doing what `next` says, counting its calls in
+	/// `calls`.
+	fn attempt<'a>(
+		next: &'a Cell<Next>,
+		calls: &'a Cell<usize>,
+	) -> impl FnMut() -> nb::Result<u8, &'static str> + 'a {
+		move || {
+			calls.set(calls.get() + 1);
+			match next.get() {
+				Next::Fail => Err(nb::Error::Other("refused")),
+				Next::Pending => Err(nb::Error::WouldBlock),
+				Next::Connect => Ok(7),
+			}
+		}
+	}
+
+	fn policy(max_attempts: Option<u16>) -> BackoffPolicy {
+		BackoffPolicy {
+			initial_delay: Duration::from_secs(1),
+			max_delay: Duration::from_secs(5),
+			jitter_percent: 0,
+			max_attempts,
+		}
+	}
+
+	fn waiting(ms: u64) -> SupervisorState {
+		SupervisorState::Waiting {
+			retry_in: Duration::from_millis(ms),
+		}
+	}
+
+	#[test]
+	fn delays_double_up_to_the_cap() {
+		let clock = Manual(Cell::new(0));
+		let (next, calls) = (Cell::new(Next::Fail), Cell::new(0));
+		let mut supervisor =
+			ConnectionSupervisor::new(attempt(&next, &calls), &clock, policy(None), 1);
+		for &delay in &[1000, 2000, 4000, 5000, 5000] {
+			assert_eq!(supervisor.poll(), waiting(delay));
+			clock.advance(delay);
+		}
+		assert_eq!(supervisor.failures(), 5);
+		assert_eq!(supervisor.last_error(), Some(&"refused"));
+	}
+
+	#[test]
+	fn no_attempt_is_made_while_waiting() {
+		let clock = Manual(Cell::new(0));
+		let (next, calls) = (Cell::new(Next::Fail), Cell::new(0));
+		let mut supervisor =
+			ConnectionSupervisor::new(attempt(&next, &calls), &clock, policy(None), 1);
+		assert_eq!(supervisor.poll(), waiting(1000));
+		clock.advance(400);
+		assert_eq!(supervisor.poll(), waiting(600));
+		assert_eq!(supervisor.state(), waiting(600));
+		assert_eq!(calls.get(), 1);
+
+		clock.advance(600);
+		assert_eq!(supervisor.poll(), waiting(2000));
+		assert_eq!(calls.get(), 2);
+	}
+
+	#[test]
+	fn jitter_shortens_delays_by_up_to_its_percentage() {
+		let clock = Manual(Cell::new(0));
+		let next = Cell::new(Next::Fail);
+		let policy = BackoffPolicy {
+			jitter_percent: 50,
+			..policy(None)
+		};
+		let first_delay = |seed| {
+			let calls = Cell::new(0);
+			let mut supervisor =
+				ConnectionSupervisor::new(attempt(&next, &calls), &clock, policy, seed);
+			match supervisor.poll() {
+				SupervisorState::Waiting { retry_in } => millis(retry_in),
+				state => panic!("unexpected state {:?}", state),
+			}
+		};
+		let mut delays = [0; 16];
+		for (seed, delay) in delays.iter_mut().enumerate() {
+			*delay = first_delay(seed as u32);
+			assert!((500..=1000).contains(delay), "delay {}", delay);
+		}
+		assert!(delays.iter().any(|&delay| delay != delays[0]));
+		assert_eq!(first_delay(3), delays[3]);
+	}
+
+	#[test]
+	fn gives_up_after_max_attempts_until_reset() {
+		let clock = Manual(Cell::new(0));
+		let (next, calls) = (Cell::new(Next::Fail), Cell::new(0));
+		let mut supervisor =
+			ConnectionSupervisor::new(attempt(&next, &calls), &clock, policy(Some(3)), 1);
+		assert_eq!(supervisor.poll(), waiting(1000));
+		clock.advance(1000);
+		assert_eq!(supervisor.poll(), waiting(2000));
+		clock.advance(2000);
+		assert_eq!(supervisor.poll(), SupervisorState::GaveUp);
+		clock.advance(60_000);
+		assert_eq!(supervisor.poll(), SupervisorState::GaveUp);
+		assert_eq!(calls.get(), 3);
+
+		supervisor.reset();
+		assert_eq!(supervisor.state(), SupervisorState::Connecting);
+		assert_eq!(supervisor.failures(), 0);
+		assert_eq!(supervisor.poll(), waiting(1000));
+		assert_eq!(calls.get(), 4);
+	}
+
+	#[test]
+	fn reset_skips_the_wait() {
+		let clock = Manual(Cell::new(0));
+		let (next, calls) = (Cell::new(Next::Fail), Cell::new(0));
+		let mut supervisor =
+			ConnectionSupervisor::new(attempt(&next, &calls), &clock, policy(None), 1);
+		assert_eq!(supervisor.poll(), waiting(1000));
+		next.set(Next::Connect);
+		supervisor.reset();
+		assert_eq!(supervisor.poll(), SupervisorState::Connected);
+		assert_eq!(calls.get(), 2);
+	}
+
+	#[test]
+	fn reconnects_straight_away_once_disconnected() {
+		let clock = Manual(Cell::new(0));
+		let (next, calls) = (Cell::new(Next::Pending), Cell::new(0));
+		let mut supervisor =
+			ConnectionSupervisor::new(attempt(&next, &calls), &clock, policy(None), 1);
+		assert_eq!(supervisor.poll(), SupervisorState::Connecting);
+		assert_eq!(supervisor.disconnected(), None);
+		assert_eq!(supervisor.connection(), None);
+
+		next.set(Next::Fail);
+		assert_eq!(supervisor.poll(), waiting(1000));
+		assert_eq!(supervisor.disconnected(), None);
+		assert_eq!(supervisor.state(), waiting(1000));
+
+		clock.advance(1000);
+		next.set(Next::Connect);
+		assert_eq!(supervisor.poll(), SupervisorState::Connected);
+		assert_eq!(supervisor.connection(), Some(&mut 7));
+		assert_eq!(supervisor.failures(), 0);
+		assert_eq!(supervisor.poll(), SupervisorState::Connected);
+		assert_eq!(calls.get(), 3);
+
+		assert_eq!(supervisor.disconnected(), Some(7));
+		assert_eq!(supervisor.state(), SupervisorState::Connecting);
+		assert_eq!(supervisor.poll(), SupervisorState::Connected);
+		assert_eq!(calls.get(), 4);
+	}
+}
@@ -0,0 +1,366 @@
+use crate::{Clock, DatagramBuf, Mode, RecvOutcome, TcpStack, UdpStack};
+use core::cell::RefCell;
+use no_std_net::SocketAddr;
+
+struct Bucket {
+	/// Bytes that may be sent right now. This goes negative when a datagram
+	/// larger than the bucket is let through, delaying what comes after it.
+	tokens: i64,
+	refilled_at: u64,
+}
+
+/// A wrapper around a [`TcpStack`] and [`UdpStack`] that limits the rate at
+/// which data is sent with a token bucket, so that firmware stays within a
+/// metered data budget without changing application code.
+///
+/// The bucket holds up to `burst` bytes and is refilled at `rate` bytes per
+/// second; it starts full. Writes that find the bucket empty return
+/// `WouldBlock`, whatever the [`Mode`] of the socket, and TCP writes are
+/// shortened to what the bucket holds. A datagram is only sent once the
+/// bucket holds all of it, or is full for datagrams larger than `burst`;
+/// empty datagrams cost nothing. TCP and UDP traffic draw from the same
+/// bucket. Reads are not limited.
+///
+/// [`TcpStack`]: trait.TcpStack.html
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`Mode`]: enum.Mode.html
+pub struct Throttled<S, C> {
+	stack: S,
+	clock: C,
+	rate: u32,
+	burst: u32,
+	bucket: RefCell<Bucket>,
+}
+
+impl<S, C: Clock> Throttled<S, C> {
+	/// Wrap `stack`, letting it send `rate` bytes per second on average and
+	/// up to `burst` bytes at once, as measured by `clock`, or return `None`
+	/// if `burst` is 0, which would never let anything through.
+	pub fn new(stack: S, clock: C, rate: u32, burst: u32) -> Option<Self> {
+		if burst == 0 {
+			return None;
+		}
+		let now = clock.now_ms();
+		Some(Throttled {
+			stack,
+			clock,
+			rate,
+			burst,
+			bucket: RefCell::new(Bucket {
+				tokens: i64::from(burst),
+				refilled_at: now,
+			}),
+		})
+	}
+
+	/// Get a reference to the wrapped stack.
+	pub fn inner(&self) -> &S {
+		&self.stack
+	}
+
+	/// Destroy the wrapper, returning the wrapped stack.
+	pub fn into_inner(self) -> S {
+		self.stack
+	}
+
+	/// Refill the bucket for the time elapsed since it was last refilled,
+	/// returning how many bytes may be sent now.
+	fn refill(&self) -> i64 {
+		let now = self.clock.now_ms();
+		let mut bucket = self.bucket.borrow_mut();
+		let elapsed = now.saturating_sub(bucket.refilled_at);
+		let earned = elapsed * u64::from(self.rate) / 1000;
+		if earned > 0 {
+			let tokens = bucket.tokens + earned as i64;
+			bucket.tokens = core::cmp::min(tokens, i64::from(self.burst));
+			// Only account for the time that earned whole bytes, so that
+			// frequent refills at low rates don't lose the remainder.
+			bucket.refilled_at += earned * 1000 / u64::from(self.rate);
+		} else if self.rate == 0 {
+			bucket.refilled_at = now;
+		}
+		bucket.tokens
+	}
+
+	/// Whether a datagram of `len` bytes may be sent with `tokens` in the
+	/// bucket.
+	fn fits(&self, tokens: i64, len: usize) -> bool {
+		tokens >= core::cmp::min(len as i64, i64::from(self.burst))
+	}
+
+	fn spend(&self, len: usize) {
+		self.bucket.borrow_mut().tokens -= len as i64;
+	}
+}
+
+impl<S: TcpStack, C: Clock> TcpStack for Throttled<S, C> {
+	type TcpSocket = S::TcpSocket;
+	type Error = S::Error;
+
+	fn open(&self, mode: Mode) -> Result<Self::TcpSocket, Self::Error> {
+		self.stack.open(mode)
+	}
+
+	fn connect(
+		&self,
+		socket: Self::TcpSocket,
+		remote: SocketAddr,
+	) -> Result<Self::TcpSocket, Self::Error> {
+		self.stack.connect(socket, remote)
+	}
+
+	fn is_connected(&self, socket: &Self::TcpSocket) -> Result<bool, Self::Error> {
+		self.stack.is_connected(socket)
+	}
+
+	fn write(&self, socket: &mut Self::TcpSocket, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+		if buffer.is_empty() {
+			return self.stack.write(socket, buffer);
+		}
+		let tokens = self.refill();
+		if tokens <= 0 {
+			return Err(nb::Error::WouldBlock);
+		}
+		let allowed = core::cmp::min(buffer.len() as i64, tokens) as usize;
+		let written = self.stack.write(socket, &buffer[..allowed])?;
+		self.spend(written);
+		Ok(written)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		TcpStack::read(&self.stack, socket, buffer)
+	}
+
+	fn receive(
+		&self,
+		socket: &mut Self::TcpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<RecvOutcome, Self::Error> {
+		self.stack.receive(socket, buffer)
+	}
+
+	fn close(&self, socket: Self::TcpSocket) -> Result<(), Self::Error> {
+		TcpStack::close(&self.stack, socket)
+	}
+}
+
+impl<S: UdpStack, C: Clock> UdpStack for Throttled<S, C> {
+	type UdpSocket = S::UdpSocket;
+	type Error = S::Error;
+
+	fn open(&self, remote: SocketAddr, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		self.stack.open(remote, mode)
+	}
+
+	fn write(&self, socket: &mut Self::UdpSocket, buffer: &[u8]) -> nb::Result<(), Self::Error> {
+		if !self.fits(self.refill(), buffer.len()) {
+			return Err(nb::Error::WouldBlock);
+		}
+		self.stack.write(socket, buffer)?;
+		self.spend(buffer.len());
+		Ok(())
+	}
+
+	fn write_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &[&[u8]],
+	) -> nb::Result<usize, Self::Error> {
+		// Only pass on the datagrams the bucket holds enough for.
+		let mut tokens = self.refill();
+		let mut allowed = 0;
+		for datagram in datagrams {
+			if !self.fits(tokens, datagram.len()) {
+				break;
+			}
+			tokens -= datagram.len() as i64;
+			allowed += 1;
+		}
+		if allowed == 0 && !datagrams.is_empty() {
+			return Err(nb::Error::WouldBlock);
+		}
+		let sent = self.stack.write_batch(socket, &datagrams[..allowed])?;
+		self.spend(datagrams[..sent].iter().map(|d| d.len()).sum());
+		Ok(sent)
+	}
+
+	fn read(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<usize, Self::Error> {
+		UdpStack::read(&self.stack, socket, buffer)
+	}
+
+	fn read_batch(
+		&self,
+		socket: &mut Self::UdpSocket,
+		datagrams: &mut [DatagramBuf],
+	) -> nb::Result<usize, Self::Error> {
+		self.stack.read_batch(socket, datagrams)
+	}
+
+	fn close(&self, socket: Self::UdpSocket) -> Result<(), Self::Error> {
+		UdpStack::close(&self.stack, socket)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use core::cell::Cell;
+
+	struct Manual(Cell<u64>);
+
+	impl Clock for Manual {
+		fn now_ms(&self) -> u64 {
+			self.0.get()
+		}
+	}
+
+	/// A stack that accepts everything, counting the bytes written to it.
+	#[derive(Default)]
+	struct Sink {
+		sent: Cell<usize>,
+	}
+
+	impl TcpStack for Sink {
+		type TcpSocket = ();
+		type Error = crate::NetworkErrorKind;
+
+		fn open(&self, _mode: Mode) -> Result<(), Self::Error> {
+			Ok(())
+		}
+
+		fn connect(&self, _socket: (), _remote: SocketAddr) -> Result<(), Self::Error> {
+			Ok(())
+		}
+
+		fn is_connected(&self, _socket: &()) -> Result<bool, Self::Error> {
+			Ok(true)
+		}
+
+		fn write(&self, _socket: &mut (), buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+			self.sent.set(self.sent.get() + buffer.len());
+			Ok(buffer.len())
+		}
+
+		fn read(&self, _socket: &mut (), buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+			Ok(buffer.len())
+		}
+
+		fn close(&self, _socket: ()) -> Result<(), Self::Error> {
+			Ok(())
+		}
+	}
+
+	impl UdpStack for Sink {
+		type UdpSocket = ();
+		type Error = crate::NetworkErrorKind;
+
+		fn open(&self, _remote: SocketAddr, _mode: Mode) -> Result<(), Self::Error> {
+			Ok(())
+		}
+
+		fn write(&self, _socket: &mut (), buffer: &[u8]) -> nb::Result<(), Self::Error> {
+			self.sent.set(self.sent.get() + buffer.len());
+			Ok(())
+		}
+
+		fn read(&self, _socket: &mut (), buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+			Ok(buffer.len())
+		}
+
+		fn close(&self, _socket: ()) -> Result<(), Self::Error> {
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn empty_bursts_are_rejected() {
+		assert!(Throttled::new(Sink::default(), Manual(Cell::new(0)), 1000, 0).is_none());
+	}
+
+	#[test]
+	fn tcp_writes_are_shortened_to_the_tokens_refilled() {
+		let clock = Manual(Cell::new(0));
+		let stack = Throttled::new(Sink::default(), &clock, 1000, 100).unwrap();
+		let data = [0; 150];
+		assert_eq!(TcpStack::write(&stack, &mut (), &data), Ok(100));
+		assert_eq!(
+			TcpStack::write(&stack, &mut (), &data),
+			Err(nb::Error::WouldBlock)
+		);
+		clock.0.set(30);
+		assert_eq!(TcpStack::write(&stack, &mut (), &data), Ok(30));
+		// The bucket holds no more than `burst`.
+		clock.0.set(10_000);
+		assert_eq!(TcpStack::write(&stack, &mut (), &data), Ok(100));
+		assert_eq!(stack.inner().sent.get(), 230);
+
+		let mut buffer = [0; 200];
+		assert_eq!(TcpStack::read(&stack, &mut (), &mut buffer), Ok(200));
+	}
+
+	#[test]
+	fn slow_rates_keep_their_remainder() {
+		let clock = Manual(Cell::new(0));
+		let stack = Throttled::new(Sink::default(), &clock, 3, 10).unwrap();
+		assert_eq!(TcpStack::write(&stack, &mut (), &[0; 10]), Ok(10));
+		// A byte every 333 ms, however often the bucket is refilled.
+		for ms in (0..=1000).step_by(100) {
+			clock.0.set(ms);
+			if let Ok(sent) = TcpStack::write(&stack, &mut (), &[0; 10]) {
+				assert_eq!(sent, 1);
+			}
+		}
+		assert_eq!(stack.inner().sent.get(), 13);
+	}
+
+	#[test]
+	fn datagrams_wait_for_the_whole_of_them() {
+		let clock = Manual(Cell::new(0));
+		let stack = Throttled::new(Sink::default(), &clock, 1000, 100).unwrap();
+		assert_eq!(UdpStack::write(&stack, &mut (), &[0; 60]), Ok(()));
+		assert_eq!(
+			UdpStack::write(&stack, &mut (), &[0; 60]),
+			Err(nb::Error::WouldBlock)
+		);
+		clock.0.set(20);
+		assert_eq!(UdpStack::write(&stack, &mut (), &[0; 60]), Ok(()));
+		// Empty datagrams cost nothing, even with an empty bucket.
+		assert_eq!(UdpStack::write(&stack, &mut (), &[]), Ok(()));
+
+		// A datagram larger than the bucket goes out once it is full, and
+		// the ones after it wait for the overdraft to be paid back.
+		clock.0.set(1000);
+		assert_eq!(UdpStack::write(&stack, &mut (), &[0; 150]), Ok(()));
+		clock.0.set(1040);
+		assert_eq!(
+			UdpStack::write(&stack, &mut (), &[0; 1]),
+			Err(nb::Error::WouldBlock)
+		);
+		clock.0.set(1051);
+		assert_eq!(UdpStack::write(&stack, &mut (), &[0; 1]), Ok(()));
+		assert_eq!(stack.inner().sent.get(), 271);
+	}
+
+	#[test]
+	fn batches_send_what_the_bucket_holds() {
+		let clock = Manual(Cell::new(0));
+		let stack = Throttled::new(Sink::default(), &clock, 1000, 100).unwrap();
+		let datagrams: [&[u8]; 3] = [&[0; 40], &[0; 40], &[0; 40]];
+		assert_eq!(stack.write_batch(&mut (), &datagrams), Ok(2));
+		assert_eq!(
+			stack.write_batch(&mut (), &datagrams),
+			Err(nb::Error::WouldBlock)
+		);
+		clock.0.set(20);
+		assert_eq!(stack.write_batch(&mut (), &datagrams), Ok(1));
+		assert_eq!(stack.inner().sent.get(), 120);
+	}
+}
@@ -0,0 +1,41 @@
+use core::time::Duration;
+use no_std_net::SocketAddr;
+
+/// A point in time, as the time elapsed since the Unix epoch
+/// (1970-01-01 00:00:00 UTC), not counting leap seconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct UnixTimestamp {
+	/// Whole seconds since the epoch
+	pub secs: u64,
+	/// Fraction of the current second, in nanoseconds
+	pub subsec_nanos: u32,
+}
+
+/// This trait is implemented by stacks that can get the current time from a
+/// network time server with SNTP ([`rfc4330`]), which most offload modems can
+/// do natively. Certificate validity checks need a trustworthy wall clock,
+/// so this is usually the first thing done after connecting.
+///
+/// [`rfc4330`]: https://tools.ietf.org/html/rfc4330
+pub trait TimeSync {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Query `server`, usually on port 123, for the current time.
+	///
+	/// Returns `WouldBlock` until the answer arrives; calling it again with
+	/// the same server keeps waiting for the same query.
+	fn sync(&self, server: SocketAddr) -> nb::Result<UnixTimestamp, Self::Error>;
+
+	/// Let the stack query `server` on its own every `interval`,
+	/// keeping its clock in sync, or stop doing so with `None`.
+	fn set_periodic_sync(
+		&self,
+		server: SocketAddr,
+		interval: Option<Duration>,
+	) -> Result<(), Self::Error>;
+
+	/// The current time according to the stack's clock, or `None` if it has
+	/// never been synchronized.
+	fn now(&self) -> Result<Option<UnixTimestamp>, Self::Error>;
+}
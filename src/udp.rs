@@ -0,0 +1,320 @@
+use crate::{Mode, UdpStack};
+use core::time::Duration;
+use no_std_net::{IpAddr, Ipv4Addr, SocketAddr};
+
+/// A caller-provided buffer for one datagram of a batched read, see
+/// [`UdpStack::read_batch`].
+///
+/// [`UdpStack::read_batch`]: trait.UdpStack.html#method.read_batch
+#[derive(Debug)]
+pub struct DatagramBuf<'a> {
+	/// Storage for the datagram payload
+	pub buffer: &'a mut [u8],
+	/// The size of the datagram that has been placed in `buffer`
+	pub len: usize,
+}
+
+impl<'a> DatagramBuf<'a> {
+	/// Wrap `buffer` so it can be filled by a batched read
+	pub fn new(buffer: &'a mut [u8]) -> Self {
+		DatagramBuf { buffer, len: 0 }
+	}
+
+	/// The datagram that has been received into this buffer
+	pub fn data(&self) -> &[u8] {
+		&self.buffer[..self.len]
+	}
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that allow
+/// per-socket options to be configured on UDP sockets.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpOptions: UdpStack {
+	/// Allow or forbid sending datagrams to broadcast addresses, such as
+	/// `255.255.255.255`, from this socket (`SO_BROADCAST`).
+	fn set_broadcast(&self, socket: &mut Self::UdpSocket, enable: bool) -> Result<(), Self::Error>;
+
+	/// Set the time-to-live of unicast IPv4 datagrams sent from this socket
+	/// (`IP_TTL`).
+	fn set_ttl(&self, socket: &mut Self::UdpSocket, ttl: u8) -> Result<(), Self::Error>;
+
+	/// Set the hop limit of unicast IPv6 datagrams sent from this socket
+	/// (`IPV6_UNICAST_HOPS`).
+	fn set_hop_limit(&self, socket: &mut Self::UdpSocket, hops: u8) -> Result<(), Self::Error>;
+
+	/// The largest payload, in bytes, that can be sent in a single datagram
+	/// on this socket. Many modems cap this well below the 65507 bytes UDP
+	/// allows, commonly at 1024 or 1472 bytes.
+	fn max_datagram_size(&self, socket: &Self::UdpSocket) -> Result<usize, Self::Error>;
+
+	/// Set how long a read on this socket may wait for a
+	/// datagram before failing with an error (`SO_RCVTIMEO`). For
+	/// non-blocking sockets the time is counted from the first `read` that
+	/// returned `WouldBlock`. `None` removes the timeout.
+	///
+	/// This lets request/response exchanges such as DNS or SNTP fail fast
+	/// without an external timer around every `WouldBlock` loop.
+	fn set_read_timeout(
+		&self,
+		socket: &mut Self::UdpSocket,
+		timeout: Option<Duration>,
+	) -> Result<(), Self::Error>;
+}
+
+/// The Explicit Congestion Notification codepoint of a datagram ([`rfc3168`])
+///
+/// [`rfc3168`]: https://tools.ietf.org/html/rfc3168
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ecn {
+	/// Not ECN-capable transport
+	NotEct,
+	/// ECN-capable transport, codepoint `ECT(1)`
+	Ect1,
+	/// ECN-capable transport, codepoint `ECT(0)`
+	Ect0,
+	/// Congestion experienced
+	Ce,
+}
+
+/// The contents of the IPv4 type of service / IPv6 traffic class field of a
+/// datagram.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrafficClass {
+	/// The 6 bit differentiated services codepoint
+	pub dscp: u8,
+	/// The 2 bit explicit congestion notification codepoint
+	pub ecn: Ecn,
+}
+
+/// Ancillary information about a received datagram, the equivalent of
+/// `IP_PKTINFO` / `IPV6_PKTINFO`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PacketInfo {
+	/// The address the datagram was sent to. For multicast datagrams this is
+	/// the group address rather than an address of the local host.
+	pub destination: IpAddr,
+	/// The index of the interface the datagram was received on, if known
+	pub interface: Option<u32>,
+	/// The traffic class the datagram was received with, if known
+	pub traffic_class: Option<TrafficClass>,
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that give
+/// access to the IP level details of each datagram: how and where it was
+/// received, and which traffic class it is sent with. Multi-homed hosts and
+/// multicast responders need this to reply from the correct source address,
+/// and ECN-aware streams to react to congestion.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpPacketInfo: UdpStack {
+	/// Read a datagram the remote host has sent to us, like
+	/// [`UdpStack::read`], additionally returning the [`PacketInfo`] of the
+	/// datagram.
+	///
+	/// [`UdpStack::read`]: trait.UdpStack.html#tymethod.read
+	/// [`PacketInfo`]: struct.PacketInfo.html
+	fn read_with_info(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<(usize, PacketInfo), Self::Error>;
+
+	/// Send a datagram to the remote host, like [`UdpStack::write`], with the
+	/// given DSCP and ECN bits set.
+	///
+	/// [`UdpStack::write`]: trait.UdpStack.html#tymethod.write
+	fn write_with_traffic_class(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &[u8],
+		traffic_class: TrafficClass,
+	) -> nb::Result<(), Self::Error>;
+}
+
+/// Options applied when binding a UDP socket to a local address, see
+/// [`UdpServer::bind`].
+///
+/// [`UdpServer::bind`]: trait.UdpServer.html#tymethod.bind
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BindOptions {
+	/// Allow other sockets to bind the same local address (`SO_REUSEADDR`)
+	pub reuse_address: bool,
+	/// Allow other sockets to bind the same local port (`SO_REUSEPORT`). This
+	/// is what lets several services share the mDNS port 5353.
+	pub reuse_port: bool,
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that can bind
+/// UDP sockets to a local port and exchange datagrams with any remote host,
+/// rather than only the one a socket was opened to.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpServer: UdpStack {
+	/// Open a new UDP socket bound to the given local address and port. The
+	/// socket is not connected, so datagrams are exchanged with
+	/// [`write_to`](#tymethod.write_to) and [`read_from`](#tymethod.read_from).
+	///
+	/// Binding fails if another socket already uses `local`, unless both
+	/// sockets permit it through `options`. If the port of `local` is 0, the
+	/// stack picks a free ephemeral port.
+	fn bind(
+		&self,
+		local: SocketAddr,
+		options: BindOptions,
+		mode: Mode,
+	) -> Result<Self::UdpSocket, Self::Error>;
+
+	/// Open a new, unconnected client UDP socket on an ephemeral local port.
+	/// Unlike sockets from [`UdpStack::open`], it can send to any number of
+	/// remote hosts with [`write_to`](#tymethod.write_to), and
+	/// [`read_from`](#tymethod.read_from) reports which host each reply came
+	/// from. This lets a single socket query several NTP servers at once.
+	///
+	/// The default implementation binds to `0.0.0.0:0`. Stacks that can reach
+	/// IPv6 hosts from such a socket should override it.
+	///
+	/// [`UdpStack::open`]: trait.UdpStack.html#tymethod.open
+	fn open_unconnected(&self, mode: Mode) -> Result<Self::UdpSocket, Self::Error> {
+		let local = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)), 0);
+		self.bind(local, BindOptions::default(), mode)
+	}
+
+	/// Send a datagram to the given remote host.
+	fn write_to(
+		&self,
+		socket: &mut Self::UdpSocket,
+		remote: SocketAddr,
+		buffer: &[u8],
+	) -> nb::Result<(), Self::Error>;
+
+	/// Read a datagram any remote host has sent to us. Returns `Ok((n,
+	/// remote))`, which means a datagram of size `n` has been received from
+	/// `remote` and it has been placed in `&buffer[0..n]`, or an error.
+	fn read_from(
+		&self,
+		socket: &mut Self::UdpSocket,
+		buffer: &mut [u8],
+	) -> nb::Result<(usize, SocketAddr), Self::Error>;
+}
+
+/// How much of each outgoing datagram the UDP checksum covers, see
+/// [`UdpChecksum`].
+///
+/// [`UdpChecksum`]: trait.UdpChecksum.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChecksumCoverage {
+	/// The checksum covers the whole datagram, as in plain UDP
+	Full,
+	/// The checksum covers only the first `n` bytes of the datagram,
+	/// including the 8 byte header, as in UDP-Lite ([`rfc3828`])
+	///
+	/// [`rfc3828`]: https://tools.ietf.org/html/rfc3828
+	Partial(u16),
+	/// No checksum is sent. This is only allowed for IPv4.
+	Disabled,
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that can
+/// disable or restrict the UDP checksum, so that lossy links deliver
+/// datagrams with corrupted payloads instead of dropping them. This suits
+/// audio and sensor streams that tolerate bit errors better than loss.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpChecksum: UdpStack {
+	/// Set the checksum coverage of datagrams sent from this socket.
+	fn set_checksum_coverage(
+		&self,
+		socket: &mut Self::UdpSocket,
+		coverage: ChecksumCoverage,
+	) -> Result<(), Self::Error>;
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that can lend
+/// their internal receive buffer to the caller, avoiding a copy of every
+/// datagram into a caller-provided buffer.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpZeroCopy: UdpStack {
+	/// Receive a datagram and pass its payload, along with the address of the
+	/// host that sent it, to `f`. The payload is only borrowed for the
+	/// duration of the call; the datagram is released once `f` returns, and
+	/// `f`'s result is returned.
+	fn read_from_with<F, R>(
+		&self,
+		socket: &mut Self::UdpSocket,
+		f: F,
+	) -> nb::Result<R, Self::Error>
+	where
+		F: FnOnce(&[u8], SocketAddr) -> R;
+}
+
+/// Per-socket datagram counters, see [`UdpStatistics`]. The counters wrap
+/// around on overflow.
+///
+/// [`UdpStatistics`]: trait.UdpStatistics.html
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct UdpStats {
+	/// Number of datagrams sent
+	pub sent: u32,
+	/// Number of datagrams received and handed to the application
+	pub received: u32,
+	/// Number of datagrams that were received but dropped because the receive
+	/// buffer of the socket was full
+	pub dropped: u32,
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that keep
+/// track of how many datagrams each socket has handled, so that receive
+/// overruns can be detected and reported instead of going unnoticed.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+pub trait UdpStatistics: UdpStack {
+	/// Get the counters of this socket, accumulated since it was opened.
+	fn statistics(&self, socket: &Self::UdpSocket) -> Result<UdpStats, Self::Error>;
+}
+
+/// This trait is an extension trait for [`UdpServer`] for stacks that can
+/// drop datagrams from unexpected sources themselves, so that they never have
+/// to be copied up to the application only to be thrown away.
+///
+/// [`UdpServer`]: trait.UdpServer.html
+pub trait UdpSourceFilter: UdpServer {
+	/// Only accept datagrams from the given hosts on this bound socket. The
+	/// stack copies the list, so it does not need to outlive the call. An
+	/// empty list removes the filter, accepting datagrams from any host.
+	///
+	/// Stacks may limit how many addresses they can filter on, and return an
+	/// error if `allowed` is longer than that.
+	fn set_source_filter(
+		&self,
+		socket: &mut Self::UdpSocket,
+		allowed: &[IpAddr],
+	) -> Result<(), Self::Error>;
+}
+
+/// This trait is an extension trait for [`UdpStack`] for stacks that track
+/// the path MTU to destinations ([`rfc1191`], [`rfc8201`]). Block-wise
+/// protocols such as CoAP can size their blocks to avoid fragmentation on
+/// links with small MTUs, such as PPP over cellular, which is often 1358
+/// bytes.
+///
+/// [`UdpStack`]: trait.UdpStack.html
+/// [`rfc1191`]: https://tools.ietf.org/html/rfc1191
+/// [`rfc8201`]: https://tools.ietf.org/html/rfc8201
+pub trait PathMtu: UdpStack {
+	/// The currently known MTU, in bytes and including the IP header, of the
+	/// path to `remote`. Before anything is known about the path, this is
+	/// the MTU of the outgoing interface.
+	fn path_mtu(&self, remote: IpAddr) -> Result<u16, Self::Error>;
+
+	/// Set or clear the don't fragment bit of IPv4 datagrams sent from this
+	/// socket (`IP_MTU_DISCOVER`). Datagrams larger than the path MTU then
+	/// fail to send instead of being fragmented. IPv6 datagrams are never
+	/// fragmented by routers, so this only affects IPv4.
+	fn set_dont_fragment(
+		&self,
+		socket: &mut Self::UdpSocket,
+		enable: bool,
+	) -> Result<(), Self::Error>;
+}
@@ -0,0 +1,96 @@
+use crate::MacAddr;
+use heapless::{consts, String};
+
+/// The security an access point requires of its clients.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WifiSecurity {
+	/// No authentication or encryption
+	Open,
+	/// WEP, which is broken and only listed for completeness
+	Wep,
+	/// WPA with a pre-shared key
+	WpaPersonal,
+	/// WPA2 with a pre-shared key
+	Wpa2Personal,
+	/// WPA3 with SAE
+	Wpa3Personal,
+	/// WPA2 and WPA3 transition mode, accepting both
+	Wpa2Wpa3Personal,
+	/// WPA2 or WPA3 with 802.1X authentication
+	Enterprise,
+}
+
+impl Default for WifiSecurity {
+	fn default() -> Self {
+		WifiSecurity::Open
+	}
+}
+
+/// An access point found by [`WifiControl::scan`].
+///
+/// [`WifiControl::scan`]: trait.WifiControl.html#tymethod.scan
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccessPoint {
+	/// The network name
+	pub ssid: String<consts::U32>,
+	/// The MAC address of the access point
+	pub bssid: MacAddr,
+	/// The channel the access point is on
+	pub channel: u8,
+	/// The received signal strength, in dBm
+	pub rssi_dbm: i16,
+	/// The security the access point requires
+	pub security: WifiSecurity,
+}
+
+/// The credentials to join a network with, see [`WifiControl::join`].
+///
+/// [`WifiControl::join`]: trait.WifiControl.html#tymethod.join
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WifiCredentials<'a> {
+	/// Join an open network
+	Open,
+	/// Join a WPA2 network with the given passphrase
+	Wpa2(&'a str),
+	/// Join a WPA3 network with the given password
+	Wpa3(&'a str),
+}
+
+/// Whether the station is associated with a network, as returned by
+/// [`WifiControl::status`].
+///
+/// [`WifiControl::status`]: trait.WifiControl.html#tymethod.status
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WifiStatus {
+	/// Not associated with any network
+	Disconnected,
+	/// Joining a network, which has not completed yet
+	Connecting,
+	/// Associated with a network
+	Connected,
+}
+
+/// This trait is implemented by Wi-Fi stacks that can be told which network
+/// to join at runtime, so that provisioning flows can be shared between
+/// drivers such as ESP-AT, WINC1500 and CYW43.
+pub trait WifiControl {
+	/// The type returned when we have an error
+	type Error: core::fmt::Debug;
+
+	/// Scan for access points. Returns `Ok(n)`, which means `n` access points
+	/// have been placed in `&out[0..n]`. If more were found than fit in
+	/// `out`, only the ones with the strongest signal are returned.
+	fn scan(&self, out: &mut [AccessPoint]) -> nb::Result<usize, Self::Error>;
+
+	/// Join the network named `ssid`, leaving the current one if any.
+	///
+	/// Returns `WouldBlock` until the association and authentication are
+	/// complete; calling it again with the same arguments keeps waiting.
+	fn join(&self, ssid: &str, credentials: WifiCredentials) -> nb::Result<(), Self::Error>;
+
+	/// Leave the current network.
+	fn disconnect(&self) -> Result<(), Self::Error>;
+
+	/// Report whether the station is associated with a network.
+	fn status(&self) -> Result<WifiStatus, Self::Error>;
+}